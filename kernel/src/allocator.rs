@@ -115,6 +115,18 @@ pub fn kfree(dp: *mut u64) {
     // }
 }
 
+// Returns the number of free blocks within the kernel heap along with their total size in bytes
+pub fn heap_statistics() -> (usize, u64) {
+    let free_list = FREE_MEMORY_BLOCK_LIST.lock();
+    let blocks = free_list.length;
+    let bytes = free_list
+        .into_iter()
+        .map(|memory_block| memory_block.unwrap().payload.size)
+        .sum();
+    FREE_MEMORY_BLOCK_LIST.free();
+    (blocks, bytes)
+}
+
 /*
     For faster memory access, blocks should be aligned by machine word (8 for x64)
 */
@@ -0,0 +1,336 @@
+// src/ata.rs
+
+/*
+    ATA (IDE) drives are controlled through IO ports, with two buses (primary and secondary) each having a master and slave drive
+    PIO mode is used, where the CPU copies each sector through the data port 2 bytes at a time (no DMA or interrupts)
+    Sectors are addressed using LBA28 (up to 128GiB) or LBA48 when the drive supports it
+*/
+
+use crate::allocator::kmalloc;
+use crate::block::{self, BlockDevice, SECTOR_SIZE};
+use crate::ports::{inb, inpw, outb, outpw};
+use crate::{print_serial, CONSOLE};
+use core::mem::size_of;
+
+// IO ports of each bus (command block and control block)
+const PRIMARY_IO_BASE: u16 = 0x1F0;
+const PRIMARY_CONTROL_BASE: u16 = 0x3F6;
+const SECONDARY_IO_BASE: u16 = 0x170;
+const SECONDARY_CONTROL_BASE: u16 = 0x376;
+
+// Offsets of registers from the IO base
+const DATA: u16 = 0;
+const ERROR: u16 = 1;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DRIVE_HEAD: u16 = 6;
+const STATUS: u16 = 7;
+const COMMAND: u16 = 7;
+
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08; // Drive is ready to transfer data
+const STATUS_DF: u8 = 0x20; // Drive fault
+const STATUS_BSY: u8 = 0x80;
+
+const CONTROL_NIEN: u8 = 0x02; // Disables interrupts from the drive
+
+const COMMAND_READ_PIO: u8 = 0x20;
+const COMMAND_READ_PIO_EXT: u8 = 0x24;
+const COMMAND_WRITE_PIO: u8 = 0x30;
+const COMMAND_WRITE_PIO_EXT: u8 = 0x34;
+const COMMAND_CACHE_FLUSH: u8 = 0xE7;
+const COMMAND_CACHE_FLUSH_EXT: u8 = 0xEA;
+const COMMAND_IDENTIFY: u8 = 0xEC;
+
+const LBA28_MAX_SECTOR: u64 = 0x0FFFFFFF;
+
+pub struct AtaDrive {
+    io_base: u16,
+    control_base: u16,
+    slave: bool,
+    lba48: bool,
+    sector_count: u64,
+    model: [u8; 40],
+}
+
+impl AtaDrive {
+    /*
+        Sends IDENTIFY to a drive, which replies with 256 words of information on it
+        Returns None if there is no drive or it isn't an ATA drive (such as ATAPI cd drives)
+    */
+    fn identify(io_base: u16, control_base: u16, slave: bool) -> Option<AtaDrive> {
+        // Floating bus (no drives attached)
+        if inb(io_base + STATUS) == 0xFF {
+            return None;
+        }
+
+        outb(control_base, CONTROL_NIEN);
+        outb(io_base + DRIVE_HEAD, 0xA0 | ((slave as u8) << 4));
+        delay(control_base);
+
+        outb(io_base + SECTOR_COUNT, 0);
+        outb(io_base + LBA_LOW, 0);
+        outb(io_base + LBA_MID, 0);
+        outb(io_base + LBA_HIGH, 0);
+        outb(io_base + COMMAND, COMMAND_IDENTIFY);
+
+        if inb(io_base + STATUS) == 0 {
+            return None;
+        }
+
+        while inb(io_base + STATUS) & STATUS_BSY != 0 {}
+
+        // ATAPI and SATA devices set these to a signature
+        if inb(io_base + LBA_MID) != 0 || inb(io_base + LBA_HIGH) != 0 {
+            return None;
+        }
+
+        loop {
+            let status = inb(io_base + STATUS);
+            if status & STATUS_ERR != 0 {
+                return None;
+            }
+            if status & STATUS_DRQ != 0 {
+                break;
+            }
+        }
+
+        let mut data: [u16; 256] = [0; 256];
+        for word in data.iter_mut() {
+            *word = inpw(io_base + DATA);
+        }
+
+        // Bit 10 of word 83 marks LBA48 support
+        let lba48 = data[83] & (1 << 10) != 0;
+        let sector_count = match lba48 {
+            true => {
+                (data[100] as u64)
+                    | ((data[101] as u64) << 16)
+                    | ((data[102] as u64) << 32)
+                    | ((data[103] as u64) << 48)
+            }
+            false => (data[60] as u64) | ((data[61] as u64) << 16),
+        };
+
+        // Model name is stored with the bytes of each word swapped
+        let mut model: [u8; 40] = [0; 40];
+        for i in 0..20 {
+            model[i * 2] = (data[27 + i] >> 8) as u8;
+            model[i * 2 + 1] = data[27 + i] as u8;
+        }
+
+        Some(AtaDrive {
+            io_base,
+            control_base,
+            slave,
+            lba48,
+            sector_count,
+            model,
+        })
+    }
+
+    // Sets up the drive registers for a transfer and sends the command
+    fn send_command(&self, lba: u64, count: usize, command_28: u8, command_48: u8) {
+        let slave_bit = (self.slave as u8) << 4;
+
+        if self.lba48 && (lba + count as u64 > LBA28_MAX_SECTOR || count > 256) {
+            outb(self.io_base + DRIVE_HEAD, 0x40 | slave_bit);
+            delay(self.control_base);
+
+            // High bytes are written first
+            outb(self.io_base + SECTOR_COUNT, (count >> 8) as u8);
+            outb(self.io_base + LBA_LOW, (lba >> 24) as u8);
+            outb(self.io_base + LBA_MID, (lba >> 32) as u8);
+            outb(self.io_base + LBA_HIGH, (lba >> 40) as u8);
+            outb(self.io_base + SECTOR_COUNT, count as u8);
+            outb(self.io_base + LBA_LOW, lba as u8);
+            outb(self.io_base + LBA_MID, (lba >> 8) as u8);
+            outb(self.io_base + LBA_HIGH, (lba >> 16) as u8);
+            outb(self.io_base + COMMAND, command_48);
+        } else {
+            // Top 4 bits of the LBA are within the drive/head register
+            outb(
+                self.io_base + DRIVE_HEAD,
+                0xE0 | slave_bit | ((lba >> 24) & 0x0F) as u8,
+            );
+            delay(self.control_base);
+
+            outb(self.io_base + SECTOR_COUNT, count as u8); // 0 means 256
+            outb(self.io_base + LBA_LOW, lba as u8);
+            outb(self.io_base + LBA_MID, (lba >> 8) as u8);
+            outb(self.io_base + LBA_HIGH, (lba >> 16) as u8);
+            outb(self.io_base + COMMAND, command_28);
+        }
+    }
+
+    // Waits until the drive is ready to transfer the next sector
+    fn poll(&self) -> Result<(), &'static str> {
+        delay(self.control_base);
+
+        loop {
+            let status = inb(self.io_base + STATUS);
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                print_serial!("ATA ERROR 0x{:x}\n", inb(self.io_base + ERROR));
+                return Err("ATA drive error");
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // Largest number of sectors which can be transferred by a single command
+    fn max_sectors_per_command(&self) -> usize {
+        match self.lba48 {
+            true => 65536,
+            false => 256,
+        }
+    }
+
+    fn check_range(&self, lba: u64, count: usize) -> Result<(), &'static str> {
+        if lba + count as u64 > self.sector_count {
+            return Err("Sector is past the end of the drive");
+        }
+        if !self.lba48 && lba + count as u64 > LBA28_MAX_SECTOR + 1 {
+            return Err("Sector can't be addressed with LBA28");
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.model)
+            .unwrap_or("ATA drive")
+            .trim()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *mut u8,
+    ) -> Result<(), &'static str> {
+        self.check_range(lba, count)?;
+
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(self.max_sectors_per_command());
+            self.send_command(
+                lba + done as u64,
+                chunk,
+                COMMAND_READ_PIO,
+                COMMAND_READ_PIO_EXT,
+            );
+
+            for sector in 0..chunk {
+                self.poll()?;
+
+                let sector_buffer =
+                    unsafe { buffer.add((done + sector) * SECTOR_SIZE) } as *mut u16;
+                for i in 0..(SECTOR_SIZE / 2) {
+                    unsafe {
+                        sector_buffer
+                            .add(i)
+                            .write_unaligned(inpw(self.io_base + DATA));
+                    }
+                }
+            }
+
+            done += chunk;
+        }
+
+        Ok(())
+    }
+
+    fn write_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *const u8,
+    ) -> Result<(), &'static str> {
+        self.check_range(lba, count)?;
+
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(self.max_sectors_per_command());
+            self.send_command(
+                lba + done as u64,
+                chunk,
+                COMMAND_WRITE_PIO,
+                COMMAND_WRITE_PIO_EXT,
+            );
+
+            for sector in 0..chunk {
+                self.poll()?;
+
+                let sector_buffer =
+                    unsafe { buffer.add((done + sector) * SECTOR_SIZE) } as *const u16;
+                for i in 0..(SECTOR_SIZE / 2) {
+                    unsafe {
+                        outpw(self.io_base + DATA, sector_buffer.add(i).read_unaligned());
+                    }
+                }
+            }
+
+            done += chunk;
+        }
+
+        // Drives may cache writes so they must be flushed to reach the disk
+        outb(
+            self.io_base + COMMAND,
+            match self.lba48 {
+                true => COMMAND_CACHE_FLUSH_EXT,
+                false => COMMAND_CACHE_FLUSH,
+            },
+        );
+        delay(self.control_base);
+        while inb(self.io_base + STATUS) & STATUS_BSY != 0 {}
+
+        Ok(())
+    }
+}
+
+// Reading the alternate status register 4 times gives the 400ns drives need after selecting them
+fn delay(control_base: u16) {
+    for _i in 0..4 {
+        inb(control_base);
+    }
+}
+
+// Looks for drives on both buses and registers each one as a block device
+pub fn init() {
+    let buses = [
+        ("primary", PRIMARY_IO_BASE, PRIMARY_CONTROL_BASE),
+        ("secondary", SECONDARY_IO_BASE, SECONDARY_CONTROL_BASE),
+    ];
+
+    for (bus_name, io_base, control_base) in buses {
+        for slave in [false, true] {
+            if let Some(drive) = AtaDrive::identify(io_base, control_base, slave) {
+                print_serial!(
+                    "ATA {} {}: {} sectors{}\n",
+                    bus_name,
+                    if slave { "slave" } else { "master" },
+                    drive.sector_count,
+                    if drive.lba48 { " (LBA48)" } else { "" }
+                );
+
+                let device = kmalloc(size_of::<AtaDrive>() as u64) as *mut AtaDrive;
+                unsafe {
+                    *device = drive;
+                }
+                block::register(device);
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// src/block.rs
+
+/*
+    Block devices are storage which is read/written in fixed size units (sectors) rather than single bytes
+    Filesystems sit on top of a block device so the same driver works with any kind of storage (ramdisk, ATA, etc)
+    Devices are registered once found and are then referred to by their index
+*/
+
+use crate::allocator::kmalloc;
+use crate::spinlock::Lock;
+use crate::{print_serial, CONSOLE};
+use core::mem::size_of;
+
+pub const SECTOR_SIZE: usize = 512;
+const MAX_BLOCK_DEVICES: usize = 8;
+
+pub trait BlockDevice {
+    fn name(&self) -> &str;
+
+    fn sector_count(&self) -> u64;
+
+    // Reads count sectors starting at the logical block address into the buffer
+    fn read_sectors(&mut self, lba: u64, count: usize, buffer: *mut u8)
+        -> Result<(), &'static str>;
+
+    // Writes count sectors starting at the logical block address from the buffer
+    fn write_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *const u8,
+    ) -> Result<(), &'static str>;
+}
+
+static BLOCK_DEVICES: Lock<[Option<*mut dyn BlockDevice>; MAX_BLOCK_DEVICES]> =
+    Lock::new([None; MAX_BLOCK_DEVICES]);
+
+// Adds a device (which must live forever) and returns its index
+pub fn register(device: *mut dyn BlockDevice) -> Option<usize> {
+    let devices = BLOCK_DEVICES.lock();
+    let index = devices.iter().position(|slot| slot.is_none());
+
+    if let Some(index) = index {
+        devices[index] = Some(device);
+        unsafe {
+            print_serial!(
+                "BLOCK DEVICE {} = {} ({} sectors)\n",
+                index,
+                (*device).name(),
+                (*device).sector_count()
+            );
+        }
+    }
+
+    BLOCK_DEVICES.free();
+    index
+}
+
+pub fn get(index: usize) -> Option<*mut dyn BlockDevice> {
+    let device = BLOCK_DEVICES.lock().get(index).copied().flatten();
+    BLOCK_DEVICES.free();
+    device
+}
+
+/*
+    Memory which holds a disk image (such as a module loaded by GRUB)
+    Changes are only kept until the machine is turned off
+*/
+pub struct RamDisk {
+    start_address: u64,
+    sector_count: u64,
+}
+
+impl RamDisk {
+    // Allocates a ramdisk which lives forever so it can be registered
+    pub fn new(start_address: u64, size: u64) -> *mut RamDisk {
+        let ramdisk = kmalloc(size_of::<RamDisk>() as u64) as *mut RamDisk;
+        unsafe {
+            *ramdisk = RamDisk {
+                start_address,
+                sector_count: size / SECTOR_SIZE as u64,
+            };
+        }
+        ramdisk
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        "ramdisk"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *mut u8,
+    ) -> Result<(), &'static str> {
+        if lba + count as u64 > self.sector_count {
+            return Err("Read past the end of the ramdisk");
+        }
+
+        let address = self.start_address + lba * SECTOR_SIZE as u64;
+        unsafe {
+            core::ptr::copy(address as *const u8, buffer, count * SECTOR_SIZE);
+        }
+        Ok(())
+    }
+
+    fn write_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *const u8,
+    ) -> Result<(), &'static str> {
+        if lba + count as u64 > self.sector_count {
+            return Err("Write past the end of the ramdisk");
+        }
+
+        let address = self.start_address + lba * SECTOR_SIZE as u64;
+        unsafe {
+            core::ptr::copy(buffer, address as *mut u8, count * SECTOR_SIZE);
+        }
+        Ok(())
+    }
+}
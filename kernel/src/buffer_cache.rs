@@ -0,0 +1,337 @@
+// src/buffer_cache.rs
+
+/*
+    Buffer cache keeps recently used sectors of block devices in memory so the disk isn't read every time
+    Sectors are looked up by (device, lba) and the least recently used sector is evicted when the cache is full
+    Writes only change the cached sector and mark it as dirty, with dirty sectors written back to the disk:
+        Periodically by the kernel worker thread (scheduled from the PIT)
+        When a dirty sector is evicted
+        When sync/fsync is called
+    Single sectors (filesystem structures such as the FAT and directories) are cached whilst larger transfers go straight to the device
+*/
+
+use crate::allocator::kmalloc;
+use crate::block::{self, SECTOR_SIZE};
+use crate::multitask;
+use crate::pit::FREQUENCY;
+use crate::spinlock::Lock;
+use crate::{print_serial, CONSOLE};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+const CACHE_SIZE: usize = 256; // Number of sectors
+const WRITE_BACK_INTERVAL: u64 = FREQUENCY * 5; // Ticks between each write back
+
+#[derive(Copy, Clone)]
+struct Buffer {
+    device: usize,
+    lba: u64,
+    data: *mut u8,
+    valid: bool,
+    dirty: bool,
+    last_used: u64, // Value of the cache's counter when the buffer was last accessed
+}
+
+struct BufferCache {
+    buffers: [Buffer; CACHE_SIZE],
+    counter: u64,
+    initialised: bool,
+}
+
+static BUFFER_CACHE: Lock<BufferCache> = Lock::new(BufferCache::new());
+
+// Set whenever a buffer becomes dirty so the PIT only schedules a write back when there is something to write
+static HAS_DIRTY_BUFFERS: AtomicBool = AtomicBool::new(false);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+impl BufferCache {
+    const fn new() -> BufferCache {
+        BufferCache {
+            buffers: [Buffer {
+                device: 0,
+                lba: 0,
+                data: core::ptr::null_mut(),
+                valid: false,
+                dirty: false,
+                last_used: 0,
+            }; CACHE_SIZE],
+            counter: 0,
+            initialised: false,
+        }
+    }
+
+    // Memory for the sectors is only allocated once the cache is first used
+    fn init(&mut self) {
+        if self.initialised {
+            return;
+        }
+
+        let memory = kmalloc((CACHE_SIZE * SECTOR_SIZE) as u64) as *mut u8;
+        for (i, buffer) in self.buffers.iter_mut().enumerate() {
+            buffer.data = unsafe { memory.add(i * SECTOR_SIZE) };
+        }
+        self.initialised = true;
+    }
+
+    fn find(&mut self, device: usize, lba: u64) -> Option<usize> {
+        let index = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.valid && buffer.device == device && buffer.lba == lba)?;
+
+        self.counter += 1;
+        self.buffers[index].last_used = self.counter;
+        Some(index)
+    }
+
+    /*
+        Finds a buffer for a sector which isn't cached, using an empty buffer or evicting the least recently used one
+        The sector isn't read, so the caller must fill the buffer
+    */
+    fn allocate(&mut self, device: usize, lba: u64) -> Result<usize, &'static str> {
+        let index = match self.buffers.iter().position(|buffer| !buffer.valid) {
+            Some(index) => index,
+            None => {
+                let mut oldest = 0;
+                for (i, buffer) in self.buffers.iter().enumerate() {
+                    if buffer.last_used < self.buffers[oldest].last_used {
+                        oldest = i;
+                    }
+                }
+                oldest
+            }
+        };
+
+        self.write_back(index)?;
+
+        self.counter += 1;
+        let buffer = &mut self.buffers[index];
+        buffer.device = device;
+        buffer.lba = lba;
+        buffer.valid = true;
+        buffer.dirty = false;
+        buffer.last_used = self.counter;
+        Ok(index)
+    }
+
+    // Writes a buffer to its device if it has been changed
+    fn write_back(&mut self, index: usize) -> Result<(), &'static str> {
+        let buffer = &mut self.buffers[index];
+        if !buffer.valid || !buffer.dirty {
+            return Ok(());
+        }
+
+        let device = block::get(buffer.device).ok_or("Block device doesn't exist")?;
+        unsafe {
+            (*device).write_sectors(buffer.lba, 1, buffer.data)?;
+        }
+        buffer.dirty = false;
+        Ok(())
+    }
+
+    // Writes every dirty buffer (of a single device if given)
+    fn flush(&mut self, device: Option<usize>) -> Result<(), &'static str> {
+        let mut result = Ok(());
+        for index in 0..CACHE_SIZE {
+            if device.is_some() && device != Some(self.buffers[index].device) {
+                continue;
+            }
+
+            // Keep writing the rest of the buffers even if one fails
+            if let Err(error) = self.write_back(index) {
+                result = Err(error);
+            }
+        }
+
+        if self.buffers.iter().all(|buffer| !buffer.dirty) {
+            HAS_DIRTY_BUFFERS.store(false, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+/*
+    Reads sectors through the cache
+    Cached sectors are always used over the device as they may have been changed
+*/
+pub fn read(device: usize, lba: u64, count: usize, buffer: *mut u8) -> Result<(), &'static str> {
+    let block_device = block::get(device).ok_or("Block device doesn't exist")?;
+    let cache = BUFFER_CACHE.lock();
+    cache.init();
+
+    let result = if count == 1 {
+        read_sector(cache, block_device, device, lba, buffer)
+    } else {
+        unsafe { (*block_device).read_sectors(lba, count, buffer) }.map(|_| {
+            for i in 0..count {
+                if let Some(index) = cache.find(device, lba + i as u64) {
+                    unsafe {
+                        core::ptr::copy(
+                            cache.buffers[index].data,
+                            buffer.add(i * SECTOR_SIZE),
+                            SECTOR_SIZE,
+                        );
+                    }
+                }
+            }
+        })
+    };
+
+    BUFFER_CACHE.free();
+    result
+}
+
+fn read_sector(
+    cache: &mut BufferCache,
+    block_device: *mut dyn block::BlockDevice,
+    device: usize,
+    lba: u64,
+    buffer: *mut u8,
+) -> Result<(), &'static str> {
+    let index = match cache.find(device, lba) {
+        Some(index) => index,
+        None => {
+            let index = cache.allocate(device, lba)?;
+            if let Err(error) =
+                unsafe { (*block_device).read_sectors(lba, 1, cache.buffers[index].data) }
+            {
+                cache.buffers[index].valid = false;
+                return Err(error);
+            }
+            index
+        }
+    };
+
+    unsafe {
+        core::ptr::copy(cache.buffers[index].data, buffer, SECTOR_SIZE);
+    }
+    Ok(())
+}
+
+/*
+    Writes sectors through the cache
+    Single sectors are only written to the cache (and marked dirty) whilst larger writes go to the device and update any cached copies
+*/
+pub fn write(device: usize, lba: u64, count: usize, buffer: *const u8) -> Result<(), &'static str> {
+    let block_device = block::get(device).ok_or("Block device doesn't exist")?;
+    let cache = BUFFER_CACHE.lock();
+    cache.init();
+
+    let result = if count == 1 {
+        let index = match cache.find(device, lba) {
+            Some(index) => Ok(index),
+            None => cache.allocate(device, lba),
+        };
+
+        index.map(|index| {
+            unsafe {
+                core::ptr::copy(buffer, cache.buffers[index].data, SECTOR_SIZE);
+            }
+            cache.buffers[index].dirty = true;
+            HAS_DIRTY_BUFFERS.store(true, Ordering::SeqCst);
+        })
+    } else {
+        unsafe { (*block_device).write_sectors(lba, count, buffer) }.map(|_| {
+            for i in 0..count {
+                if let Some(index) = cache.find(device, lba + i as u64) {
+                    unsafe {
+                        core::ptr::copy(
+                            buffer.add(i * SECTOR_SIZE),
+                            cache.buffers[index].data,
+                            SECTOR_SIZE,
+                        );
+                    }
+                    cache.buffers[index].dirty = false; // Device now holds the same data
+                }
+            }
+        })
+    };
+
+    BUFFER_CACHE.free();
+    result
+}
+
+/*
+    Reads bytes from any location on a block device (rather than whole sectors)
+    Sectors which are only partly needed are read into a separate buffer
+*/
+pub fn read_bytes(device: usize, offset: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+    let mut sector_buffer: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    let mut position = 0;
+
+    while position < buffer.len() {
+        let lba = (offset + position as u64) / SECTOR_SIZE as u64;
+        let sector_offset = ((offset + position as u64) % SECTOR_SIZE as u64) as usize;
+        let remaining = buffer.len() - position;
+
+        // Whole sectors are read straight into the buffer
+        if sector_offset == 0 && remaining >= SECTOR_SIZE {
+            let count = remaining / SECTOR_SIZE;
+            read(device, lba, count, buffer[position..].as_mut_ptr())?;
+            position += count * SECTOR_SIZE;
+            continue;
+        }
+
+        let count = (SECTOR_SIZE - sector_offset).min(remaining);
+        read(device, lba, 1, sector_buffer.as_mut_ptr())?;
+        buffer[position..position + count]
+            .copy_from_slice(&sector_buffer[sector_offset..sector_offset + count]);
+        position += count;
+    }
+
+    Ok(())
+}
+
+/*
+    Writes bytes to any location on a block device
+    Sectors which are only partly changed are read first so the rest of the sector is kept
+*/
+pub fn write_bytes(device: usize, offset: u64, buffer: &[u8]) -> Result<(), &'static str> {
+    let mut sector_buffer: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    let mut position = 0;
+
+    while position < buffer.len() {
+        let lba = (offset + position as u64) / SECTOR_SIZE as u64;
+        let sector_offset = ((offset + position as u64) % SECTOR_SIZE as u64) as usize;
+        let remaining = buffer.len() - position;
+
+        if sector_offset == 0 && remaining >= SECTOR_SIZE {
+            let count = remaining / SECTOR_SIZE;
+            write(device, lba, count, buffer[position..].as_ptr())?;
+            position += count * SECTOR_SIZE;
+            continue;
+        }
+
+        let count = (SECTOR_SIZE - sector_offset).min(remaining);
+        read(device, lba, 1, sector_buffer.as_mut_ptr())?;
+        sector_buffer[sector_offset..sector_offset + count]
+            .copy_from_slice(&buffer[position..position + count]);
+        write(device, lba, 1, sector_buffer.as_ptr())?;
+        position += count;
+    }
+
+    Ok(())
+}
+
+// Writes every dirty sector of a device back (or every device if None)
+pub fn sync(device: Option<usize>) -> Result<(), &'static str> {
+    let result = BUFFER_CACHE.lock().flush(device);
+    BUFFER_CACHE.free();
+    result
+}
+
+/*
+    Called on every PIT tick (within the interrupt handler)
+    Disk IO is too slow for an interrupt so the write back is queued for the kernel worker
+*/
+pub fn tick() {
+    let ticks = TICKS.fetch_add(1, Ordering::SeqCst) + 1;
+    if ticks.is_multiple_of(WRITE_BACK_INTERVAL) && HAS_DIRTY_BUFFERS.load(Ordering::SeqCst) {
+        multitask::schedule_work(write_back_work);
+    }
+}
+
+fn write_back_work() {
+    if let Err(error) = sync(None) {
+        print_serial!("BUFFER CACHE WRITE BACK FAILED: {}\n", error);
+    }
+}
@@ -0,0 +1,278 @@
+// src/char_device.rs
+
+/*
+    Character devices are read/written as a stream of bytes rather than in sectors (unlike block devices)
+    Each device has a driver which implements the CharDevice trait, which devfs exposes as a file under /dev
+    Devices are registered at boot and are then referred to by their index (the device number)
+    Reads never wait for input (apart from ttys), so 0 is returned when there is nothing to read yet
+*/
+
+use crate::framebuffer::{self, FRONTBUFFER_ADDRESS, PITCH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::mouse::{MouseState, MOUSE};
+use crate::spinlock::Lock;
+use crate::tty;
+use crate::vfs::INAPPROPRIATE_IOCTL;
+use crate::{print_serial, CONSOLE};
+use core::mem::size_of;
+
+const MAX_CHAR_DEVICES: usize = 32;
+
+// Fills a FramebufferInfo (given as the argument) for /dev/fb0
+pub const FBIOGET_INFO: u64 = 0x4600;
+
+pub trait CharDevice {
+    fn name(&self) -> &'static str;
+
+    // Called when the device is opened, which it can refuse (such as when it is already in use)
+    fn open(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    // Called once a process has no file descriptors left which refer to the device
+    fn close(&mut self) {}
+
+    // Offset is only used by devices which are backed by memory (such as the framebuffer)
+    fn read(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str>;
+
+    fn write(&mut self, offset: u64, buffer: &[u8]) -> Result<usize, &'static str>;
+
+    // Device specific requests which don't fit into reads/writes (argument is usually a pointer)
+    fn ioctl(&mut self, _request: u64, _argument: u64) -> Result<i64, &'static str> {
+        Err(INAPPROPRIATE_IOCTL)
+    }
+
+    // Reported as the size of the file (0 for streams)
+    fn size(&self) -> u64 {
+        0
+    }
+}
+
+static CHAR_DEVICES: Lock<[Option<*mut dyn CharDevice>; MAX_CHAR_DEVICES]> =
+    Lock::new([None; MAX_CHAR_DEVICES]);
+
+// Adds a device (which must live forever) and returns its index
+pub fn register(device: *mut dyn CharDevice) -> Option<usize> {
+    let devices = CHAR_DEVICES.lock();
+    let index = devices.iter().position(|slot| slot.is_none());
+
+    if let Some(index) = index {
+        devices[index] = Some(device);
+        unsafe {
+            print_serial!("CHARACTER DEVICE {} = {}\n", index, (*device).name());
+        }
+    }
+
+    CHAR_DEVICES.free();
+    index
+}
+
+pub fn get(index: usize) -> Option<*mut dyn CharDevice> {
+    let device = CHAR_DEVICES.lock().get(index).copied().flatten();
+    CHAR_DEVICES.free();
+    device
+}
+
+// Finds the index of a device by its name
+pub fn find(name: &str) -> Option<usize> {
+    let devices = CHAR_DEVICES.lock();
+    let index = devices
+        .iter()
+        .position(|device| device.is_some_and(|device| unsafe { (*device).name() } == name));
+    CHAR_DEVICES.free();
+    index
+}
+
+// Registers the devices which are always present (and the framebuffer when there is one)
+pub fn init() {
+    register(core::ptr::addr_of_mut!(NULL_DEVICE));
+    register(core::ptr::addr_of_mut!(ZERO_DEVICE));
+    register(core::ptr::addr_of_mut!(TTY_DEVICE));
+    register(core::ptr::addr_of_mut!(MOUSE_DEVICE));
+    if framebuffer::is_available() {
+        register(core::ptr::addr_of_mut!(FRAMEBUFFER_DEVICE));
+    }
+}
+
+// Discards everything written and is always at the end of the file
+pub struct NullDevice;
+
+static mut NULL_DEVICE: NullDevice = NullDevice;
+
+impl CharDevice for NullDevice {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn read(&mut self, _offset: u64, _buffer: &mut [u8]) -> Result<usize, &'static str> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        Ok(buffer.len())
+    }
+}
+
+// Discards everything written and reads as an endless stream of zeroes
+pub struct ZeroDevice;
+
+static mut ZERO_DEVICE: ZeroDevice = ZeroDevice;
+
+impl CharDevice for ZeroDevice {
+    fn name(&self) -> &'static str {
+        "zero"
+    }
+
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        Ok(buffer.len())
+    }
+}
+
+// Console tty which reads from the keyboard and writes to the serial console (the same as stdin and stdout)
+pub struct TtyDevice;
+
+static mut TTY_DEVICE: TtyDevice = TtyDevice;
+
+impl CharDevice for TtyDevice {
+    fn name(&self) -> &'static str {
+        "tty"
+    }
+
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        tty::read(tty::CONSOLE_TTY, buffer)
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        tty::write(tty::CONSOLE_TTY, buffer)
+    }
+
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<i64, &'static str> {
+        tty::ioctl(tty::CONSOLE_TTY, request, argument)
+    }
+}
+
+/*
+    Position of the mouse and the state of its buttons, as read from /dev/mouse
+    Matches struct MouseEvent within userland
+*/
+#[repr(C)]
+pub struct MouseEvent {
+    pub x: u32,
+    pub y: u32,
+    pub buttons: u32, // Bit 0 is the left button
+}
+
+pub struct MouseDevice;
+
+static mut MOUSE_DEVICE: MouseDevice = MouseDevice;
+
+impl CharDevice for MouseDevice {
+    fn name(&self) -> &'static str {
+        "mouse"
+    }
+
+    // Each read gives the current state of the mouse
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        if buffer.len() < size_of::<MouseEvent>() {
+            return Err("Buffer is too small");
+        }
+
+        let mouse = MOUSE.lock();
+        let event = MouseEvent {
+            x: mouse.mouse_x as u32,
+            y: mouse.mouse_y as u32,
+            buttons: (mouse.mouse_state == MouseState::Down) as u32,
+        };
+        MOUSE.free();
+
+        unsafe {
+            core::ptr::copy(
+                &event as *const MouseEvent as *const u8,
+                buffer.as_mut_ptr(),
+                size_of::<MouseEvent>(),
+            );
+        }
+        Ok(size_of::<MouseEvent>())
+    }
+
+    fn write(&mut self, _offset: u64, _buffer: &[u8]) -> Result<usize, &'static str> {
+        Err("Mouse can't be written to")
+    }
+}
+
+// Matches struct FramebufferInfo within userland
+#[repr(C)]
+pub struct FramebufferInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u32,
+    pub pitch: u32,
+}
+
+// Front buffer (what is on the screen), where the offset is the byte within video memory
+pub struct FramebufferDevice;
+
+static mut FRAMEBUFFER_DEVICE: FramebufferDevice = FramebufferDevice;
+
+impl CharDevice for FramebufferDevice {
+    fn name(&self) -> &'static str {
+        "fb0"
+    }
+
+    fn read(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        if offset >= self.size() {
+            return Ok(0);
+        }
+
+        let count = buffer.len().min((self.size() - offset) as usize);
+        unsafe {
+            core::ptr::copy(
+                (FRONTBUFFER_ADDRESS + offset) as *const u8,
+                buffer.as_mut_ptr(),
+                count,
+            );
+        }
+        Ok(count)
+    }
+
+    fn write(&mut self, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        if offset >= self.size() {
+            return Err("No space left on device");
+        }
+
+        let count = buffer.len().min((self.size() - offset) as usize);
+        unsafe {
+            core::ptr::copy(
+                buffer.as_ptr(),
+                (FRONTBUFFER_ADDRESS + offset) as *mut u8,
+                count,
+            );
+        }
+        Ok(count)
+    }
+
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<i64, &'static str> {
+        match request {
+            FBIOGET_INFO => {
+                unsafe {
+                    *(argument as *mut FramebufferInfo) = FramebufferInfo {
+                        width: SCREEN_WIDTH as u32,
+                        height: SCREEN_HEIGHT as u32,
+                        bits_per_pixel: framebuffer::BITS_PER_PIXEL as u32,
+                        pitch: PITCH as u32,
+                    };
+                }
+                Ok(0)
+            }
+            _ => Err(INAPPROPRIATE_IOCTL),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        PITCH * SCREEN_HEIGHT
+    }
+}
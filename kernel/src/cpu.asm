@@ -26,6 +26,19 @@ inpw_raw:
   in ax, dx
   ret
 
+global outl_raw
+outl_raw:
+  mov dx, di ; Address (16 Bit)
+  mov eax, esi ; Value (32 Bit)
+  out dx, eax
+  ret
+
+global inl_raw
+inl_raw:
+  mov dx, di ; Address (16 Bit)
+  in eax, dx
+  ret
+
 ; Load IDT
 global idt_flush    
 idt_flush:
@@ -0,0 +1,132 @@
+// src/devfs.rs
+
+/*
+    Device filesystem exposes every registered character device as a file within a single directory (mounted at /dev)
+    Inode 0 is the directory whilst the inode of each device is its device number plus 1
+    Opens, closes, reads, writes and ioctls on the files are passed straight to the device's driver
+*/
+
+use crate::char_device::{self, CharDevice};
+use crate::vfs::{
+    DirectoryEntry, FileSystem, FileType, Inode, Stat, FILE_NOT_FOUND, IS_A_DIRECTORY,
+    NOT_A_DIRECTORY, S_IFCHR, S_IFDIR,
+};
+
+const ROOT_INODE: Inode = 0;
+
+pub struct Devfs;
+
+static mut DEVFS: Devfs = Devfs;
+
+fn get_device(inode: Inode) -> Result<*mut dyn CharDevice, &'static str> {
+    if inode == ROOT_INODE {
+        return Err(IS_A_DIRECTORY);
+    }
+    char_device::get(inode as usize - 1).ok_or("Device doesn't exist")
+}
+
+impl FileSystem for Devfs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str> {
+        if directory != ROOT_INODE {
+            return Err(NOT_A_DIRECTORY);
+        }
+
+        match char_device::find(name) {
+            Some(index) => Ok(index as Inode + 1),
+            None => Err(FILE_NOT_FOUND),
+        }
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str> {
+        if inode == ROOT_INODE {
+            return Ok(Stat {
+                st_mode: S_IFDIR | 0o755,
+                st_nlink: 2,
+                ..Stat::default()
+            });
+        }
+
+        let device = get_device(inode)?;
+        Ok(Stat {
+            st_ino: inode as u16,
+            st_mode: S_IFCHR | 0o666,
+            st_nlink: 1,
+            st_rdev: (inode - 1) as i16,
+            st_size: unsafe { (*device).size() } as i64,
+            ..Stat::default()
+        })
+    }
+
+    fn read(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let device = get_device(inode)?;
+        unsafe { (*device).read(offset, buffer) }
+    }
+
+    fn write(&mut self, inode: Inode, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let device = get_device(inode)?;
+        unsafe { (*device).write(offset, buffer) }
+    }
+
+    // Index is the device number
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+        if directory != ROOT_INODE {
+            return Err(NOT_A_DIRECTORY);
+        }
+
+        // Devices are never removed so they fill the first slots of the table
+        match char_device::get(index as usize) {
+            Some(device) => {
+                let entry = unsafe {
+                    DirectoryEntry::new(
+                        index + 1,
+                        FileType::CharDevice,
+                        (*device).size(),
+                        (*device).name().as_bytes(),
+                    )
+                };
+                Ok(Some((entry, index + 1)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn open(&mut self, inode: Inode) -> Result<(), &'static str> {
+        match inode {
+            ROOT_INODE => Ok(()),
+            _ => unsafe { (*get_device(inode)?).open() },
+        }
+    }
+
+    fn close(&mut self, inode: Inode) {
+        if let Ok(device) = get_device(inode) {
+            unsafe { (*device).close() }
+        }
+    }
+
+    fn ioctl(&mut self, inode: Inode, request: u64, argument: u64) -> Result<i64, &'static str> {
+        let device = get_device(inode)?;
+        unsafe { (*device).ioctl(request, argument) }
+    }
+}
+
+// There is only ever one devfs as it always holds every device
+pub fn mount() -> *mut dyn FileSystem {
+    core::ptr::addr_of_mut!(DEVFS)
+}
@@ -121,60 +121,74 @@ struct ElfSymbol {
     st_size: Elf64Xword, // Symbol size
 }
 
-pub fn parse(file_start: u64) {
+// Loads every segment of an ELF file into memory and returns its entry point
+pub fn parse(file_start: u64, size: u64) -> Result<u64, &'static str> {
     let elf_header = unsafe { &*(file_start as *const ElfHeader) };
-    validate_file(elf_header);
+    validate_file(file_start, size, elf_header)?;
     parse_program_headers(file_start, elf_header);
     // parse_section_headers(file_start, elf_header);
+    Ok(elf_header.e_entry)
 }
 
-// Verify file starts with ELF Magic number and is built for the correct system
-fn validate_file(elf_header: &ElfHeader) -> bool {
-    assert!(
-        elf_header.e_ident[ElfIdent::EiMag0 as usize] == ELF_FLAG_MAG0,
-        "ELF Header EI_MAG0 incorrect\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiMag1 as usize] == ('E' as u8),
-        "ELF header EI_MAG1 incorrect\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiMag2 as usize] == ('L' as u8),
-        "ELF header EI_MAG2 incorrect\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiMag3 as usize] == ('F' as u8),
-        "ELF header EI_MAG3 incorrect\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiClass as usize] == ELF_CLASS,
-        "Unsupported ELF File class\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiData as usize] == ELF_DATA,
-        "Unsupported ELF File byte order\n"
-    );
-
-    assert!(
-        elf_header.e_ident[ElfIdent::EiVersion as usize] == ELF_VERSION,
-        "Unsupported ELF version\n"
-    );
-
-    assert!(
-        elf_header.e_machine == ELF_MACHINE,
-        "Unsupported ELF file target\n"
-    );
-
-    let test = elf_header.e_type;
+// Checks for the ELF magic number (without the checks of validate_file) so other files can be told apart
+pub fn is_elf(file_start: u64, size: u64) -> bool {
+    if size < mem::size_of::<ElfHeader>() as u64 {
+        return false;
+    }
+
+    let magic = unsafe { core::slice::from_raw_parts(file_start as *const u8, 4) };
+    magic == [ELF_FLAG_MAG0, b'E', b'L', b'F']
+}
+
+/*
+    Verify file starts with ELF Magic number and is built for the correct system
+    Program headers and the contents of loadable segments must also lie within the file, so nothing is loaded from past its end
+*/
+fn validate_file(file_start: u64, size: u64, elf_header: &ElfHeader) -> Result<(), &'static str> {
+    if !is_elf(file_start, size) {
+        return Err("ELF header magic number incorrect");
+    }
+    if elf_header.e_ident[ElfIdent::EiClass as usize] != ELF_CLASS {
+        return Err("Unsupported ELF file class");
+    }
+    if elf_header.e_ident[ElfIdent::EiData as usize] != ELF_DATA {
+        return Err("Unsupported ELF file byte order");
+    }
+    if elf_header.e_ident[ElfIdent::EiVersion as usize] != ELF_VERSION {
+        return Err("Unsupported ELF version");
+    }
+    if elf_header.e_machine != ELF_MACHINE {
+        return Err("Unsupported ELF file target");
+    }
 
+    // let test = elf_header.e_type;
     // assert!(test == 1, "Unsupported ELF file type {}", test);
 
-    return true;
+    let header_size = mem::size_of::<ElfProgramHeader>() as u64;
+    let headers_end = (elf_header.e_phnum as u64)
+        .checked_mul(header_size)
+        .and_then(|length| length.checked_add(elf_header.e_phoff));
+    if !headers_end.is_some_and(|end| end <= size) {
+        return Err("ELF program headers are past the end of the file");
+    }
+
+    for i in 0..elf_header.e_phnum {
+        let address = file_start + elf_header.e_phoff + header_size * (i as u64);
+        let program_header = unsafe { &*(address as *const ElfProgramHeader) };
+        if program_header.p_type != ProgramHeaderType::PtLoad as u32 {
+            continue;
+        }
+
+        let segment_end = program_header.p_offset.checked_add(program_header.p_filesz);
+        if !segment_end.is_some_and(|end| end <= size) {
+            return Err("ELF segment is past the end of the file");
+        }
+        if program_header.p_filesz > program_header.p_memsz {
+            return Err("ELF segment is larger in the file than in memory");
+        }
+    }
+
+    Ok(())
 }
 
 // Elf program headers specify where segments are located
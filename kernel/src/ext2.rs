@@ -0,0 +1,484 @@
+// src/ext2.rs
+
+/*
+    Driver for the second extended filesystem (read only)
+    The volume is split into blocks (1KB to 64KB) which are grouped into block groups
+    Superblock (at byte 1024) describes the volume and is followed by a descriptor for every block group
+    Each block group has a bitmap of used blocks, a bitmap of used inodes and a table of inodes
+    Inodes hold the metadata of a file along with where its data is:
+        12 direct blocks
+        Singly indirect block (a block full of block numbers)
+        Doubly/triply indirect blocks (blocks of block numbers which point to further blocks of block numbers)
+    Directories are files made of variable length entries which give the inode number of each name
+    Symbolic links hold the path they point to, which is kept within the inode itself when short enough
+*/
+
+use crate::allocator::kmalloc;
+use crate::buffer_cache;
+use crate::vfs::{
+    DirectoryEntry, FileSystem, FileType, Inode, Stat, Timespec, FILE_NOT_FOUND, IS_A_DIRECTORY,
+    NOT_A_DIRECTORY, NOT_A_SYMLINK, S_IFCHR, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG,
+};
+use crate::{print_serial, CONSOLE};
+use core::mem::{self, size_of};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT2_MAGIC: u16 = 0xEF53;
+
+// Inode 1 holds bad blocks so the root directory is always inode 2
+const ROOT_INODE: Inode = 2;
+
+// Features which must be understood to read the filesystem (anything else is refused)
+const INCOMPAT_FILETYPE: u32 = 0x0002; // Directory entries hold the type of file
+const INCOMPAT_FLEX_BG: u32 = 0x0200; // Bitmaps and inode tables can be outside of their block group
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+// Revision 0 filesystems always use 128 byte inodes
+const REVISION_0_INODE_SIZE: u64 = 128;
+
+const DIRECT_BLOCKS: u64 = 12;
+const SINGLY_INDIRECT_BLOCK: usize = 12; // Doubly and triply indirect blocks follow
+
+// Symbolic links shorter than this are stored within the block numbers of the inode (fast symlinks)
+const FAST_SYMLINK_SIZE: u64 = 60;
+
+const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct Superblock {
+    inodes_count: u32,
+    blocks_count: u32,
+    reserved_blocks_count: u32,
+    free_blocks_count: u32,
+    free_inodes_count: u32,
+    first_data_block: u32, // Block which holds the superblock (0 unless blocks are 1KB)
+    log_block_size: u32,   // Block size is 1024 << log_block_size
+    log_fragment_size: u32,
+    blocks_per_group: u32,
+    fragments_per_group: u32,
+    inodes_per_group: u32,
+    mount_time: u32,
+    write_time: u32,
+    mount_count: u16,
+    max_mount_count: u16,
+    magic: u16,
+    state: u16,
+    errors: u16,
+    minor_revision: u16,
+    last_check: u32,
+    check_interval: u32,
+    creator_os: u32,
+    revision: u32,
+    default_reserved_uid: u16,
+    default_reserved_gid: u16,
+    // Everything from here is only valid from revision 1
+    first_inode: u32,
+    inode_size: u16,
+    block_group: u16,
+    feature_compat: u32,
+    feature_incompat: u32,
+    feature_ro_compat: u32,
+    uuid: [u8; 16],
+    volume_name: [u8; 16],
+}
+
+// Table of these starts in the block after the superblock
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct BlockGroupDescriptor {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks_count: u16,
+    free_inodes_count: u16,
+    used_directories_count: u16,
+    padding: u16,
+    reserved: [u8; 12],
+}
+
+// Revision 1 inodes can be larger, but only the first 128 bytes are used
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct Ext2Inode {
+    mode: u16, // File type and permissions
+    uid: u16,
+    size: u32,
+    access_time: u32,
+    change_time: u32,
+    modification_time: u32,
+    deletion_time: u32,
+    gid: u16,
+    links_count: u16,
+    sector_count: u32, // Number of 512 byte sectors used (including indirect blocks)
+    flags: u32,
+    os_specific_1: u32,
+    block: [u32; 15],
+    generation: u32,
+    file_acl: u32,
+    size_high: u32, // Top 32 bits of the size of regular files
+    fragment_address: u32,
+    os_specific_2: [u8; 12],
+}
+
+// Directory entries are variable length with the name straight after this header
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct DirectoryEntryHeader {
+    inode: u32,         // 0 marks an unused entry
+    record_length: u16, // Distance to the next entry
+    name_length: u8,
+    file_type: u8, // Only valid with INCOMPAT_FILETYPE
+}
+
+// The header and name of a directory entry, along with the offset of the entry after it
+type RawDirectoryEntry = (DirectoryEntryHeader, [u8; MAX_NAME_LENGTH], u64);
+
+pub struct Ext2 {
+    device: usize, // Index of the block device which holds the filesystem
+    block_size: u64,
+    inodes_count: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    group_count: u32,
+    group_descriptor_offset: u64,
+}
+
+impl Ext2Inode {
+    fn file_type(&self) -> FileType {
+        match self.mode as u32 & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFCHR => FileType::CharDevice,
+            _ => FileType::File,
+        }
+    }
+
+    // Only regular files use the top 32 bits of the size (it holds the directory ACL otherwise)
+    fn get_size(&self) -> u64 {
+        match self.mode as u32 & S_IFMT {
+            S_IFREG => (self.size as u64) | ((self.size_high as u64) << 32),
+            _ => self.size as u64,
+        }
+    }
+}
+
+impl Ext2 {
+    fn read_inode(&self, inode: Inode) -> Result<Ext2Inode, &'static str> {
+        if inode == 0 || inode > self.inodes_count as u64 {
+            return Err("Inode doesn't exist");
+        }
+
+        // Inodes are numbered from 1
+        let group = (inode - 1) / self.inodes_per_group as u64;
+        let index = (inode - 1) % self.inodes_per_group as u64;
+        if group >= self.group_count as u64 {
+            return Err("Inode doesn't exist");
+        }
+
+        let descriptor: BlockGroupDescriptor = read_struct(
+            self.device,
+            self.group_descriptor_offset + group * size_of::<BlockGroupDescriptor>() as u64,
+        )?;
+        read_struct(
+            self.device,
+            descriptor.inode_table as u64 * self.block_size + index * self.inode_size,
+        )
+    }
+
+    fn read_directory_inode(&self, inode: Inode) -> Result<Ext2Inode, &'static str> {
+        let directory = self.read_inode(inode)?;
+        if directory.file_type() != FileType::Directory {
+            return Err(NOT_A_DIRECTORY);
+        }
+        Ok(directory)
+    }
+
+    /*
+        Finds the block on the volume which holds a block of a file (0 for holes within sparse files)
+        Each level of indirection covers as many times more blocks as there are block numbers within a block
+    */
+    fn get_block(&self, inode: &Ext2Inode, block_index: u64) -> Result<u32, &'static str> {
+        let blocks = inode.block;
+        if block_index < DIRECT_BLOCKS {
+            return Ok(blocks[block_index as usize]);
+        }
+
+        let pointers_per_block = self.block_size / size_of::<u32>() as u64;
+        let mut index = block_index - DIRECT_BLOCKS;
+        let mut span = 1; // Number of blocks covered by each block number at this level
+
+        for level in 0..3 {
+            if index < span * pointers_per_block {
+                // Walk down through each level of indirect blocks
+                let mut block = blocks[SINGLY_INDIRECT_BLOCK + level];
+                for _ in 0..=level {
+                    if block == 0 {
+                        return Ok(0);
+                    }
+
+                    let pointer = index / span;
+                    block = read_struct(
+                        self.device,
+                        block as u64 * self.block_size + pointer * size_of::<u32>() as u64,
+                    )?;
+                    index %= span;
+                    span /= pointers_per_block;
+                }
+                return Ok(block);
+            }
+
+            index -= span * pointers_per_block;
+            span *= pointers_per_block;
+        }
+
+        Err("Block is past the largest possible file")
+    }
+
+    // Reads the data of a file (stopping at the end of the file) and returns the number of bytes read
+    fn read_data(
+        &self,
+        inode: &Ext2Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let size = inode.get_size();
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let length = buffer.len().min((size - offset) as usize);
+        let mut position = 0;
+
+        while position < length {
+            let file_offset = offset + position as u64;
+            let block_offset = file_offset % self.block_size;
+            let count = ((self.block_size - block_offset) as usize).min(length - position);
+            let destination = &mut buffer[position..position + count];
+
+            match self.get_block(inode, file_offset / self.block_size)? {
+                0 => destination.fill(0), // Holes read as zeroes
+                block => buffer_cache::read_bytes(
+                    self.device,
+                    block as u64 * self.block_size + block_offset,
+                    destination,
+                )?,
+            }
+            position += count;
+        }
+
+        Ok(length)
+    }
+
+    /*
+        Reads the next used entry within a directory, starting at a byte offset
+        Returns the header, the name and the offset of the entry after it
+    */
+    fn read_entry(
+        &self,
+        directory: &Ext2Inode,
+        mut offset: u64,
+    ) -> Result<Option<RawDirectoryEntry>, &'static str> {
+        loop {
+            let mut header_buffer: [u8; size_of::<DirectoryEntryHeader>()] =
+                [0; size_of::<DirectoryEntryHeader>()];
+            if self.read_data(directory, offset, &mut header_buffer)? < header_buffer.len() {
+                return Ok(None);
+            }
+
+            let header =
+                unsafe { (header_buffer.as_ptr() as *const DirectoryEntryHeader).read_unaligned() };
+            if header.record_length == 0 {
+                return Err("Corrupt directory entry");
+            }
+
+            let next_offset = offset + header.record_length as u64;
+            if header.inode == 0 {
+                offset = next_offset;
+                continue;
+            }
+
+            let mut name: [u8; MAX_NAME_LENGTH] = [0; MAX_NAME_LENGTH];
+            self.read_data(
+                directory,
+                offset + size_of::<DirectoryEntryHeader>() as u64,
+                &mut name[0..header.name_length as usize],
+            )?;
+            return Ok(Some((header, name, next_offset)));
+        }
+    }
+}
+
+impl FileSystem for Ext2 {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str> {
+        let directory = self.read_directory_inode(directory)?;
+
+        let mut offset = 0;
+        while let Some((header, entry_name, next_offset)) = self.read_entry(&directory, offset)? {
+            if &entry_name[0..header.name_length as usize] == name.as_bytes() {
+                return Ok(header.inode as Inode);
+            }
+            offset = next_offset;
+        }
+
+        Err(FILE_NOT_FOUND)
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str> {
+        let ext2_inode = self.read_inode(inode)?;
+
+        Ok(Stat {
+            st_ino: inode as u16,
+            st_mode: ext2_inode.mode as u32,
+            st_nlink: ext2_inode.links_count,
+            st_uid: ext2_inode.uid,
+            st_gid: ext2_inode.gid,
+            st_size: ext2_inode.get_size() as i64,
+            st_atim: Timespec {
+                tv_sec: ext2_inode.access_time as i64,
+                tv_nsec: 0,
+            },
+            st_mtim: Timespec {
+                tv_sec: ext2_inode.modification_time as i64,
+                tv_nsec: 0,
+            },
+            st_ctim: Timespec {
+                tv_sec: ext2_inode.change_time as i64,
+                tv_nsec: 0,
+            },
+            st_blksize: self.block_size as i64,
+            st_blocks: ext2_inode.sector_count as i64,
+            ..Stat::default()
+        })
+    }
+
+    fn read(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let ext2_inode = self.read_inode(inode)?;
+        match ext2_inode.file_type() {
+            FileType::Directory => Err(IS_A_DIRECTORY),
+            FileType::Symlink => Err("Is a symbolic link"),
+            _ => self.read_data(&ext2_inode, offset, buffer),
+        }
+    }
+
+    // Index is the byte offset of the entry within the directory
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+        let directory = self.read_directory_inode(directory)?;
+
+        match self.read_entry(&directory, index)? {
+            Some((header, name, next_offset)) => {
+                let ext2_inode = self.read_inode(header.inode as Inode)?;
+                let entry = DirectoryEntry::new(
+                    header.inode as Inode,
+                    ext2_inode.file_type(),
+                    ext2_inode.get_size(),
+                    &name[0..header.name_length as usize],
+                );
+                Ok(Some((entry, next_offset)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn readlink(&mut self, inode: Inode, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let ext2_inode = self.read_inode(inode)?;
+        if ext2_inode.file_type() != FileType::Symlink {
+            return Err(NOT_A_SYMLINK);
+        }
+
+        // Fast symlinks have no blocks (other than an extended attribute block)
+        let size = ext2_inode.get_size();
+        let attribute_sectors = match ext2_inode.file_acl {
+            0 => 0,
+            _ => self.block_size as u32 / 512,
+        };
+        if size < FAST_SYMLINK_SIZE && ext2_inode.sector_count == attribute_sectors {
+            let blocks = ext2_inode.block;
+            let target =
+                unsafe { core::slice::from_raw_parts(blocks.as_ptr() as *const u8, size as usize) };
+            let length = target.len().min(buffer.len());
+            buffer[0..length].copy_from_slice(&target[0..length]);
+            return Ok(length);
+        }
+
+        self.read_data(&ext2_inode, 0, buffer)
+    }
+}
+
+// On disk structures are copied out of the volume rather than referenced
+fn read_struct<T: Copy>(device: usize, offset: u64) -> Result<T, &'static str> {
+    let mut value = mem::MaybeUninit::<T>::zeroed();
+    let buffer = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>())
+    };
+    buffer_cache::read_bytes(device, offset, buffer)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+// Mounts a block device if it holds an ext2 filesystem which can be read
+pub fn mount(device: usize) -> Option<*mut dyn FileSystem> {
+    let superblock: Superblock = read_struct(device, SUPERBLOCK_OFFSET).ok()?;
+    if superblock.magic != EXT2_MAGIC {
+        return None;
+    }
+
+    let (inode_size, feature_incompat) = match superblock.revision {
+        0 => (REVISION_0_INODE_SIZE, 0),
+        _ => (superblock.inode_size as u64, superblock.feature_incompat),
+    };
+
+    if feature_incompat & !SUPPORTED_INCOMPAT != 0 {
+        print_serial!(
+            "EXT2 FILESYSTEM USES UNSUPPORTED FEATURES {:#x}\n",
+            feature_incompat & !SUPPORTED_INCOMPAT
+        );
+        return None;
+    }
+
+    if superblock.log_block_size > 6
+        || superblock.blocks_per_group == 0
+        || superblock.inodes_per_group == 0
+        || inode_size < REVISION_0_INODE_SIZE
+    {
+        return None;
+    }
+
+    let block_size = 1024u64 << superblock.log_block_size;
+    let group_count = superblock
+        .blocks_count
+        .saturating_sub(superblock.first_data_block)
+        .div_ceil(superblock.blocks_per_group);
+
+    let filesystem = Ext2 {
+        device,
+        block_size,
+        inodes_count: superblock.inodes_count,
+        inodes_per_group: superblock.inodes_per_group,
+        inode_size,
+        group_count,
+        group_descriptor_offset: (superblock.first_data_block as u64 + 1) * block_size,
+    };
+
+    let ext2 = kmalloc(size_of::<Ext2>() as u64) as *mut Ext2;
+    unsafe {
+        ext2.write(filesystem);
+    }
+    Some(ext2)
+}
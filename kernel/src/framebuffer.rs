@@ -35,6 +35,9 @@ use multiboot2::FramebufferTag;
 
 pub const SCREEN_WIDTH: u64 = 1024;
 pub const SCREEN_HEIGHT: u64 = 768;
+pub const FRONTBUFFER_ADDRESS: u64 = 0xf90000;
+pub const BITS_PER_PIXEL: u64 = 32;
+pub const PITCH: u64 = SCREEN_WIDTH * BITS_PER_PIXEL / 8; // Bytes per row
 pub const BACKGROUND_COLOUR: u32 = 0x0b0554;
 pub const WINDOW_BACKGROUND_COLOUR: u32 = 0xc6d0ff;
 pub const WINDOW_TITLE_COLOUR: u32 = 0x00b5da;
@@ -82,6 +85,8 @@ impl Event {
 
 pub static mut EVENT_MEMORY_LOCATION: *mut Event = 0 as *mut Event;
 
+static mut IS_AVAILABLE: bool = false; // Set once init has mapped the framebuffer
+
 pub static WINDOW_MANAGER: Lock<WindowManager> = Lock::new(WindowManager::new(1024, 768));
 
 pub trait FramebuffferEntity {
@@ -838,9 +843,15 @@ pub fn init(framebuffer_tag: FramebufferTag) {
     unsafe {
         EVENT_MEMORY_LOCATION = PAGE_FRAME_ALLOCATOR.lock().alloc_frame() as *mut Event;
         PAGE_FRAME_ALLOCATOR.free();
+        IS_AVAILABLE = true;
     }
 }
 
+// Without a framebuffer (such as in VGA text mode) nothing may be drawn, so windows can't be used
+pub fn is_available() -> bool {
+    unsafe { IS_AVAILABLE }
+}
+
 // pub fn fill_gradient_rect(&mut self, x: u64, y: u64, width: u64, height: u64) {
 //     let r1 = ((0x00b5da & 0xFF0000) >> 16) as f64;
 //     let r2 = ((0x0b0554 & 0xFF0000) >> 16) as f64;
@@ -1,12 +1,14 @@
 // src/filesystem.rs
 
 /*
-    Driver for the FAT 16 file system (logical way to store, read, write data)
+    Driver for the FAT 12/16/32 file systems (logical way to store, read, write data)
     Single linked list of clusters in a table
+    The type is decided by the number of clusters, which sets the width of FAT entries (12, 16 or 28 bits)
     Storage media is a flat array of clusters
     3 areas include: Boot record, FAT, Directory/data area
     Cluster is unit of storage (physically) set by fs
     Sector is unit of storage on a disk drive (FAT level)
+    Everything is read/written through a block device, so locations are byte offsets from the start of the volume
 */
 
 /*
@@ -17,19 +19,35 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use crate::CONSOLE;
+use crate::block::{self, SECTOR_SIZE};
+use crate::buffer_cache;
+use crate::vfs::{
+    DirectoryEntry, FileSystem, FileType, Inode, Stat, DIRECTORY_NOT_EMPTY, FILE_EXISTS,
+    FILE_NOT_FOUND, INVALID_FILENAME, IS_A_DIRECTORY, NOT_A_DIRECTORY, NOT_PERMITTED, S_IFDIR,
+    S_IFREG,
+};
 use core::{mem, panic};
 use spin::Mutex;
 
-use crate::print_serial;
-
-pub struct Fat16 {
+pub struct Fat {
+    device: Option<usize>, // Index of the block device which holds the filesystem
     bpb: Option<BiosParameterBlock>,
-    start_address: u32,
-    fat_address: u32,
-    first_data_sector_address: u32,
-    root_directory_address: u32,
-    initrd: Option<File>,
+    fat_type: FatType,
+    table_size: u32, // Number of sectors per FAT
+    fat_offset: u64,
+    data_offset: u64,
+    root_directory_offset: u64, // FAT12/16 only
+    root_cluster: u32,          // FAT32 only (the root directory is a cluster chain)
+    fsinfo_offset: Option<u64>, // FAT32 only
+    cluster_count: u32,
+    next_free_cluster: u32, // Where the search for a free cluster starts
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
 }
 // Boot record occupies one sector and is at the start
 #[derive(Debug, Copy, Clone)]
@@ -64,6 +82,40 @@ struct ExtendedBootRecord {
     bootable_partition_signature: u16,
 }
 
+// FAT32 uses a larger extended boot record which holds the FAT size and where the root directory starts
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct Fat32ExtendedBootRecord {
+    table_size_32: u32,
+    flags: u16,
+    version: u16,
+    root_cluster: u32,
+    fsinfo_sector: u16,
+    backup_boot_sector: u16,
+    reserved: [u8; 12],
+    drive_number: u8,
+    nt_flags: u8,
+    signature: u8,
+    serial: u32,
+    volume_label: [u8; 11],
+    system_id_string: u64,
+    bootcode: [u8; 420],
+    bootable_partition_signature: u16,
+}
+
+// FAT32 keeps a hint of the free cluster count and the next free cluster so the FAT needn't be scanned
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+struct FsInfo {
+    lead_signature: u32, // 0x41615252
+    reserved: [u8; 480],
+    signature: u32, // 0x61417272
+    free_count: u32,
+    next_free: u32,
+    reserved_2: [u8; 12],
+    trail_signature: u32, // 0xAA550000
+}
+
 // Stores information on where a file's data/folder are stored on disk along with name, size, creation
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
@@ -72,7 +124,7 @@ struct StandardDirectoryEntry {
     ext: [u8; 3],
     attributes: u8,    // Could be LFN, Directory, Archive
     unused: [u8; 8],   // Reserved for windows NT
-    cluster_high: u16, // Always 0
+    cluster_high: u16, // Only used by FAT32 (always 0 otherwise)
     time: u16,
     date: u16,
     cluster_low: u16,
@@ -104,16 +156,10 @@ pub struct File {
     index: u32,
     file_type: FileType,
     offset: i64,
-    // permissions: u32,
-    // uid: u32,
-    // gid: u32,
-}
-
-#[derive(Copy, PartialEq, Clone, Debug)]
-pub enum FileType {
-    File,
-    Directory,
-    Syslink,
+    // Location of the directory entry which describes this file (0 for the root directory)
+    entry_offset: u64,
+    // Directory which holds the entry
+    directory_cluster: u32,
 }
 
 impl File {
@@ -126,6 +172,8 @@ impl File {
             cluster: cluster_num,
             file_type: file_type,
             offset: 0,
+            entry_offset: 0,
+            directory_cluster: ROOT_DIRECTORY_CLUSTER,
         };
     }
 
@@ -137,384 +185,1072 @@ impl File {
         self.offset
     }
 
+    // Fills in metadata using the directory entry of the file (FAT has no inodes so the location of that entry is used, as within the vfs)
+    pub fn stat(&self) -> Result<Stat, &'static str> {
+        let mut stat = Stat::default();
+        let cluster_size = get_cluster_size() as i64;
+
+        stat.st_ino = self.entry_offset as u16;
+        stat.st_nlink = 1;
+        stat.st_size = self.size as i64;
+        stat.st_blksize = cluster_size;
+        stat.st_blocks = ((stat.st_size + cluster_size - 1) / cluster_size) * (cluster_size / 512);
+
+        // FAT has no owners or permissions, so everything belongs to root and can be executed (programs are run from it)
+        stat.st_mode = match self.file_type {
+            FileType::Directory => S_IFDIR | 0o755,
+            _ => S_IFREG | 0o755,
+        };
+
+        // The root directory has no entry of its own
+        if self.entry_offset == 0 {
+            return Ok(stat);
+        }
+
+        let directory_entry: StandardDirectoryEntry = read_struct(self.entry_offset)?;
+
+        // Read only files can't be written to by anyone
+        if directory_entry.attributes & 0x01 != 0 {
+            stat.st_mode &= !0o222;
+        }
+
+        // Creation time/date and access date are held within the reserved bytes
+        let unused = directory_entry.unused;
+        let creation_time = u16::from_le_bytes([unused[2], unused[3]]);
+        let creation_date = u16::from_le_bytes([unused[4], unused[5]]);
+        let access_date = u16::from_le_bytes([unused[6], unused[7]]);
+
+        stat.st_mtim.tv_sec = fat_timestamp_to_unix(directory_entry.date, directory_entry.time);
+        stat.st_atim.tv_sec = match access_date {
+            0 => stat.st_mtim.tv_sec,
+            _ => fat_timestamp_to_unix(access_date, 0),
+        };
+        stat.st_ctim.tv_sec = match creation_date {
+            0 => stat.st_mtim.tv_sec,
+            _ => fat_timestamp_to_unix(creation_date, creation_time),
+        };
+
+        Ok(stat)
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
     pub fn get_size(&self) -> u32 {
         self.size
     }
 
-    pub fn read(&mut self, buffer: *mut u8, length: usize) -> Result<u64, &str> {
+    pub fn read(&mut self, buffer: *mut u8, length: usize) -> Result<u64, &'static str> {
         if self.file_type != FileType::File {
-            return Err("Tried to read on a directory");
+            return Err(IS_A_DIRECTORY);
         }
 
-        self._modify(buffer, false, length);
-
-        return Ok(0);
+        Ok(self._modify(buffer, false, length)? as u64)
     }
 
-    pub fn write(&mut self, buffer: *mut u8, length: usize) -> Result<u64, &str> {
+    pub fn write(&mut self, buffer: *mut u8, length: usize) -> Result<u64, &'static str> {
         if self.file_type != FileType::File {
-            return Err("Tried to write on a directory");
+            return Err(IS_A_DIRECTORY);
         }
 
-        self._modify(buffer, true, length);
-
-        return Ok(0);
+        Ok(self._modify(buffer, true, length)? as u64)
     }
 
-    pub fn find(&self, filename: &str) -> Result<File, &str> {
-        let cluster_address =
-            convert_sector_to_bytes(get_lba(self.cluster)) + FS.lock().first_data_sector_address;
-        return self._find(filename, cluster_address);
+    pub fn find(&self, filename: &str) -> Result<File, &'static str> {
+        self._find(filename, self.cluster)
     }
 
-    pub fn find_root(&self, filename: &str) -> Result<File, &str> {
-        let root_directory_address = FS.lock().root_directory_address;
-        return self._find(filename, root_directory_address);
-    }
-
-    pub fn readdir(&mut self) -> Result<*const [File; 64], &str> {
-        if self.file_type != FileType::Directory {
-            return Err("Tried to open on a file");
-        }
-        let cluster_address =
-            convert_sector_to_bytes(get_lba(self.cluster)) + FS.lock().first_data_sector_address;
-        return Ok(unsafe { &*(cluster_address as *const [File; 64]) });
+    pub fn find_root(&self, filename: &str) -> Result<File, &'static str> {
+        self._find(filename, ROOT_DIRECTORY_CLUSTER)
     }
 
+    // Creates a file/directory (which is given its first cluster) within a directory
     fn _mk(
         &mut self,
-        mut cluster_address: u32,
+        directory_cluster: u32,
         filename: &str,
         filetype: FileType,
-    ) -> Result<File, &str> {
-        // Search the directory for an empty space
-        for i in 0..64 {
-            let directory_entry = unsafe { &*(cluster_address as *const StandardDirectoryEntry) };
-
-            // Free entry
-            if directory_entry.filename[0] == 0 {
-                unsafe {
-                    let directory_entry_mut =
-                        &mut *(cluster_address as *mut StandardDirectoryEntry);
-                    let file_name_bytes = filename.as_bytes();
-
-                    for j in 0..8 {
-                        if j < file_name_bytes.len() {
-                            directory_entry_mut.filename[j] = file_name_bytes[j];
-                        }
-                    }
+    ) -> Result<File, &'static str> {
+        match self._find(filename, directory_cluster) {
+            Ok(_) => return Err(FILE_EXISTS),
+            Err(FILE_NOT_FOUND) => {}
+            Err(error) => return Err(error),
+        }
 
-                    directory_entry_mut.attributes = match filetype {
-                        FileType::Directory => 0x10,
-                        FileType::File => 0x20,
-                        _ => panic!("Unknown file type"),
-                    };
-
-                    directory_entry_mut.cluster_low = get_next_unallocated_cluster().unwrap();
-
-                    let mut node = File::new(
-                        directory_entry_mut.cluster_low as u32,
-                        directory_entry_mut.file_size,
-                        FileType::File,
-                    );
-                    node.name = directory_entry.filename;
-                    return Ok(node);
-                }
-            };
+        let cluster_num = allocate_cluster()?.ok_or(NO_FREE_CLUSTERS)?;
+
+        let directory_entry = StandardDirectoryEntry {
+            filename: [0; 8],
+            ext: [0; 3],
+            attributes: match filetype {
+                FileType::Directory => 0x10,
+                FileType::File => 0x20,
+                _ => panic!("Unknown file type"),
+            },
+            unused: [0; 8],
+            cluster_high: (cluster_num >> 16) as u16,
+            time: 0,
+            date: 0,
+            cluster_low: cluster_num as u16,
+            file_size: 0,
+        };
 
-            cluster_address += 0x20;
+        let entry_offset = match create_entry(directory_cluster, filename, directory_entry) {
+            Ok(entry_offset) => entry_offset,
+            Err(error) => {
+                free_cluster_chain(cluster_num)?;
+                return Err(error);
+            }
+        };
+
+        if filetype == FileType::Directory {
+            init_directory(cluster_num, directory_cluster)?;
         }
 
-        return Err("Directory is full");
+        let mut node = File::new(cluster_num, 0, filetype);
+        node.name = directory_entry.filename;
+        node.entry_offset = entry_offset;
+        node.directory_cluster = directory_cluster;
+        Ok(node)
     }
 
-    pub fn mkdir_root(&mut self, filename: &str) -> Result<File, &str> {
+    pub fn mkdir_root(&mut self, filename: &str) -> Result<File, &'static str> {
         if self.file_type != FileType::Directory {
             return Err("Tried to mkdir on a file");
         }
 
-        let root_directory_address = FS.lock().root_directory_address;
-
-        self._mk(root_directory_address, filename, FileType::Directory)
+        self._mk(ROOT_DIRECTORY_CLUSTER, filename, FileType::Directory)
     }
 
-    pub fn mkdir(&mut self, filename: &str) -> Result<File, &str> {
+    pub fn mkdir(&mut self, filename: &str) -> Result<File, &'static str> {
         if self.file_type != FileType::Directory {
             return Err("Tried to mkdir on a file");
         }
 
-        let cluster_address =
-            convert_sector_to_bytes(get_lba(self.cluster)) + FS.lock().first_data_sector_address;
-
-        self._mk(cluster_address, filename, FileType::Directory)
+        self._mk(self.cluster, filename, FileType::Directory)
     }
 
-    pub fn mkf(&mut self, filename: &str) -> Result<File, &str> {
+    pub fn mkf(&mut self, filename: &str) -> Result<File, &'static str> {
         if self.file_type != FileType::Directory {
             return Err("Tried to mkf on a file");
         }
 
-        let cluster_address =
-            convert_sector_to_bytes(get_lba(self.cluster)) + FS.lock().first_data_sector_address;
-
-        self._mk(cluster_address, filename, FileType::File)
+        self._mk(self.cluster, filename, FileType::File)
     }
 
-    pub fn mkf_root(&mut self, filename: &str) -> Result<File, &str> {
+    pub fn mkf_root(&mut self, filename: &str) -> Result<File, &'static str> {
         if self.file_type != FileType::Directory {
             return Err("Tried to mkf on a file");
         }
 
-        let root_directory_address = FS.lock().root_directory_address;
-        self._mk(root_directory_address, filename, FileType::File)
+        self._mk(ROOT_DIRECTORY_CLUSTER, filename, FileType::File)
     }
 
-    fn _modify(&mut self, buffer: *mut u8, write: bool, length: usize) {
-        let mut cluster_address =
-            convert_sector_to_bytes(get_lba(self.cluster)) + FS.lock().first_data_sector_address;
+    /*
+        Reads/writes length bytes at the current offset, following the chain of clusters which make up the file
+        Writes which extend the file allocate new clusters and update the size within the directory entry
+        Returns the number of bytes actually read/written (reads stop at the end of the file)
+    */
+    fn _modify(
+        &mut self,
+        buffer: *mut u8,
+        write: bool,
+        length: usize,
+    ) -> Result<usize, &'static str> {
+        let cluster_size = get_cluster_size() as usize;
+        let offset = self.offset as usize;
+        let size = self.size as usize;
+
+        let length = match write {
+            true => length,
+            false => length.min(size.saturating_sub(offset)),
+        };
 
-        let mut file_contents = cluster_address as *mut u8;
+        if length == 0 {
+            return Ok(0);
+        }
 
-        let mut total_count = 0;
-        let mut i = 0;
+        // Empty files may not have a cluster yet
+        if self.cluster == 0 {
+            if !write {
+                return Ok(0);
+            }
 
-        let cluster_clone = self.cluster;
+            match allocate_cluster()? {
+                Some(cluster_num) => {
+                    self.cluster = cluster_num;
+                    self.update_directory_entry()?;
+                }
+                None => return Ok(0),
+            }
+        }
 
         // Get the correct cluster which needs to be addressed using the current offset
-        unsafe {
-            let offset = self.offset;
-            if offset >= 2048 {
-                for j in 0..(offset / 2048) {
-                    match get_next_cluster(self.cluster) {
-                        None => return, // End of file
-                        Some(cluster_num) => {
-                            cluster_address = convert_sector_to_bytes(get_lba(cluster_num))
-                                + FS.lock().first_data_sector_address;
-                            file_contents = cluster_address as *mut u8;
-                            self.cluster = cluster_num;
-                        }
-                    }
-                }
-                file_contents = file_contents.offset((self.offset % 2048) as isize);
-            } else {
-                file_contents = file_contents.offset(self.offset as isize);
+        let mut cluster_num = self.cluster;
+        for _i in 0..(offset / cluster_size) {
+            match get_next_cluster_or_extend(cluster_num, write)? {
+                Some(next_cluster) => cluster_num = next_cluster,
+                None => return Ok(0), // End of file
             }
         }
 
+        let mut cluster_offset = offset % cluster_size;
+        let mut total_count = 0;
+
         while total_count < length {
-            unsafe {
-                if write {
-                    *file_contents.offset(i as isize) = *buffer.offset(total_count as isize);
-                } else {
-                    *buffer.offset(total_count as isize) = *file_contents.offset(i as isize);
+            if cluster_offset == cluster_size {
+                match get_next_cluster_or_extend(cluster_num, write)? {
+                    Some(next_cluster) => cluster_num = next_cluster,
+                    None => break, // End of file (or disk is full)
                 }
+                cluster_offset = 0;
+            }
 
-                i += 1;
-                total_count += 1;
-
-                if i >= 2048 {
-                    panic!("READING OVER A CLUSTER?");
-                    i = 0;
-                    match get_next_cluster(self.cluster) {
-                        None => return, // End of file
-                        Some(cluster_num) => {
-                            cluster_address = convert_sector_to_bytes(get_lba(cluster_num))
-                                + FS.lock().first_data_sector_address;
-                            file_contents = cluster_address as *mut u8;
-                        }
-                    }
-                }
+            // Copy as much as possible within this cluster
+            let count = (cluster_size - cluster_offset).min(length - total_count);
+            let file_contents = get_cluster_offset(cluster_num) + cluster_offset as u64;
+            let buffer_position =
+                unsafe { core::slice::from_raw_parts_mut(buffer.add(total_count), count) };
+
+            match write {
+                true => write_volume(file_contents, buffer_position)?,
+                false => read_volume(file_contents, buffer_position)?,
             }
+
+            cluster_offset += count;
+            total_count += count;
+        }
+
+        self.offset += total_count as i64;
+
+        if write && self.offset as usize > size {
+            self.size = self.offset as u32;
+            self.update_directory_entry()?;
         }
-        self.offset += length as i64;
-        self.cluster = cluster_clone;
+
+        Ok(total_count)
     }
 
-    fn _find(&self, filename: &str, mut cluster_address: u32) -> Result<File, &str> {
+    // Persists the size and first cluster of the file to its directory entry
+    fn update_directory_entry(&self) -> Result<(), &'static str> {
+        if self.entry_offset == 0 {
+            return Ok(());
+        }
+
+        let mut directory_entry: StandardDirectoryEntry = read_struct(self.entry_offset)?;
+        directory_entry.file_size = self.size;
+        directory_entry.set_cluster(self.cluster);
+        write_struct(self.entry_offset, &directory_entry)
+    }
+
+    fn _find(&self, filename: &str, directory_cluster: u32) -> Result<File, &'static str> {
         if self.file_type != FileType::Directory {
-            return Err("Tried to find on a directory");
+            return Err("Tried to find on a file");
         }
 
-        let mut filename_buffer: [u8; 12] = [0; 12];
+        let mut long_filename = LongFileName::new();
 
-        // Loop through each directory entry within the directory cluster
-        for _i in 0..64 {
-            let directory_entry = unsafe { &*(cluster_address as *const StandardDirectoryEntry) };
+        // Loop through each directory entry within the directory (following the cluster chain for larger directories)
+        for entry_offset in DirectoryIterator::new(directory_cluster) {
+            let entry_offset = entry_offset?;
+            let directory_entry: StandardDirectoryEntry = read_struct(entry_offset)?;
 
             match directory_entry.filename[0] {
-                0x00 => return Err("File cannot be found in this directory"), // Marks the end (no more files/directories)
-                0xE5 => panic!("Unused entry"),
+                0x00 => return Err(FILE_NOT_FOUND), // Marks the end (no more files/directories)
+                0xE5 => {
+                    // Deleted entry which is skipped along with any long file name belonging to it
+                    long_filename.reset();
+                    continue;
+                }
                 _ => {}
             }
 
             // Check against attributes of a directory entry
-            match directory_entry.attributes {
-                0x0F => {
-                    // Handle long file name entry
-                    let long_file_entry = unsafe { &*(cluster_address as *const LongFileEntry) };
-                    let mut index = 0;
-
-                    // Convert the lowercase into uppercase
-
-                    for i in 0..5 {
-                        filename_buffer[index] =
-                            (long_file_entry.name_start[i] as u8).wrapping_sub(32);
-                        index += 1;
-                    }
+            if directory_entry.attributes == LFN_ATTRIBUTE {
+                long_filename.add_entry(&read_struct(entry_offset)?);
+                continue;
+            }
 
-                    for i in 0..6 {
-                        filename_buffer[index] =
-                            (long_file_entry.name_middle[i] as u8).wrapping_sub(32);
-                        index += 1
-                    }
+            // Volume labels aren't files
+            if directory_entry.attributes & 0x08 != 0 {
+                long_filename.reset();
+                continue;
+            }
 
-                    for i in 0..1 {
-                        filename_buffer[index] =
-                            (long_file_entry.name_end[i] as u8).wrapping_sub(32);
-                        index += 1;
-                    }
+            // There may be a corresponding long file name for this entry, otherwise only the standard filename is used
+            let mut short_filename_buffer: [u8; 12] = [0; 12];
+            let short_filename = directory_entry.get_short_name(&mut short_filename_buffer);
+
+            let long_filename_matches = long_filename
+                .get(lfn_checksum(&directory_entry.get_raw_short_name()))
+                .is_some_and(|name| long_filename_eq(name, filename));
+            long_filename.reset();
+
+            if short_filename.eq_ignore_ascii_case(filename) || long_filename_matches {
+                let mut node = File::new(
+                    directory_entry.get_cluster(),
+                    directory_entry.file_size,
+                    directory_entry.get_file_type(),
+                );
+                node.name = directory_entry.filename;
+                node.entry_offset = entry_offset;
+                node.directory_cluster = directory_cluster;
+                return Ok(node);
+            }
+        }
+
+        Err(FILE_NOT_FOUND)
+    }
+}
+
+impl StandardDirectoryEntry {
+    fn get_cluster(&self) -> u32 {
+        ((self.cluster_high as u32) << 16) | self.cluster_low as u32
+    }
+
+    fn set_cluster(&mut self, cluster_num: u32) {
+        self.cluster_high = (cluster_num >> 16) as u16;
+        self.cluster_low = cluster_num as u16;
+    }
+
+    fn get_file_type(&self) -> FileType {
+        match self.attributes & 0x10 {
+            0 => FileType::File,
+            _ => FileType::Directory,
+        }
+    }
+
+    // Formats the 8.3 filename as NAME.EXT without padding
+    fn get_short_name<'a>(&self, buffer: &'a mut [u8; 12]) -> &'a str {
+        let mut length = 0;
+
+        for &character in self.filename.iter().take_while(|&&c| c != b' ' && c != 0) {
+            buffer[length] = character;
+            length += 1;
+        }
+
+        let mut extension = self
+            .ext
+            .iter()
+            .take_while(|&&c| c != b' ' && c != 0)
+            .peekable();
+        if extension.peek().is_some() {
+            buffer[length] = b'.';
+            length += 1;
+            for &character in extension {
+                buffer[length] = character;
+                length += 1;
+            }
+        }
+
+        core::str::from_utf8(&buffer[0..length]).unwrap_or("")
+    }
+
+    // Filename and extension as stored on disk (padded with spaces)
+    fn get_raw_short_name(&self) -> [u8; 11] {
+        let mut short_name = [0; 11];
+        short_name[0..8].copy_from_slice(&self.filename);
+        short_name[8..11].copy_from_slice(&self.ext);
+        short_name
+    }
+}
+
+impl LongFileEntry {
+    fn new(order: u8, checksum: u8, characters: &[u16]) -> LongFileEntry {
+        let mut name_start = [0; 5];
+        let mut name_middle = [0; 6];
+        let mut name_end = [0; 2];
+        name_start.copy_from_slice(&characters[0..5]);
+        name_middle.copy_from_slice(&characters[5..11]);
+        name_end.copy_from_slice(&characters[11..13]);
+
+        LongFileEntry {
+            order,
+            name_start,
+            attribute: LFN_ATTRIBUTE,
+            long_entry_type: 0,
+            checksum,
+            name_middle,
+            zero: 0,
+            name_end,
+        }
+    }
+
+    // Copies the 13 UCS-2 characters held by this entry into the buffer
+    fn get_characters(&self, buffer: &mut [u16]) {
+        let (name_start, name_middle, name_end) =
+            (self.name_start, self.name_middle, self.name_end);
+        buffer[0..5].copy_from_slice(&name_start);
+        buffer[5..11].copy_from_slice(&name_middle);
+        buffer[11..13].copy_from_slice(&name_end);
+    }
+}
+
+/*
+    Collects the long file name entries which precede a standard entry
+    Entries are stored last first, so the order must count down to 1 for the name to be valid
+*/
+struct LongFileName {
+    characters: [u16; MAX_LFN_ENTRIES * LFN_CHARACTERS],
+    checksum: u8,
+    next_order: u8, // Order expected from the next entry (0 once every entry has been read)
+    valid: bool,
+}
+
+impl LongFileName {
+    fn new() -> LongFileName {
+        LongFileName {
+            characters: [0; MAX_LFN_ENTRIES * LFN_CHARACTERS],
+            checksum: 0,
+            next_order: 0,
+            valid: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.valid = false;
+        self.next_order = 0;
+    }
+
+    fn add_entry(&mut self, long_file_entry: &LongFileEntry) {
+        let order = long_file_entry.order & LFN_ORDER_MASK;
+
+        if long_file_entry.order & LFN_LAST_ENTRY != 0 {
+            // Start of a new name
+            self.characters = [0; MAX_LFN_ENTRIES * LFN_CHARACTERS];
+            self.checksum = long_file_entry.checksum;
+            self.valid = order != 0 && order as usize <= MAX_LFN_ENTRIES;
+        } else if order != self.next_order || long_file_entry.checksum != self.checksum {
+            self.valid = false;
+        }
+
+        if !self.valid {
+            return;
+        }
+
+        let start = (order as usize - 1) * LFN_CHARACTERS;
+        long_file_entry.get_characters(&mut self.characters[start..start + LFN_CHARACTERS]);
+        self.next_order = order - 1;
+    }
+
+    // Gives the name (without terminator/padding) if every entry was read and belongs to the short name
+    fn get(&self, checksum: u8) -> Option<&[u16]> {
+        if !self.valid || self.next_order != 0 || self.checksum != checksum {
+            return None;
+        }
+
+        let length = self
+            .characters
+            .iter()
+            .position(|&character| character == 0x0000)
+            .unwrap_or(self.characters.len())
+            .min(MAX_FILENAME_LENGTH);
+        Some(&self.characters[0..length])
+    }
+}
+
+// Compares a UCS-2 long file name against a filename (ignoring the case of ASCII characters)
+fn long_filename_eq(long_filename: &[u16], filename: &str) -> bool {
+    let mut characters = core::char::decode_utf16(long_filename.iter().cloned());
+
+    for character in filename.chars() {
+        match characters.next() {
+            Some(Ok(long_character)) if long_character.eq_ignore_ascii_case(&character) => {}
+            _ => return false,
+        }
+    }
+
+    characters.next().is_none()
+}
+
+// Checksum of the 8.3 name which is stored in each of its long file name entries
+fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    let mut checksum: u8 = 0;
+    for &character in short_name.iter() {
+        checksum = ((checksum & 1) << 7)
+            .wrapping_add(checksum >> 1)
+            .wrapping_add(character);
+    }
+    checksum
+}
+
+// Characters which can't be used within 8.3 names
+fn is_valid_short_character(character: u8) -> bool {
+    matches!(
+        character,
+        b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'%'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'-'
+            | b'@'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'{'
+            | b'}'
+            | b'~'
+    )
+}
+
+/*
+    Generates the 8.3 name for a file, along with whether long file name entries are needed
+    Names which can't be converted without losing information are given a numeric tail (NAME~1.EXT) to keep them unique
+*/
+fn generate_short_name(
+    directory_cluster: u32,
+    filename: &str,
+) -> Result<([u8; 11], bool), &'static str> {
+    let mut short_name: [u8; 11] = [b' '; 11];
+
+    // Extension comes after the last period (leading periods don't count)
+    let trimmed_filename = filename.trim_start_matches('.');
+    let (base, extension) = match trimmed_filename.rfind('.') {
+        Some(index) => (&trimmed_filename[..index], &trimmed_filename[index + 1..]),
+        None => (trimmed_filename, ""),
+    };
+
+    let mut lossy = trimmed_filename.len() != filename.len();
+    let mut needs_long_name = lossy;
+
+    let mut convert = |part: &str, destination: &mut [u8]| -> usize {
+        let mut length = 0;
+        for character in part.chars() {
+            if character == ' ' || character == '.' {
+                lossy = true; // Spaces and extra periods are dropped
+                continue;
+            }
+
+            let short_character = match character.is_ascii() {
+                true => character.to_ascii_uppercase() as u8,
+                false => b'_',
+            };
+
+            if short_character as char != character {
+                needs_long_name = true;
+            }
+
+            let short_character = match is_valid_short_character(short_character) {
+                true => short_character,
+                false => {
+                    lossy = true;
+                    b'_'
                 }
-                0x10 => {
-                    // Directory
-                    let dos_filename: &str;
-                    let filename_clone = filename_buffer.clone();
-
-                    if filename_buffer[0] != 0 {
-                        // There is a corresponding long file name for this entry
-                        dos_filename = core::str::from_utf8(&filename_clone).unwrap().trim();
-
-                        // Clean up
-                        for i in 0..12 {
-                            filename_buffer[i] = 0;
-                        }
-                    } else {
-                        // Use the standard filename
-                        dos_filename = core::str::from_utf8(&directory_entry.filename)
-                            .unwrap()
-                            .trim();
-                    }
+            };
 
-                    let mut split_filename = filename.split(".");
+            if length == destination.len() {
+                lossy = true;
+                break;
+            }
+            destination[length] = short_character;
+            length += 1;
+        }
+        length
+    };
 
-                    if split_filename.next().unwrap() == dos_filename {
-                        let mut node = File::new(
-                            directory_entry.cluster_low as u32,
-                            directory_entry.file_size,
-                            FileType::Directory,
-                        );
-                        node.name = directory_entry.filename;
-                        return Ok(node);
-                    }
+    let base_length = convert(base, &mut short_name[0..8]);
+    convert(extension, &mut short_name[8..11]);
+
+    if base_length == 0 {
+        lossy = true;
+        short_name[0] = b'_';
+    }
+
+    // 0xE5 marks a deleted entry so is stored as 0x05
+    if short_name[0] == 0xE5 {
+        short_name[0] = 0x05;
+    }
+
+    if !lossy {
+        return Ok((short_name, needs_long_name));
+    }
+
+    // Add the lowest numeric tail which isn't already used within the directory
+    let base_length = base_length.max(1);
+    for n in 1..1_000_000u32 {
+        let mut tail: [u8; 7] = [0; 7];
+        let mut tail_length = 0;
+        let mut value = n;
+        while value > 0 {
+            tail[tail_length] = b'0' + (value % 10) as u8;
+            tail_length += 1;
+            value /= 10;
+        }
+
+        let mut candidate = short_name;
+        let position = base_length.min(8 - (tail_length + 1));
+        candidate[position] = b'~';
+        for i in 0..tail_length {
+            candidate[position + 1 + i] = tail[tail_length - 1 - i];
+        }
+        candidate[(position + 1 + tail_length)..8].fill(b' ');
+
+        if !short_name_exists(directory_cluster, &candidate)? {
+            return Ok((candidate, true));
+        }
+    }
+
+    Err("Cannot generate a unique short name")
+}
+
+fn short_name_exists(directory_cluster: u32, short_name: &[u8; 11]) -> Result<bool, &'static str> {
+    for entry_offset in DirectoryIterator::new(directory_cluster) {
+        let directory_entry: StandardDirectoryEntry = read_struct(entry_offset?)?;
+
+        match directory_entry.filename[0] {
+            0x00 => return Ok(false),
+            0xE5 => continue,
+            _ => {}
+        }
+
+        if directory_entry.attributes != LFN_ATTRIBUTE
+            && directory_entry.get_raw_short_name() == *short_name
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/*
+    Writes a directory entry (using the name given rather than the one within the entry) into a directory
+    Names which can't be stored as 8.3 are given long file name entries along with a unique short alias
+    Returns the location of the standard entry
+*/
+fn create_entry(
+    directory_cluster: u32,
+    filename: &str,
+    mut directory_entry: StandardDirectoryEntry,
+) -> Result<u64, &'static str> {
+    if filename.is_empty()
+        || filename == "."
+        || filename == ".."
+        || filename.contains('/')
+        || filename.encode_utf16().count() > MAX_FILENAME_LENGTH
+    {
+        return Err(INVALID_FILENAME);
+    }
+
+    let (short_name, needs_long_name) = generate_short_name(directory_cluster, filename)?;
+
+    // Long file name entries are placed directly before the standard entry
+    let long_entry_count = match needs_long_name {
+        true => filename.encode_utf16().count().div_ceil(LFN_CHARACTERS),
+        false => 0,
+    };
+
+    let mut entry_offsets: [u64; MAX_LFN_ENTRIES + 1] = [0; MAX_LFN_ENTRIES + 1];
+    let entry_count = long_entry_count + 1;
+    find_free_entries(directory_cluster, &mut entry_offsets[0..entry_count])?;
+
+    let checksum = lfn_checksum(&short_name);
+    let mut characters: [u16; MAX_LFN_ENTRIES * LFN_CHARACTERS] =
+        [0xFFFF; MAX_LFN_ENTRIES * LFN_CHARACTERS];
+    let mut length = 0;
+    for character in filename.encode_utf16() {
+        characters[length] = character;
+        length += 1;
+    }
+    characters[length] = 0; // Null terminated unless the name fills the last entry exactly
+
+    // Entries are stored in reverse order, with the last one flagged
+    for (i, &offset) in entry_offsets.iter().take(long_entry_count).enumerate() {
+        let order = (long_entry_count - i) as u8;
+        let start = (order as usize - 1) * LFN_CHARACTERS;
+
+        let long_file_entry = LongFileEntry::new(
+            match i {
+                0 => order | LFN_LAST_ENTRY,
+                _ => order,
+            },
+            checksum,
+            &characters[start..start + LFN_CHARACTERS],
+        );
+        write_struct(offset, &long_file_entry)?;
+    }
+
+    directory_entry.filename.copy_from_slice(&short_name[0..8]);
+    directory_entry.ext.copy_from_slice(&short_name[8..11]);
+
+    let entry_offset = entry_offsets[long_entry_count];
+    write_struct(entry_offset, &directory_entry)?;
+    Ok(entry_offset)
+}
+
+// Marks a standard entry and the long file name entries belonging to it as deleted
+fn remove_entry(directory_cluster: u32, entry_offset: u64) -> Result<(), &'static str> {
+    let mut long_entry_offsets: [u64; MAX_LFN_ENTRIES] = [0; MAX_LFN_ENTRIES];
+    let mut long_entry_count = 0;
+
+    for offset in DirectoryIterator::new(directory_cluster) {
+        let offset = offset?;
+        if offset == entry_offset {
+            for &long_entry_offset in long_entry_offsets[0..long_entry_count].iter() {
+                write_struct(long_entry_offset, &DELETED_ENTRY_MARKER)?;
+            }
+            return write_struct(offset, &DELETED_ENTRY_MARKER);
+        }
+
+        let directory_entry: StandardDirectoryEntry = read_struct(offset)?;
+
+        match directory_entry.filename[0] {
+            0x00 => return Ok(()),
+            0xE5 => long_entry_count = 0,
+            _ if directory_entry.attributes == LFN_ATTRIBUTE => {
+                // Long file name entries always start with the entry flagged as the last one
+                let order = directory_entry.filename[0];
+                if order & LFN_LAST_ENTRY != 0 || long_entry_count == MAX_LFN_ENTRIES {
+                    long_entry_count = 0;
                 }
-                0x20 => {
-                    // Archive
-
-                    let dos_filename: &str;
-                    let filename_clone = filename_buffer.clone();
-
-                    if filename_buffer[0] != 0 {
-                        // There is a corresponding long file name for this entry
-                        dos_filename = core::str::from_utf8(&filename_clone).unwrap().trim_end();
-
-                        // Clean up
-                        for i in 0..12 {
-                            filename_buffer[i] = 0;
-                        }
-                    } else {
-                        // Use the standard filename
-                        dos_filename = core::str::from_utf8(&directory_entry.filename)
-                            .unwrap()
-                            .trim();
-                    }
+                long_entry_offsets[long_entry_count] = offset;
+                long_entry_count += 1;
+            }
+            _ => long_entry_count = 0,
+        }
+    }
+    Ok(())
+}
+
+// Checks whether a directory only holds the . and .. entries
+fn is_directory_empty(directory_cluster: u32) -> Result<bool, &'static str> {
+    for entry_offset in DirectoryIterator::new(directory_cluster) {
+        let directory_entry: StandardDirectoryEntry = read_struct(entry_offset?)?;
+
+        match directory_entry.filename[0] {
+            0x00 => return Ok(true),
+            0xE5 => continue,
+            _ => {}
+        }
+
+        if directory_entry.attributes == LFN_ATTRIBUTE || directory_entry.attributes & 0x08 != 0 {
+            continue;
+        }
+
+        if directory_entry.filename != *b".       " && directory_entry.filename != *b"..      " {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/*
+    Reads the entry at an index within a directory, skipping deleted entries and volume labels
+    Indexes count every entry (including long file name entries) so the next index is the one after the standard entry
+*/
+fn read_directory_entry(
+    directory_cluster: u32,
+    start_index: u64,
+) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+    let mut long_filename = LongFileName::new();
+
+    for (index, entry_offset) in DirectoryIterator::new(directory_cluster).enumerate() {
+        let entry_offset = entry_offset?;
+        if (index as u64) < start_index {
+            continue;
+        }
 
-                    let mut split_filename = filename.split(".");
-
-                    if split_filename.next().unwrap().trim() == dos_filename.trim() {
-                        print_serial!("ALL DONE");
-                        let mut node = File::new(
-                            directory_entry.cluster_low as u32,
-                            directory_entry.file_size,
-                            FileType::File,
-                        );
-                        node.name = directory_entry.filename;
-                        return Ok(node);
+        let directory_entry: StandardDirectoryEntry = read_struct(entry_offset)?;
+
+        match directory_entry.filename[0] {
+            0x00 => return Ok(None), // No more entries
+            0xE5 => {
+                long_filename.reset();
+                continue;
+            }
+            _ => {}
+        }
+
+        if directory_entry.attributes == LFN_ATTRIBUTE {
+            long_filename.add_entry(&read_struct(entry_offset)?);
+            continue;
+        }
+
+        if directory_entry.attributes & 0x08 != 0 {
+            long_filename.reset();
+            continue;
+        }
+
+        // Use the long file name when there is a valid one
+        let mut name_buffer: [u8; MAX_FILENAME_LENGTH * 3] = [0; MAX_FILENAME_LENGTH * 3];
+        let mut name_length = 0;
+        match long_filename.get(lfn_checksum(&directory_entry.get_raw_short_name())) {
+            Some(name) => {
+                for character in core::char::decode_utf16(name.iter().cloned()) {
+                    let character = character.unwrap_or(core::char::REPLACEMENT_CHARACTER);
+                    name_length += character.encode_utf8(&mut name_buffer[name_length..]).len();
+                }
+            }
+            None => {
+                let mut short_filename_buffer: [u8; 12] = [0; 12];
+                let short_filename = directory_entry.get_short_name(&mut short_filename_buffer);
+                name_buffer[0..short_filename.len()].copy_from_slice(short_filename.as_bytes());
+                name_length = short_filename.len();
+            }
+        }
+
+        let entry = DirectoryEntry::new(
+            entry_offset,
+            directory_entry.get_file_type(),
+            directory_entry.file_size as u64,
+            &name_buffer[0..name_length],
+        );
+        return Ok(Some((entry, index as u64 + 1)));
+    }
+
+    Ok(None)
+}
+
+// Reads the parent of a directory from its .. entry
+fn get_parent_cluster(directory_cluster: u32) -> Result<u32, &'static str> {
+    let dot_dot_entry: StandardDirectoryEntry =
+        read_struct(get_cluster_offset(directory_cluster) + DIRECTORY_ENTRY_SIZE)?;
+    Ok(dot_dot_entry.get_cluster())
+}
+
+/*
+    Finds a run of consecutive free entries within a directory, storing their locations
+    Directories other than the root are extended with a new cluster if there isn't enough space
+*/
+fn find_free_entries(
+    directory_cluster: u32,
+    entry_offsets: &mut [u64],
+) -> Result<(), &'static str> {
+    let mut count = 0;
+
+    loop {
+        for entry_offset in DirectoryIterator::new(directory_cluster) {
+            let entry_offset = entry_offset?;
+            let directory_entry: StandardDirectoryEntry = read_struct(entry_offset)?;
+
+            match directory_entry.filename[0] {
+                0x00 | 0xE5 => {
+                    entry_offsets[count] = entry_offset;
+                    count += 1;
+                    if count == entry_offsets.len() {
+                        return Ok(());
                     }
                 }
-                _ => panic!("Unknown attribute"),
+                _ => count = 0,
             }
+        }
 
-            cluster_address += 0x20;
+        // The FAT12/16 root directory has a fixed size
+        let mut last_cluster = match get_first_directory_cluster(directory_cluster) {
+            Some(first_cluster) => first_cluster,
+            None => return Err("Directory is full"),
+        };
+
+        while let Some(next_cluster) = get_next_cluster(last_cluster)? {
+            last_cluster = next_cluster;
         }
 
-        // TODO: Add support for directories which are greater then a cluster
-        return Err("File cannot be found in this directory");
+        // Clusters are zeroed when allocated, so every entry within the new one is free
+        let new_cluster = allocate_cluster()?.ok_or(NO_FREE_CLUSTERS)?;
+        set_fat_entry(last_cluster, new_cluster)?;
+        count = 0;
     }
 }
 
-impl Fat16 {
-    pub const fn new() -> Fat16 {
-        Fat16 {
-            bpb: None,
-            start_address: 0,
-            fat_address: 0,
-            first_data_sector_address: 0,
-            root_directory_address: 0,
-            initrd: None,
+/*
+    Gives the first cluster of a directory's cluster chain
+    The FAT12/16 root directory is a fixed region after the FATs (rather than a chain) so has none
+*/
+fn get_first_directory_cluster(directory_cluster: u32) -> Option<u32> {
+    if directory_cluster != ROOT_DIRECTORY_CLUSTER {
+        return Some(directory_cluster);
+    }
+
+    let fs = FS.lock();
+    match fs.fat_type {
+        FatType::Fat32 => Some(fs.root_cluster),
+        _ => None,
+    }
+}
+
+/*
+    Walks through every directory entry (as a location on the volume) within a directory
+    The FAT12/16 root directory is a fixed region after the FATs whilst all other directories are chains of clusters
+    Failing to read the cluster chain gives an error, after which there are no more entries
+*/
+struct DirectoryIterator {
+    cluster: Option<u32>, // None whilst walking the root directory
+    offset: u64,
+    remaining: u32, // Entries left in the current cluster (or root directory)
+}
+
+impl DirectoryIterator {
+    fn new(directory_cluster: u32) -> DirectoryIterator {
+        match get_first_directory_cluster(directory_cluster) {
+            Some(first_cluster) => DirectoryIterator {
+                cluster: Some(first_cluster),
+                offset: get_cluster_offset(first_cluster),
+                remaining: get_cluster_size() / DIRECTORY_ENTRY_SIZE as u32,
+            },
+            None => {
+                let fs = FS.lock();
+                DirectoryIterator {
+                    cluster: None,
+                    offset: fs.root_directory_offset,
+                    remaining: fs.bpb.unwrap().root_entry_count as u32,
+                }
+            }
         }
     }
 }
 
-// Should ensure it's FAT16 and check certain values
-fn validate_fat(bpb: &BiosParameterBlock, ebr: &ExtendedBootRecord) -> bool {
-    // TODO: Calculate number of clusters and check whether smaller then 65525 and expand
-    assert!(
-        ebr.signature == 0x28 || ebr.signature == 0x29,
-        "Invalid signature"
-    );
-    assert!(
-        ebr.bootable_partition_signature == 0xAA55,
-        "Bootable partition signature is not 0xAA55"
-    );
+impl Iterator for DirectoryIterator {
+    type Item = Result<u64, &'static str>;
+
+    fn next(&mut self) -> Option<Result<u64, &'static str>> {
+        if self.remaining == 0 {
+            // Move onto the next cluster of the directory
+            let next_cluster = match get_next_cluster(self.cluster?) {
+                Ok(next_cluster) => next_cluster?,
+                Err(error) => {
+                    self.cluster = None;
+                    return Some(Err(error));
+                }
+            };
+            self.cluster = Some(next_cluster);
+            self.offset = get_cluster_offset(next_cluster);
+            self.remaining = get_cluster_size() / DIRECTORY_ENTRY_SIZE as u32;
+        }
 
-    let root_directory_sectors =
-        (bpb.root_entry_count * 32) + (bpb.bytes_per_sector - 1) / bpb.bytes_per_sector;
+        let offset = self.offset;
+        self.offset += DIRECTORY_ENTRY_SIZE;
+        self.remaining -= 1;
+        Some(Ok(offset))
+    }
+}
+
+impl Fat {
+    pub const fn new() -> Fat {
+        Fat {
+            device: None,
+            bpb: None,
+            fat_type: FatType::Fat16,
+            table_size: 0,
+            fat_offset: 0,
+            data_offset: 0,
+            root_directory_offset: 0,
+            root_cluster: 0,
+            fsinfo_offset: None,
+            cluster_count: 0,
+            next_free_cluster: 2,
+        }
+    }
+}
 
-    let data_sectors = bpb.sector_count_16
-        - (bpb.reserved_sector_count
-            + (bpb.table_count as u16 * bpb.table_size_16)
-            + root_directory_sectors);
+impl FatType {
+    // Volumes are only FAT32 when they have at least 65525 clusters (whatever the boot record says)
+    fn from_cluster_count(cluster_count: u32) -> FatType {
+        match cluster_count {
+            0..=4084 => FatType::Fat12,
+            4085..=65524 => FatType::Fat16,
+            _ => FatType::Fat32,
+        }
+    }
 
-    let total_clusters = data_sectors / bpb.sectors_per_track;
+    // Entries of at least this value mark the end of a cluster chain
+    fn end_of_chain(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFF8,
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        }
+    }
 
-    assert!(total_clusters < 65525, "File system is FAT16");
+    fn bad_cluster(&self) -> u32 {
+        self.end_of_chain() - 1
+    }
+}
 
-    return true;
+// Checks the boot record belongs to a FAT filesystem which this driver can use
+fn validate_fat(
+    bpb: &BiosParameterBlock,
+    signature: u8,
+    bootable_partition_signature: u16,
+) -> bool {
+    (signature == 0x28 || signature == 0x29)
+        && bootable_partition_signature == 0xAA55
+        && bpb.bytes_per_sector as usize == SECTOR_SIZE
+        && bpb.sectors_per_cluster != 0
+        && bpb.table_count != 0
 }
 
-fn get_next_cluster(cluster_num: u32) -> Option<u32> {
-    let fat_offset = cluster_num * 2;
-    let sector_number = fat_offset / 512;
-    let byte_offset = fat_offset % 512;
+fn get_next_cluster(cluster_num: u32) -> Result<Option<u32>, &'static str> {
+    let next_cluster = get_fat_entry(cluster_num)?;
+    let fat_type = FS.lock().fat_type;
 
-    let next_cluster = read_fat(sector_number, byte_offset as usize);
+    Ok(match next_cluster {
+        0 => None, // Free cluster (shouldn't be part of a chain)
+        _ if next_cluster == fat_type.bad_cluster() => return Err(CORRUPT_CLUSTER_CHAIN),
+        _ if next_cluster >= fat_type.end_of_chain() => None, // Indicates the whole file has been read
+        _ => Some(next_cluster),                              // Gives next cluster number
+    })
+}
 
-    return match next_cluster {
-        0xFFF7 => panic!("Bad cluster!"), // Indicates bad cluster
-        0xFFF8..=0xFFFF => None,          // Indicates the whole file has been read
-        _ => Some(next_cluster as u32),   // Gives next cluster number
-    };
+/*
+    Gets the next cluster in a chain, and when writing, extends the chain with a new cluster if at the end
+    Gives None at the end of the chain (or when the disk is full)
+*/
+fn get_next_cluster_or_extend(cluster_num: u32, extend: bool) -> Result<Option<u32>, &'static str> {
+    match get_next_cluster(cluster_num)? {
+        Some(next_cluster) => Ok(Some(next_cluster)),
+        None if extend => {
+            let new_cluster = match allocate_cluster()? {
+                Some(new_cluster) => new_cluster,
+                None => return Ok(None),
+            };
+            set_fat_entry(cluster_num, new_cluster)?;
+            Ok(Some(new_cluster))
+        }
+        None => Ok(None),
+    }
 }
 
-fn get_next_unallocated_cluster() -> Option<u16> {
-    let fat = unsafe { &mut *((FS.lock().fat_address) as *mut [u8; 512]) };
-    for i in 0..512 {
-        if ((fat[i + 1] as u16) << 8 | (fat[i] as u16)) == 0 {
-            fat[i] = 0xFF;
-            fat[i + 1] = 0xFF;
-            return Some(i as u16);
+/*
+    Finds a free cluster within the FAT, marks it as the end of a chain and returns it
+    The search starts after the last allocated cluster and wraps around
+*/
+fn get_next_unallocated_cluster() -> Result<Option<u32>, &'static str> {
+    let (cluster_count, start, end_of_chain) = {
+        let fs = FS.lock();
+        (
+            fs.cluster_count,
+            fs.next_free_cluster,
+            fs.fat_type.end_of_chain(),
+        )
+    };
+
+    // First 2 entries are reserved
+    for i in 0..cluster_count {
+        let cluster_num = (start - 2 + i) % cluster_count + 2;
+        if get_fat_entry(cluster_num)? == 0 {
+            set_fat_entry(cluster_num, end_of_chain)?;
+            FS.lock().next_free_cluster = cluster_num + 1;
+            update_fsinfo(-1)?;
+            return Ok(Some(cluster_num));
         }
     }
-    return None;
+    Ok(None)
+}
+
+// Allocates a cluster and clears it so no old data leaks into new files (None when there are no free clusters)
+fn allocate_cluster() -> Result<Option<u32>, &'static str> {
+    let cluster_num = match get_next_unallocated_cluster()? {
+        Some(cluster_num) => cluster_num,
+        None => return Ok(None),
+    };
+    zero_volume(get_cluster_offset(cluster_num), get_cluster_size() as u64)?;
+    Ok(Some(cluster_num))
 }
 
 /*
@@ -525,104 +1261,625 @@ fn get_lba(cluster_num: u32) -> u32 {
     return (cluster_num - 2) * (FS.lock().bpb.unwrap().sectors_per_cluster) as u32;
 }
 
-// Uses 16 bits to address clusters
-fn read_fat(sector_num: u32, byte_offset: usize) -> u16 {
-    let fat = unsafe {
-        &*((FS.lock().fat_address + convert_sector_to_bytes(sector_num)) as *const [u8; 512])
+// Marks every cluster within a chain as free
+fn free_cluster_chain(cluster_num: u32) -> Result<(), &'static str> {
+    let mut cluster_num = cluster_num;
+
+    // Clusters 0 and 1 are reserved (empty files have no clusters)
+    while cluster_num >= 2 {
+        let next_cluster = get_next_cluster(cluster_num)?;
+        set_fat_entry(cluster_num, 0)?;
+        update_fsinfo(1)?;
+
+        match next_cluster {
+            Some(next_cluster) => cluster_num = next_cluster,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/*
+    Entries are 12 bits (1.5 bytes), 16 bits or 32 bits (of which the top 4 are reserved) depending on the FAT type
+    FAT12 entries are read 2 bytes at a time, with odd clusters using the top 12 bits and even clusters the bottom 12
+*/
+fn get_fat_entry(cluster_num: u32) -> Result<u32, &'static str> {
+    let (fat_offset, fat_type) = {
+        let fs = FS.lock();
+        (fs.fat_offset, fs.fat_type)
     };
-    return ((fat[byte_offset + 1] as u16) << 8) | (fat[byte_offset] as u16); // Little endian
+
+    // Little endian
+    let entry_offset = fat_offset + get_fat_entry_offset(cluster_num, fat_type);
+    Ok(match fat_type {
+        FatType::Fat12 => {
+            let entry = read_struct::<u16>(entry_offset)?;
+            match cluster_num & 1 {
+                0 => (entry & 0x0FFF) as u32,
+                _ => (entry >> 4) as u32,
+            }
+        }
+        FatType::Fat16 => read_struct::<u16>(entry_offset)? as u32,
+        FatType::Fat32 => read_struct::<u32>(entry_offset)? & 0x0FFF_FFFF,
+    })
 }
 
-// Most addresses are calculated sectors and therefore must be converted into bytes to be read/written
-fn convert_sector_to_bytes(sector: u32) -> u32 {
-    return sector * 512;
+// Updates the entry for a cluster within every copy of the FAT
+fn set_fat_entry(cluster_num: u32, value: u32) -> Result<(), &'static str> {
+    let (fat_offset, table_count, table_size, fat_type) = {
+        let fs = FS.lock();
+        (
+            fs.fat_offset,
+            fs.bpb.unwrap().table_count,
+            fs.table_size,
+            fs.fat_type,
+        )
+    };
+
+    for i in 0..table_count as u64 {
+        let entry_offset = fat_offset
+            + i * convert_sector_to_bytes(table_size)
+            + get_fat_entry_offset(cluster_num, fat_type);
+
+        // Neighbouring bits (the other FAT12 entry sharing the bytes, or the reserved FAT32 bits) are kept
+        match fat_type {
+            FatType::Fat12 => {
+                let entry = read_struct::<u16>(entry_offset)?;
+                let entry = match cluster_num & 1 {
+                    0 => (entry & 0xF000) | (value as u16 & 0x0FFF),
+                    _ => (entry & 0x000F) | ((value as u16) << 4),
+                };
+                write_struct(entry_offset, &entry)?;
+            }
+            FatType::Fat16 => write_struct(entry_offset, &(value as u16))?,
+            FatType::Fat32 => {
+                let entry = read_struct::<u32>(entry_offset)?;
+                write_struct(
+                    entry_offset,
+                    &((entry & 0xF000_0000) | (value & 0x0FFF_FFFF)),
+                )?;
+            }
+        }
+    }
+    Ok(())
 }
 
-pub static FS: Mutex<Fat16> = Mutex::new(Fat16::new());
+fn get_fat_entry_offset(cluster_num: u32, fat_type: FatType) -> u64 {
+    let cluster_num = cluster_num as u64;
+    match fat_type {
+        FatType::Fat12 => cluster_num + cluster_num / 2,
+        FatType::Fat16 => cluster_num * 2,
+        FatType::Fat32 => cluster_num * 4,
+    }
+}
+
+/*
+    Keeps the FAT32 FSInfo sector up to date when clusters are allocated or freed
+    A free count of 0xFFFFFFFF means unknown and is left alone
+*/
+fn update_fsinfo(free_change: i32) -> Result<(), &'static str> {
+    let (fsinfo_offset, next_free_cluster) = {
+        let fs = FS.lock();
+        (fs.fsinfo_offset, fs.next_free_cluster)
+    };
+
+    if let Some(fsinfo_offset) = fsinfo_offset {
+        let mut fsinfo: FsInfo = read_struct(fsinfo_offset)?;
+        if fsinfo.free_count != 0xFFFF_FFFF {
+            fsinfo.free_count = (fsinfo.free_count as i64 + free_change as i64) as u32;
+        }
+        fsinfo.next_free = next_free_cluster;
+        write_struct(fsinfo_offset, &fsinfo)?;
+    }
+    Ok(())
+}
+
+// Most locations are calculated sectors and therefore must be converted into bytes to be read/written
+fn convert_sector_to_bytes(sector: u32) -> u64 {
+    sector as u64 * SECTOR_SIZE as u64
+}
+
+// Everything goes through the buffer cache so FAT and directory sectors stay in memory
+fn read_volume(offset: u64, buffer: &mut [u8]) -> Result<(), &'static str> {
+    buffer_cache::read_bytes(get_device()?, offset, buffer)
+}
+
+fn write_volume(offset: u64, buffer: &[u8]) -> Result<(), &'static str> {
+    buffer_cache::write_bytes(get_device()?, offset, buffer)
+}
+
+// Clears a region of the volume
+fn zero_volume(offset: u64, length: u64) -> Result<(), &'static str> {
+    let zeroes: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    let mut position = 0;
+
+    while position < length {
+        let count =
+            (SECTOR_SIZE as u64 - (offset + position) % SECTOR_SIZE as u64).min(length - position);
+        write_volume(offset + position, &zeroes[0..count as usize])?;
+        position += count;
+    }
+    Ok(())
+}
+
+// On disk structures (directory entries, FAT entries) are copied out of the volume rather than referenced
+fn read_struct<T: Copy>(offset: u64) -> Result<T, &'static str> {
+    let mut value = mem::MaybeUninit::<T>::zeroed();
+    let buffer = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>())
+    };
+    read_volume(offset, buffer)?;
+    Ok(unsafe { value.assume_init() })
+}
 
-pub fn init(start_address: u32) {
-    let bpb = unsafe { &*(start_address as *const BiosParameterBlock) };
+fn write_struct<T: Copy>(offset: u64, value: &T) -> Result<(), &'static str> {
+    let buffer =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
+    write_volume(offset, buffer)
+}
 
-    let ebr_address = start_address + (mem::size_of::<BiosParameterBlock>() as u32);
-    let ebr = unsafe { &*(ebr_address as *const ExtendedBootRecord) };
+fn get_device() -> Result<usize, &'static str> {
+    FS.lock().device.ok_or("No filesystem is mounted")
+}
 
-    validate_fat(bpb, ebr);
+// Writes every change to the filesystem which is still within the buffer cache back to the disk
+pub fn sync() -> Result<(), &'static str> {
+    buffer_cache::sync(Some(get_device()?))
+}
 
-    let first_fat = start_address + convert_sector_to_bytes(bpb.reserved_sector_count as u32);
-    let fat_size: u32 = bpb.table_size_16 as u32;
+// Cluster size is set by the filesystem itself within the BPB
+fn get_cluster_size() -> u32 {
+    let bpb = FS.lock().bpb.unwrap();
+    bpb.bytes_per_sector as u32 * bpb.sectors_per_cluster as u32
+}
+
+fn get_cluster_offset(cluster_num: u32) -> u64 {
+    convert_sector_to_bytes(get_lba(cluster_num)) + FS.lock().data_offset
+}
+
+// New directories are empty apart from entries which point to itself (.) and its parent (..)
+fn init_directory(cluster_num: u32, parent_cluster: u32) -> Result<(), &'static str> {
+    let cluster_offset = get_cluster_offset(cluster_num);
+    zero_volume(cluster_offset, get_cluster_size() as u64)?;
+
+    let mut dot_entry: StandardDirectoryEntry = read_struct(cluster_offset)?;
+    dot_entry.filename = *b".       ";
+    dot_entry.ext = *b"   ";
+    dot_entry.attributes = 0x10;
+    dot_entry.set_cluster(cluster_num);
+    write_struct(cluster_offset, &dot_entry)?;
+
+    let mut dot_dot_entry = dot_entry;
+    dot_dot_entry.filename = *b"..      ";
+    dot_dot_entry.set_cluster(parent_cluster);
+    write_struct(cluster_offset + DIRECTORY_ENTRY_SIZE, &dot_dot_entry)
+}
+
+pub static FS: Mutex<Fat> = Mutex::new(Fat::new());
+
+// Clusters 0 and 1 are reserved, so cluster 0 is used to refer to the root directory (as within .. entries)
+const ROOT_DIRECTORY_CLUSTER: u32 = 0;
+const DIRECTORY_ENTRY_SIZE: u64 = 32;
+
+const NO_FREE_CLUSTERS: &str = "No free clusters";
+const CORRUPT_CLUSTER_CHAIN: &str = "Corrupt cluster chain";
+
+// Written over the first byte of deleted entries
+const DELETED_ENTRY_MARKER: u8 = 0xE5;
+
+// Long file names are split across entries which each hold 13 UCS-2 characters
+const LFN_ATTRIBUTE: u8 = 0x0F;
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_ORDER_MASK: u8 = 0x1F;
+const LFN_CHARACTERS: usize = 13;
+const MAX_LFN_ENTRIES: usize = 20;
+const MAX_FILENAME_LENGTH: usize = 255;
+
+// Mounts a block device if it holds a FAT filesystem (only one can be mounted at a time as the state is global)
+pub fn mount(device: usize) -> Option<*mut dyn FileSystem> {
+    if FS.lock().device.is_some() {
+        return None;
+    }
+
+    // Boot record is within the first sector
+    let mut boot_sector: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    unsafe {
+        if (*block::get(device).unwrap())
+            .read_sectors(0, 1, boot_sector.as_mut_ptr())
+            .is_err()
+        {
+            return None;
+        }
+    }
+
+    let bpb = unsafe { (boot_sector.as_ptr() as *const BiosParameterBlock).read_unaligned() };
+    let extended_boot_record = unsafe {
+        boot_sector
+            .as_ptr()
+            .add(mem::size_of::<BiosParameterBlock>())
+    };
+    let ebr = unsafe { (extended_boot_record as *const ExtendedBootRecord).read_unaligned() };
+    let ebr_32 =
+        unsafe { (extended_boot_record as *const Fat32ExtendedBootRecord).read_unaligned() };
+
+    // FAT32 volumes have no 16 bit FAT size (the size is within the FAT32 extended boot record instead)
+    let (table_size, is_valid) = match bpb.table_size_16 {
+        0 => (
+            ebr_32.table_size_32,
+            validate_fat(&bpb, ebr_32.signature, ebr_32.bootable_partition_signature),
+        ),
+        table_size => (
+            table_size as u32,
+            validate_fat(&bpb, ebr.signature, ebr.bootable_partition_signature),
+        ),
+    };
+
+    if !is_valid || table_size == 0 {
+        return None;
+    }
+
+    let first_fat = convert_sector_to_bytes(bpb.reserved_sector_count as u32);
 
     let root_directory_sector: u32 =
-        (bpb.reserved_sector_count as u32) + ((bpb.table_count as u32) * fat_size);
+        (bpb.reserved_sector_count as u32) + ((bpb.table_count as u32) * table_size);
 
-    let root_directory_address: u32 =
-        start_address + convert_sector_to_bytes(root_directory_sector);
+    let root_directory_offset = convert_sector_to_bytes(root_directory_sector);
 
-    let root_directory_size: u32 = ((((bpb.root_entry_count) * 32) + (bpb.bytes_per_sector - 1))
-        / bpb.bytes_per_sector) as u32;
-    let first_data_sector: u32 =
-        convert_sector_to_bytes(root_directory_size) + root_directory_address;
+    // FAT32 has no fixed root directory so the size is 0
+    let root_directory_size: u32 =
+        ((bpb.root_entry_count * 32).div_ceil(bpb.bytes_per_sector)) as u32;
+    let first_data_sector = convert_sector_to_bytes(root_directory_size) + root_directory_offset;
 
-    let initrd: File = File::new(root_directory_sector, 512, FileType::Directory);
+    // Data region is split into clusters (which are numbered from 2)
+    let total_sectors = match bpb.sector_count_16 {
+        0 => bpb.sector_count_32,
+        sector_count => sector_count as u32,
+    };
+    let data_sectors = total_sectors.checked_sub(root_directory_sector + root_directory_size)?;
+    let cluster_count = data_sectors / (bpb.sectors_per_cluster as u32);
 
-    FS.lock().bpb = Some(bpb.clone());
+    // Cluster count is what decides the type of FAT
+    let fat_type = FatType::from_cluster_count(cluster_count);
+    if (fat_type == FatType::Fat32) != (bpb.table_size_16 == 0) {
+        return None;
+    }
 
-    FS.lock().start_address = start_address;
-    FS.lock().fat_address = first_fat;
-    FS.lock().first_data_sector_address = first_data_sector;
-    FS.lock().root_directory_address = root_directory_address;
-    FS.lock().initrd = Some(initrd);
+    let mut fs = FS.lock();
+    fs.device = Some(device);
+    fs.bpb = Some(bpb);
+    fs.fat_type = fat_type;
+    fs.table_size = table_size;
+    fs.fat_offset = first_fat;
+    fs.data_offset = first_data_sector;
+    fs.root_directory_offset = root_directory_offset;
+    fs.cluster_count = cluster_count;
+    fs.next_free_cluster = 2;
+    fs.fsinfo_offset = None;
+
+    if fat_type == FatType::Fat32 {
+        fs.root_cluster = ebr_32.root_cluster;
+        drop(fs);
+        mount_fsinfo(ebr_32.fsinfo_sector as u32, cluster_count);
+    }
+    Some(core::ptr::addr_of_mut!(FAT_FILESYSTEM))
 }
 
-// Copies number of bytes into destination
+// Reads the FSInfo sector (if it's valid) to find where to start looking for free clusters
+fn mount_fsinfo(fsinfo_sector: u32, cluster_count: u32) {
+    if fsinfo_sector == 0 || fsinfo_sector == 0xFFFF {
+        return;
+    }
+
+    let fsinfo_offset = convert_sector_to_bytes(fsinfo_sector);
+    let fsinfo: FsInfo = match read_struct(fsinfo_offset) {
+        Ok(fsinfo) => fsinfo,
+        Err(_) => return,
+    };
+    let (lead_signature, signature, trail_signature, next_free) = (
+        fsinfo.lead_signature,
+        fsinfo.signature,
+        fsinfo.trail_signature,
+        fsinfo.next_free,
+    );
+
+    if lead_signature != 0x41615252 || signature != 0x61417272 || trail_signature != 0xAA550000 {
+        return;
+    }
+
+    let mut fs = FS.lock();
+    fs.fsinfo_offset = Some(fsinfo_offset);
+    if next_free >= 2 && next_free < cluster_count + 2 {
+        fs.next_free_cluster = next_free;
+    }
+}
+
+// Copies a cluster worth of bytes into destination
 pub unsafe fn memcpy_cluster(dest: *mut u8, src: *mut u8, index: u32) {
-    for i in 0..2048 {
-        let offset = ((index * 2048) + i) as isize;
+    let cluster_size = get_cluster_size();
+    for i in 0..cluster_size {
+        let offset = ((index * cluster_size) + i) as isize;
         *(dest.offset(offset)) = *(src.offset(offset));
     }
 }
 
 /*
-    Parses an absolute filepath and returns a file descriptor
-    File descriptor is a unqiue unsigned integer which is used to identify an open file
+    Converts a FAT date and time into seconds since the unix epoch
+    Dates count years from 1980 and times are stored to a precision of 2 seconds
 */
-pub fn parse_absolute_filepath(filepath: &str) -> Result<File, &str> {
-    let cleaned_filepath: &str = &filepath[0..filepath.len()]; // Remove the initial / for ease
+fn fat_timestamp_to_unix(date: u16, time: u16) -> i64 {
+    if date == 0 {
+        return 0;
+    }
 
-    let mut current_fd = FS.lock().initrd.unwrap();
+    let year = 1980 + ((date >> 9) & 0x7F) as i64;
+    let month = ((date >> 5) & 0x0F).max(1) as i64;
+    let day = (date & 0x1F).max(1) as i64;
 
-    // Split path against /
-    let mut filepath_components = cleaned_filepath.split("/");
+    let hours = ((time >> 11) & 0x1F) as i64;
+    let minutes = ((time >> 5) & 0x3F) as i64;
+    let seconds = ((time & 0x1F) * 2) as i64;
 
-    match filepath_components.next() {
-        Some(first_component) => {
-            current_fd = current_fd.find_root(first_component).unwrap();
+    // Days since the epoch (counting years from March so the leap day comes last)
+    let (year, month) = match month {
+        1 | 2 => (year - 1, month + 9),
+        _ => (year, month - 3),
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
 
-            // Loop through each other portion and return the file/directory needed
-            for component in filepath_components {
-                let result = current_fd.find(component);
+    days * 86400 + hours * 3600 + minutes * 60 + seconds
+}
 
-                if result.is_ok() {
-                    current_fd = result.unwrap();
-                } else {
-                    return Err("File not found");
-                }
+// Inodes are the location of a file's directory entry, apart from the root directory which has no entry
+const ROOT_INODE: Inode = 0;
+
+// Gives the file which an inode refers to
+fn get_file(inode: Inode) -> Result<File, &'static str> {
+    if inode == ROOT_INODE {
+        return Ok(File::new(ROOT_DIRECTORY_CLUSTER, 0, FileType::Directory));
+    }
+
+    let directory_entry: StandardDirectoryEntry = read_struct(inode)?;
+    let mut file = File::new(
+        directory_entry.get_cluster(),
+        directory_entry.file_size,
+        directory_entry.get_file_type(),
+    );
+    file.name = directory_entry.filename;
+    file.entry_offset = inode;
+    Ok(file)
+}
+
+fn find_file(directory: Inode, filename: &str) -> Result<File, &'static str> {
+    let directory = get_file(directory)?;
+    if !directory.is_directory() {
+        return Err(NOT_A_DIRECTORY);
+    }
+    directory.find(filename)
+}
+
+// Deletes a file and frees its clusters
+fn unlink(directory: Inode, filename: &str) -> Result<(), &'static str> {
+    let file = find_file(directory, filename)?;
+
+    if file.file_type == FileType::Directory {
+        return Err("Tried to unlink a directory");
+    }
+
+    remove_entry(file.directory_cluster, file.entry_offset)?;
+    free_cluster_chain(file.cluster)
+}
+
+// Deletes a directory as long as it's empty
+fn rmdir(directory: Inode, filename: &str) -> Result<(), &'static str> {
+    if filename == "." || filename == ".." {
+        return Err(INVALID_FILENAME);
+    }
+
+    let directory = find_file(directory, filename)?;
+
+    if directory.file_type != FileType::Directory {
+        return Err("Tried to rmdir on a file");
+    }
+    if !is_directory_empty(directory.cluster)? {
+        return Err(DIRECTORY_NOT_EMPTY);
+    }
+
+    remove_entry(directory.directory_cluster, directory.entry_offset)?;
+    free_cluster_chain(directory.cluster)
+}
+
+/*
+    Moves a file/directory to a new name (which can be within another directory)
+    An existing file with the new name is replaced, as is an existing empty directory when moving a directory
+*/
+fn rename(
+    old_directory: Inode,
+    old_filename: &str,
+    new_directory: Inode,
+    new_filename: &str,
+) -> Result<(), &'static str> {
+    if old_filename == "." || old_filename == ".." {
+        return Err(INVALID_FILENAME);
+    }
+
+    let source = find_file(old_directory, old_filename)?;
+
+    let destination_directory = get_file(new_directory)?;
+    if destination_directory.file_type != FileType::Directory {
+        return Err(NOT_A_DIRECTORY);
+    }
+    let destination_cluster = destination_directory.cluster;
+
+    // A directory can't be moved inside of itself
+    if source.file_type == FileType::Directory {
+        let mut cluster_num = destination_cluster;
+        while cluster_num != ROOT_DIRECTORY_CLUSTER {
+            if cluster_num == source.cluster {
+                return Err("Cannot move a directory inside of itself");
             }
+            cluster_num = get_parent_cluster(cluster_num)?;
         }
-        None => {
-            return Err("Filepath is in incorrect format");
+    }
+
+    let directory_entry: StandardDirectoryEntry = read_struct(source.entry_offset)?;
+
+    match destination_directory.find(new_filename) {
+        // Same file (such as when only the case changes), so the new entry briefly shares its short name with the old one
+        Ok(existing) if existing.entry_offset == source.entry_offset => {}
+        Ok(existing) => {
+            match (source.file_type, existing.file_type) {
+                (FileType::File, FileType::File) => {}
+                (FileType::Directory, FileType::Directory)
+                    if is_directory_empty(existing.cluster)? => {}
+                (_, FileType::Directory) => return Err("Cannot replace a directory"),
+                _ => return Err("Cannot replace a file with a directory"),
+            }
+
+            remove_entry(existing.directory_cluster, existing.entry_offset)?;
+            free_cluster_chain(existing.cluster)?;
         }
+        Err(_) => {}
     }
 
-    return Ok(current_fd);
+    // The old entry is only removed once the new one exists, so a failed rename doesn't lose the file
+    create_entry(destination_cluster, new_filename, directory_entry)?;
+    remove_entry(source.directory_cluster, source.entry_offset)?;
+
+    // Directories which have moved need their .. entry updated
+    if source.file_type == FileType::Directory && source.directory_cluster != destination_cluster {
+        let dot_dot_offset = get_cluster_offset(source.cluster) + DIRECTORY_ENTRY_SIZE;
+        let mut dot_dot_entry: StandardDirectoryEntry = read_struct(dot_dot_offset)?;
+        dot_dot_entry.set_cluster(destination_cluster);
+        write_struct(dot_dot_offset, &dot_dot_entry)?;
+    }
+
+    Ok(())
 }
 
-// Replace this by parsing the file and creating it if it doesn't exist in a specific path
-pub fn create_new_root_file(filename: &str) -> File {
-    let mut current_fd = FS.lock().initrd.unwrap();
-    let fd = current_fd.mkf_root(filename).unwrap();
-    fd
+/*
+    FAT has no inodes, so files are referred to by the location of their directory entry
+    Renaming a file moves its entry, so it is given a new inode
+*/
+pub struct FatFileSystem;
+
+static mut FAT_FILESYSTEM: FatFileSystem = FatFileSystem;
+
+impl FileSystem for FatFileSystem {
+    fn name(&self) -> &'static str {
+        match FS.lock().fat_type {
+            FatType::Fat12 => "FAT12",
+            FatType::Fat16 => "FAT16",
+            FatType::Fat32 => "FAT32",
+        }
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str> {
+        find_file(directory, name).map(|file| file.entry_offset)
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str> {
+        get_file(inode)?.stat()
+    }
+
+    fn read(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let mut file = get_file(inode)?;
+        file.set_offset(offset as i64);
+        Ok(file.read(buffer.as_mut_ptr(), buffer.len())? as usize)
+    }
+
+    fn write(&mut self, inode: Inode, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let mut file = get_file(inode)?;
+        file.set_offset(offset as i64);
+        Ok(file.write(buffer.as_ptr() as *mut u8, buffer.len())? as usize)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+        let directory = get_file(directory)?;
+        if !directory.is_directory() {
+            return Err(NOT_A_DIRECTORY);
+        }
+        read_directory_entry(directory.cluster, index)
+    }
+
+    fn create(
+        &mut self,
+        directory: Inode,
+        name: &str,
+        file_type: FileType,
+    ) -> Result<Inode, &'static str> {
+        let mut directory = get_file(directory)?;
+        let file = match file_type {
+            FileType::File => directory.mkf(name)?,
+            FileType::Directory => directory.mkdir(name)?,
+            _ => return Err("FAT can only hold files and directories"),
+        };
+        Ok(file.entry_offset)
+    }
+
+    // FAT has no way to store links
+    fn symlink(
+        &mut self,
+        _directory: Inode,
+        _name: &str,
+        _target: &str,
+    ) -> Result<Inode, &'static str> {
+        Err(NOT_PERMITTED)
+    }
+
+    fn link(&mut self, _directory: Inode, _name: &str, _inode: Inode) -> Result<(), &'static str> {
+        Err(NOT_PERMITTED)
+    }
+
+    // FAT has no way to store owners or permissions
+    fn chmod(&mut self, _inode: Inode, _mode: u32) -> Result<(), &'static str> {
+        Err(NOT_PERMITTED)
+    }
+
+    fn chown(&mut self, _inode: Inode, _uid: u16, _gid: u16) -> Result<(), &'static str> {
+        Err(NOT_PERMITTED)
+    }
+
+    fn unlink(&mut self, directory: Inode, name: &str) -> Result<(), &'static str> {
+        unlink(directory, name)
+    }
+
+    fn rmdir(&mut self, directory: Inode, name: &str) -> Result<(), &'static str> {
+        rmdir(directory, name)
+    }
+
+    fn rename(
+        &mut self,
+        old_directory: Inode,
+        old_name: &str,
+        new_directory: Inode,
+        new_name: &str,
+    ) -> Result<(), &'static str> {
+        rename(old_directory, old_name, new_directory, new_name)
+    }
+
+    fn sync(&mut self) -> Result<(), &'static str> {
+        sync()
+    }
 }
 
 pub fn round_to_nearest_cluster(size: u64) -> u64 {
-    ((size as i64 + 2047) & (-2048)) as u64
+    let cluster_size = get_cluster_size() as u64;
+    size.div_ceil(cluster_size) * cluster_size
 }
@@ -0,0 +1,123 @@
+// src/futex.rs
+
+/*
+    Futexes (fast userspace mutexes) allow userland to build mutexes and condition variables which only enter the kernel on contention
+    The lock itself is a 32 bit word in user memory which is modified atomically by userland
+    FUTEX_WAIT puts the calling thread to sleep as long as the word still holds the expected value
+    FUTEX_WAKE wakes up a number of threads sleeping on the word
+    Waiters are keyed by the physical address of the word so processes which map the same memory at different addresses share a queue
+*/
+
+use crate::multitask::{self, WaitQueue, PROCESS_SCHEDULAR};
+use crate::paging;
+use crate::spinlock::Lock;
+
+pub const FUTEX_WAIT: u64 = 0;
+pub const FUTEX_WAKE: u64 = 1;
+
+const MAX_FUTEX_QUEUES: usize = 64;
+
+pub const VALUE_CHANGED: &str = "Futex word no longer holds the expected value";
+pub const MISALIGNED_ADDRESS: &str = "Futex word is not 4 byte aligned";
+pub const BAD_ADDRESS: &str = "Futex word is not mapped";
+pub const NO_FREE_QUEUES: &str = "Every futex queue is in use";
+pub const UNKNOWN_OPERATION: &str = "Unknown futex operation";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct FutexQueue {
+    key: u64, // Physical address of the futex word
+    waiters: WaitQueue,
+}
+
+// Queues are only created for words which currently have waiters
+static FUTEX_QUEUES: Lock<[Option<FutexQueue>; MAX_FUTEX_QUEUES]> =
+    Lock::new([None; MAX_FUTEX_QUEUES]);
+
+/*
+    Blocks the current thread until woken, as long as the word at address still equals expected
+    Syscalls run with interrupts disabled so checking the word and joining the queue cannot race with a wake
+*/
+pub fn wait(address: *const u32, expected: u32) -> Result<u64, &'static str> {
+    let key = get_key(address)?;
+
+    if unsafe { core::ptr::read_volatile(address) } != expected {
+        return Err(VALUE_CHANGED); // Value has already changed so there is no need to sleep
+    }
+
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+
+    let tid = match current_thread {
+        Some(thread) => thread.tid,
+        None => return Err("No thread is running"),
+    };
+
+    let queues = FUTEX_QUEUES.lock();
+    let index = match find_queue(queues, key) {
+        Some(index) => index,
+        None => match queues.iter().position(|queue| queue.is_none()) {
+            Some(index) => {
+                queues[index] = Some(FutexQueue {
+                    key,
+                    waiters: WaitQueue::new(),
+                });
+                index
+            }
+            None => {
+                FUTEX_QUEUES.free();
+                return Err(NO_FREE_QUEUES);
+            }
+        },
+    };
+    queues[index].as_mut().unwrap().waiters.push(tid);
+    FUTEX_QUEUES.free();
+
+    multitask::block_current_thread();
+
+    Ok(0)
+}
+
+// Wakes up to count threads waiting on the word at address and returns how many were woken
+pub fn wake(address: *const u32, count: u64) -> Result<u64, &'static str> {
+    let key = get_key(address)?;
+
+    let mut woken = 0;
+
+    let queues = FUTEX_QUEUES.lock();
+    if let Some(index) = find_queue(queues, key) {
+        let queue = queues[index].as_mut().unwrap();
+
+        while woken < count {
+            match queue.waiters.pop() {
+                Some(tid) => {
+                    multitask::wake_thread(tid);
+                    woken += 1;
+                }
+                None => break,
+            }
+        }
+
+        // Free up the queue once nobody is waiting upon it
+        if queue.waiters.is_empty() {
+            queues[index] = None;
+        }
+    }
+    FUTEX_QUEUES.free();
+
+    Ok(woken)
+}
+
+// The word must be aligned so it can't straddle two pages (which may not be physically contiguous)
+fn get_key(address: *const u32) -> Result<u64, &'static str> {
+    if !address.is_aligned() {
+        return Err(MISALIGNED_ADDRESS);
+    }
+
+    paging::translate(address as u64).ok_or(BAD_ADDRESS)
+}
+
+fn find_queue(queues: &[Option<FutexQueue>; MAX_FUTEX_QUEUES], key: u64) -> Option<usize> {
+    queues
+        .iter()
+        .position(|queue| queue.is_some_and(|queue| queue.key == key))
+}
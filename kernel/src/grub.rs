@@ -10,14 +10,19 @@
 
 #![allow(unused_variables)]
 
+use crate::allocator::{kfree, kmalloc};
+use crate::block::{self, RamDisk};
 use crate::elf;
 use crate::framebuffer;
-use crate::fs;
-use crate::multitask;
+use crate::initrd;
+use crate::multitask::{self, PROCESS_SCHEDULAR};
 use crate::page_frame_allocator::FrameAllocator;
 use crate::page_frame_allocator::PAGE_FRAME_ALLOCATOR;
+use crate::path::Path;
 use crate::ports::inpw;
 use crate::ports::outpw;
+use crate::tty::{self, Termios};
+use crate::vfs::{self, OpenFile, Vnode};
 use crate::{print_serial, CONSOLE};
 use multiboot2::BootInformation;
 
@@ -39,9 +44,13 @@ const VBE_DISPI_LFB_ENABLED: u16 = 0x40;
 
 pub static mut process_index: u64 = 0; // This index determines the PID for each process
 
+/*
+    Modules are told apart by their contents:
+        ELF files are userspace programs which are run straight away
+        ustar/cpio archives are the initial ramdisk, which is unpacked into a tmpfs used as the root filesystem
+        Anything else is a filesystem image which is used as a ramdisk (if the constant is true)
+*/
 pub fn initialise_userland(boot_info: &BootInformation) {
-    let mut i = 0;
-
     for module in boot_info.module_tags() {
         print_serial!(
             "MODULE ADDRESS = 0x{:x}, SIZE =  0x{:x} END ADDRESS = 0x{:x}\n",
@@ -49,31 +58,148 @@ pub fn initialise_userland(boot_info: &BootInformation) {
             module.module_size(),
             module.end_address(),
         );
-        // First module will be filesystem if given and constant is true
-        if FILESYSTEM_ON && i == 0 {
-            fs::init(module.start_address());
-        } else {
-            // Else, modules are userspace programs
-            elf::parse(module.start_address() as u64);
-
-            // Allocate memory for the usermode process
-            let user_process = unsafe {
-                multitask::Process::init(multitask::ProcessPriority::High, process_index)
-            };
-
-            // Add process to list of processes
-            multitask::PROCESS_SCHEDULAR
-                .lock()
-                .add_process(user_process);
-            multitask::PROCESS_SCHEDULAR.free();
-
-            unsafe {
-                process_index += 1;
+
+        let start = module.start_address() as u64;
+        let size = module.module_size() as u64;
+
+        if elf::is_elf(start, size) {
+            match elf::parse(start, size) {
+                Ok(entry) => {
+                    start_process(entry);
+                }
+                Err(error) => print_serial!("FAILED TO RUN MODULE AT 0x{:x}: {}\n", start, error),
+            }
+        } else if initrd::is_archive(start, size) {
+            if let Err(error) = initrd::load(start, size) {
+                print_serial!("FAILED TO LOAD THE INITIAL RAMDISK: {}\n", error);
+            }
+        } else if FILESYSTEM_ON {
+            block::register(RamDisk::new(start, size));
+        }
+    }
+}
+
+/*
+    Runs every program given by a run= option on the kernel command line (such as run=/bin/terminal)
+    Programs are read through the VFS so filesystems must be mounted first
+    Their standard streams use the console tty, unless a console= option names another tty within /dev
+    The tty can be followed by a speed (such as console=ttyS0,115200 to run a shell on COM1)
+*/
+pub fn run_programs(boot_info: &BootInformation) {
+    let command_line = match boot_info.command_line_tag() {
+        Some(tag) => tag.command_line(),
+        None => return,
+    };
+
+    let console = command_line
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("console="))
+        .and_then(|option| match open_console(option) {
+            Ok(vnode) => Some(vnode),
+            Err(error) => {
+                print_serial!("FAILED TO OPEN CONSOLE {}: {}\n", option, error);
+                None
+            }
+        });
+
+    for option in command_line.split_whitespace() {
+        if let Some(filepath) = option.strip_prefix("run=") {
+            match run_program(filepath) {
+                Ok(pid) => {
+                    if let Some(vnode) = console {
+                        set_standard_streams(pid, vnode);
+                    }
+                    print_serial!("RUNNING {} AS TASK {}\n", filepath, pid)
+                }
+                Err(error) => print_serial!("FAILED TO RUN {}: {}\n", filepath, error),
+            }
+        }
+    }
+}
+
+fn open_console(option: &str) -> Result<Vnode, &'static str> {
+    let (name, speed) = option.split_once(',').unwrap_or((option, ""));
+    let vnode = vfs::resolve(&Path::root().join("dev")?.join(name)?)?;
+    vfs::open(vnode)?;
+
+    let mut termios = core::mem::MaybeUninit::<Termios>::uninit();
+    vfs::ioctl(vnode, tty::TCGETS, termios.as_mut_ptr() as u64).map_err(|_| "Not a tty")?;
+
+    if !speed.is_empty() {
+        let mut termios = unsafe { termios.assume_init() };
+        let speed = speed.parse::<u32>().map_err(|_| "Invalid speed")?;
+        termios.c_ispeed = speed;
+        termios.c_ospeed = speed;
+        vfs::ioctl(vnode, tty::TCSETS, &termios as *const Termios as u64)?;
+    }
+    Ok(vnode)
+}
+
+// Replaces stdin, stdout and stderr of a process (which otherwise use the console tty)
+fn set_standard_streams(pid: u64, vnode: Vnode) {
+    let process = PROCESS_SCHEDULAR.lock().get_process(pid);
+    PROCESS_SCHEDULAR.free();
+
+    if let Some(process) = process {
+        let file_table = unsafe { &mut *process.file_table };
+        for fd in 0..=2 {
+            file_table.set(fd, OpenFile::new(vnode));
+        }
+    }
+}
+
+// Loads a program by name from the filesystem and starts it as a new process, returning its pid
+pub fn run_program(filepath: &str) -> Result<u64, &'static str> {
+    let vnode = vfs::resolve(&Path::root().join(filepath)?)?;
+
+    // Programs are started by the kernel as root, which can still only run files with an execute bit set
+    vfs::check_access(vnode, &multitask::Credentials::root(), vfs::X_OK)?;
+    let size = vfs::stat(vnode)?.st_size as u64;
+
+    // Segments are loaded from memory so the whole file is copied out of the filesystem first
+    let buffer = kmalloc(size.max(1)) as *mut u8;
+    let contents = unsafe { core::slice::from_raw_parts_mut(buffer, size as usize) };
+
+    let mut position = 0;
+    let mut result = Ok(());
+    while position < contents.len() {
+        match vfs::read(vnode, position as u64, &mut contents[position..]) {
+            Ok(0) => break,
+            Ok(count) => position += count,
+            Err(error) => {
+                result = Err(error);
+                break;
             }
         }
+    }
+
+    let result = result.and_then(|_| match elf::is_elf(buffer as u64, position as u64) {
+        true => Ok(start_process(elf::parse(buffer as u64, position as u64)?)),
+        false => Err("Not an ELF executable"),
+    });
+
+    kfree(buffer as *mut u64);
+    result
+}
+
+// Creates a process whose main thread starts at the entry point of a program which has been loaded
+fn start_process(entry: u64) -> u64 {
+    let pid = unsafe { process_index };
+
+    // Allocate memory for the usermode process
+    let user_process = multitask::Process::init(multitask::ProcessPriority::High, pid);
+    let main_thread = user_process.create_main_thread(entry);
+
+    // Add process to list of processes
+    multitask::PROCESS_SCHEDULAR
+        .lock()
+        .add_process(user_process, main_thread);
+    multitask::PROCESS_SCHEDULAR.free();
 
-        i += 1;
+    unsafe {
+        process_index += 1;
     }
+    pid
 }
 
 pub fn bga_set_video_mode() {
@@ -97,6 +223,6 @@ fn read_bga_register(index: u16) -> u16 {
     return inpw(VBE_DISPI_IOPORT_DATA);
 }
 
-fn is_bga_available() -> bool {
+pub fn is_bga_available() -> bool {
     return read_bga_register(VBE_DISPI_INDEX_ID) == 0xB0C5;
 }
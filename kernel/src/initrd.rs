@@ -0,0 +1,291 @@
+// src/initrd.rs
+
+/*
+    Initial ramdisk is an archive given as a module by grub, which is unpacked into a tmpfs mounted as the root
+    Two archive formats are supported (told apart by their magic numbers):
+        ustar (tar) - 512 byte headers with octal fields, followed by the data padded to 512 bytes
+        newc cpio - 110 byte headers with hexadecimal fields, followed by the name and the data (each padded to 4 bytes)
+    Files, directories and links are unpacked, with any parent directories which are missing being created
+    Files and directories keep the permissions and owners they have within the archive
+*/
+
+use crate::path::Path;
+use crate::tmpfs::Tmpfs;
+use crate::vfs::{self, FileType, Vnode, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use crate::{print_serial, CONSOLE};
+
+const USTAR_BLOCK_SIZE: usize = 512;
+const USTAR_MAGIC: &[u8] = b"ustar"; // Followed by a null (POSIX) or a space (GNU)
+const USTAR_MAGIC_OFFSET: usize = 257;
+
+const CPIO_MAGIC: &[u8] = b"07070"; // Followed by 1 (newc) or 2 (newc with checksums)
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &[u8] = b"TRAILER!!!";
+
+// Fields of a ustar header as (offset, length)
+const USTAR_NAME: (usize, usize) = (0, 100);
+const USTAR_MODE: (usize, usize) = (100, 8);
+const USTAR_UID: (usize, usize) = (108, 8);
+const USTAR_GID: (usize, usize) = (116, 8);
+const USTAR_SIZE: (usize, usize) = (124, 12);
+const USTAR_TYPE: usize = 156;
+const USTAR_LINK_NAME: (usize, usize) = (157, 100);
+const USTAR_PREFIX: (usize, usize) = (345, 155);
+
+// Types of ustar entries
+const USTAR_FILE: u8 = b'0';
+const USTAR_OLD_FILE: u8 = 0;
+const USTAR_HARD_LINK: u8 = b'1';
+const USTAR_SYMLINK: u8 = b'2';
+const USTAR_DIRECTORY: u8 = b'5';
+
+// Fields of a cpio header are 8 hexadecimal digits each, following the 6 digit magic number
+const CPIO_MODE: usize = 1;
+const CPIO_UID: usize = 2;
+const CPIO_GID: usize = 3;
+const CPIO_FILE_SIZE: usize = 6;
+const CPIO_NAME_SIZE: usize = 11;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ustar,
+    Cpio,
+}
+
+fn get_format(archive: &[u8]) -> Option<Format> {
+    if archive.len() >= USTAR_BLOCK_SIZE
+        && &archive[USTAR_MAGIC_OFFSET..USTAR_MAGIC_OFFSET + USTAR_MAGIC.len()] == USTAR_MAGIC
+    {
+        return Some(Format::Ustar);
+    }
+
+    if archive.len() >= CPIO_HEADER_SIZE
+        && &archive[0..CPIO_MAGIC.len()] == CPIO_MAGIC
+        && (archive[CPIO_MAGIC.len()] == b'1' || archive[CPIO_MAGIC.len()] == b'2')
+    {
+        return Some(Format::Cpio);
+    }
+
+    None
+}
+
+// Checks whether a module is an archive which can be used as the initial ramdisk
+pub fn is_archive(start: u64, size: u64) -> bool {
+    get_format(get_archive(start, size)).is_some()
+}
+
+fn get_archive(start: u64, size: u64) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(start as *const u8, size as usize) }
+}
+
+// Creates a tmpfs as the root filesystem and fills it with the contents of the archive
+pub fn load(start: u64, size: u64) -> Result<(), &'static str> {
+    let archive = get_archive(start, size);
+
+    vfs::mount("/", Tmpfs::new())?;
+
+    let count = match get_format(archive) {
+        Some(Format::Ustar) => unpack_ustar(archive)?,
+        Some(Format::Cpio) => unpack_cpio(archive)?,
+        None => return Err("Initial ramdisk isn't a ustar or cpio archive"),
+    };
+
+    print_serial!("UNPACKED {} FILES FROM THE INITIAL RAMDISK\n", count);
+    Ok(())
+}
+
+fn unpack_ustar(archive: &[u8]) -> Result<usize, &'static str> {
+    let mut offset = 0;
+    let mut count = 0;
+
+    while offset + USTAR_BLOCK_SIZE <= archive.len() {
+        let header = &archive[offset..offset + USTAR_BLOCK_SIZE];
+
+        // Archive ends with (at least) 2 empty blocks
+        if header[0] == 0 {
+            break;
+        }
+
+        let size = parse_number(get_field(header, USTAR_SIZE), 8)? as usize;
+        let data_start = offset + USTAR_BLOCK_SIZE;
+        let data = archive
+            .get(data_start..data_start + size)
+            .ok_or("Archive is truncated")?;
+
+        // Long names are split between the prefix and the name
+        let mut path = Path::root().join(as_str(get_field(header, USTAR_PREFIX))?)?;
+        path = path.join(as_str(get_field(header, USTAR_NAME))?)?;
+
+        let owner = Owner {
+            mode: parse_number(get_field(header, USTAR_MODE), 8)?,
+            uid: parse_number(get_field(header, USTAR_UID), 8)? as u16,
+            gid: parse_number(get_field(header, USTAR_GID), 8)? as u16,
+        };
+
+        match header[USTAR_TYPE] {
+            USTAR_FILE | USTAR_OLD_FILE => {
+                set_owner(unpack_file(&path, data)?, owner);
+                count += 1;
+            }
+            USTAR_DIRECTORY => {
+                set_owner(create_directories(&path)?, owner);
+            }
+            USTAR_SYMLINK => {
+                unpack_symlink(&path, get_field(header, USTAR_LINK_NAME))?;
+            }
+            USTAR_HARD_LINK => {
+                let target = Path::root().join(as_str(get_field(header, USTAR_LINK_NAME))?)?;
+                vfs::link(&target, &path)?;
+            }
+            entry_type => print_serial!(
+                "INITRD: SKIPPING {} (UNSUPPORTED TYPE {})\n",
+                path.as_str(),
+                entry_type as char
+            ),
+        }
+
+        offset = data_start + align(size, USTAR_BLOCK_SIZE);
+    }
+
+    Ok(count)
+}
+
+fn unpack_cpio(archive: &[u8]) -> Result<usize, &'static str> {
+    let mut offset = 0;
+    let mut count = 0;
+
+    while offset + CPIO_HEADER_SIZE <= archive.len() {
+        let header = &archive[offset..offset + CPIO_HEADER_SIZE];
+        if get_format(header) != Some(Format::Cpio) {
+            return Err("Invalid cpio header");
+        }
+
+        let mode = parse_cpio_field(header, CPIO_MODE)?;
+        let file_size = parse_cpio_field(header, CPIO_FILE_SIZE)? as usize;
+        let name_size = parse_cpio_field(header, CPIO_NAME_SIZE)? as usize;
+
+        // Name includes its null terminator
+        let name_start = offset + CPIO_HEADER_SIZE;
+        let name = archive
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or("Archive is truncated")?;
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        let data_start = offset + align(CPIO_HEADER_SIZE + name_size, 4);
+        let data = archive
+            .get(data_start..data_start + file_size)
+            .ok_or("Archive is truncated")?;
+
+        let path = Path::root().join(as_str(name)?)?;
+        let owner = Owner {
+            mode,
+            uid: parse_cpio_field(header, CPIO_UID)? as u16,
+            gid: parse_cpio_field(header, CPIO_GID)? as u16,
+        };
+
+        match mode & S_IFMT {
+            S_IFREG => {
+                set_owner(unpack_file(&path, data)?, owner);
+                count += 1;
+            }
+            S_IFDIR => {
+                set_owner(create_directories(&path)?, owner);
+            }
+            S_IFLNK => {
+                unpack_symlink(&path, data)?;
+            }
+            _ => print_serial!(
+                "INITRD: SKIPPING {} (UNSUPPORTED MODE {:o})\n",
+                path.as_str(),
+                mode
+            ),
+        }
+
+        offset = data_start + align(file_size, 4);
+    }
+
+    Ok(count)
+}
+
+fn unpack_file(path: &Path, data: &[u8]) -> Result<Vnode, &'static str> {
+    let filepath = path.as_str();
+    if let Some(index) = filepath.rfind('/') {
+        create_directories(&Path::root().join(&filepath[..index])?)?;
+    }
+
+    // Later entries with the same name replace earlier ones
+    let vnode = match vfs::resolve(path) {
+        Ok(vnode) => vnode,
+        Err(_) => vfs::create(path, FileType::File)?,
+    };
+    vfs::write(vnode, 0, data)?;
+    Ok(vnode)
+}
+
+// Targets are stored exactly as they are within the archive
+fn unpack_symlink(path: &Path, target: &[u8]) -> Result<(), &'static str> {
+    let filepath = path.as_str();
+    if let Some(index) = filepath.rfind('/') {
+        create_directories(&Path::root().join(&filepath[..index])?)?;
+    }
+
+    vfs::symlink(as_str(target)?, path)?;
+    Ok(())
+}
+
+// Permissions and owner of an entry within the archive (the mode may also hold the file type bits)
+#[derive(Copy, Clone, Debug)]
+struct Owner {
+    mode: u32,
+    uid: u16,
+    gid: u16,
+}
+
+fn set_owner(vnode: Vnode, owner: Owner) {
+    let _ = vfs::chmod(vnode, owner.mode);
+    let _ = vfs::chown(vnode, owner.uid, owner.gid);
+}
+
+// Creates a directory along with any of its parents which don't exist yet
+fn create_directories(path: &Path) -> Result<Vnode, &'static str> {
+    let mut current_path = Path::root();
+
+    for component in path.as_str().split('/').filter(|c| !c.is_empty()) {
+        current_path = current_path.join(component)?;
+        if vfs::resolve(&current_path).is_err() {
+            vfs::create(&current_path, FileType::Directory)?;
+        }
+    }
+
+    vfs::resolve(path)
+}
+
+// Header fields are padded with nulls (and sometimes spaces)
+fn get_field(header: &[u8], (offset, length): (usize, usize)) -> &[u8] {
+    let field = &header[offset..offset + length];
+    let end = field.iter().position(|&c| c == 0).unwrap_or(length);
+    &field[0..end]
+}
+
+fn parse_cpio_field(header: &[u8], index: usize) -> Result<u32, &'static str> {
+    let start = CPIO_MAGIC.len() + 1 + index * 8;
+    parse_number(&header[start..start + 8], 16)
+}
+
+fn parse_number(field: &[u8], radix: u32) -> Result<u32, &'static str> {
+    let digits = as_str(field)?.trim_matches(|c| c == ' ' || c == '\0');
+    match digits.is_empty() {
+        true => Ok(0),
+        false => u32::from_str_radix(digits, radix).map_err(|_| "Invalid number within header"),
+    }
+}
+
+fn as_str(bytes: &[u8]) -> Result<&str, &'static str> {
+    core::str::from_utf8(bytes).map_err(|_| "Invalid name within archive")
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
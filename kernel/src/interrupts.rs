@@ -8,16 +8,16 @@ An interrupt descriptor table defines what each interrupt will do
 TODO: Clean and refactor (use Lock)
 */
 
+use crate::buffer_cache;
 use crate::gdt::TSS;
 use crate::keyboard::KEYBOARD;
 use crate::mouse::MOUSE;
-use crate::multitask;
 use crate::multitask::PROCESS_SCHEDULAR;
 use crate::pic::PicFunctions;
 use crate::pic::PICS;
-use crate::pit::PIT;
+use crate::pit;
 use crate::print_serial;
-use crate::uart::CONSOLE;
+use crate::uart::{self, CONSOLE};
 use core::arch::asm;
 use core::mem::size_of;
 use x86_64::addr::VirtAddr;
@@ -147,6 +147,10 @@ pub static mut old_process: IretStack = IretStack::new();
 #[no_mangle]
 pub static mut new_process_rsp: u64 = 0;
 
+// Number of times each vector has been triggered (shown within /proc/interrupts)
+static mut INTERRUPT_COUNTS: [u64; IDT_MAX_DESCRIPTIONS as usize] =
+    [0; IDT_MAX_DESCRIPTIONS as usize];
+
 impl idt_entry {
     pub fn edit_entry(
         vector: usize,
@@ -198,6 +202,7 @@ impl IretStack {
 pub extern "C" fn exception_handler(registers: Registers) {
     let unaligned_error_code = core::ptr::addr_of!(registers.error_code); // Packed structs must be aligned properly
     let aligned_error_code = unsafe { core::ptr::read_unaligned(unaligned_error_code) };
+    count_interrupt(registers.num);
 
     // Print a suitable error messages
     match registers.num {
@@ -217,9 +222,11 @@ pub extern "C" fn exception_handler(registers: Registers) {
 #[no_mangle]
 pub extern "C" fn interrupt_handler(registers: Registers) {
     PICS.lock().acknowledge(registers.num as u8); // To allow further interrupts, an acknowledgement must be sent
+    count_interrupt(registers.num);
 
     match registers.num {
         0x21 => KEYBOARD.lock().handle_keyboard(), // Keyboard
+        0x23 | 0x24 => uart::handle_interrupt(registers.num as u8 - 0x20), // Serial ports
         0x2c => {
             MOUSE.lock().handle_mouse_interrupt();
             MOUSE.free();
@@ -232,7 +239,10 @@ pub extern "C" fn interrupt_handler(registers: Registers) {
 pub extern "C" fn pit_handler() {
     // Acknowledge interrupt and timer
     PICS.lock().acknowledge(0x20);
+    count_interrupt(0x20);
     // PIT.lock().handle_timer();
+    pit::tick();
+    buffer_cache::tick();
 
     // print_serial!("PIT INTERRUPT\n");
 
@@ -240,11 +250,15 @@ pub extern "C" fn pit_handler() {
         let new_stack = PROCESS_SCHEDULAR.lock().schedule_process(old_process.rsp);
         PROCESS_SCHEDULAR.free();
 
-        // Update TSS to have a clean stack when coming from user to kernel
-        TSS.privilege_stack_table[0] = VirtAddr::new(multitask::KERNEL_STACK as u64);
-
         new_process_rsp = new_stack.unwrap() as u64;
 
+        // Update TSS to have a clean stack (owned by the thread) when coming from user to kernel
+        let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+        PROCESS_SCHEDULAR.free();
+        if let Some(thread) = current_thread {
+            TSS.privilege_stack_table[0] = VirtAddr::new(thread.kernel_stack);
+        }
+
         // Get current process and check
         let current_process = PROCESS_SCHEDULAR.lock().get_current_process();
         PROCESS_SCHEDULAR.free();
@@ -256,6 +270,17 @@ pub extern "C" fn pit_handler() {
     }
 }
 
+// Interrupts are disabled within handlers so the counts can't be changed part way through
+pub fn count_interrupt(vector: u64) {
+    unsafe {
+        INTERRUPT_COUNTS[vector as usize % IDT_MAX_DESCRIPTIONS as usize] += 1;
+    }
+}
+
+pub fn get_interrupt_count(vector: usize) -> u64 {
+    unsafe { INTERRUPT_COUNTS[vector] }
+}
+
 pub extern "C" fn enable() {
     unsafe {
         asm!("sti");
@@ -483,6 +508,18 @@ pub fn init() {
         GateType::Interrupt,
         PrivilegeLevel::Ring3,
     ); // PS2 Keyboard
+    idt_entry::edit_entry(
+        0x23,
+        handle_interrupt35,
+        GateType::Interrupt,
+        PrivilegeLevel::Ring3,
+    ); // COM2 and COM4
+    idt_entry::edit_entry(
+        0x24,
+        handle_interrupt36,
+        GateType::Interrupt,
+        PrivilegeLevel::Ring3,
+    ); // COM1 and COM3
     idt_entry::edit_entry(
         0x2c,
         handle_interrupt44,
@@ -540,6 +577,8 @@ extern "C" {
     fn handle_no_err_exception31();
     fn handle_pit_interrupt(); // Timer
     fn handle_interrupt33(); // PPS2 Keyboard
+    fn handle_interrupt35(); // COM2 and COM4
+    fn handle_interrupt36(); // COM1 and COM3
     fn handle_interrupt44(); // PS2 Mouse
     fn handle_syscall(); // Syscalls
     fn idt_flush();
@@ -177,4 +177,6 @@ handle_err_exception 30
 handle_no_err_exception 31
 
 handle_interrupt 33
+handle_interrupt 35
+handle_interrupt 36
 handle_interrupt 44 
\ No newline at end of file
@@ -5,22 +5,26 @@
     Accepts commands and sends scancodes which comply to a scancode set
     Scancode is simply a byte and scan code set is map between ascii characters and bytes sent
     Scanset 1 is used
+    Characters typed are also given to the console tty, with control held giving control characters (such as ^C)
 */
 
 use crate::framebuffer::WINDOW_MANAGER;
 use crate::print_serial;
 use crate::ps2;
+use crate::tty;
 use crate::CONSOLE;
 use spin::Mutex;
 
 pub struct Keyboard {
     is_upper: bool,
+    is_ctrl: bool,
     kbd_us: &'static [char; 0x3A],
     scancode_set: ScancodeSet,
 }
 
 pub static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard {
     is_upper: false,
+    is_ctrl: false,
     kbd_us: &[
         '\0', '\0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', '\0', '\t', 'q',
         'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\n', '\0', 'a', 's', 'd', 'f', 'g',
@@ -69,8 +73,16 @@ impl Keyboard {
         if !ps2::ps2_is_from_mouse() {
             let scancode = ps2::ps2_read(0x60).unwrap();
 
+            // Control is tracked without stopping its scancode being seen
+            match scancode {
+                0x1D => self.is_ctrl = true,  // Left control pressed
+                0x9D => self.is_ctrl = false, // Left control released
+                _ => {}
+            }
+
             match scancode {
                 0x26 => {
+                    queue_input(self.input_byte(scancode, self.translate(scancode, self.is_upper)));
                     WINDOW_MANAGER.lock().handle_keyboard('l', 0x26);
                     WINDOW_MANAGER.free();
                 }
@@ -88,6 +100,7 @@ impl Keyboard {
 
                     // Check for letter or enter key
                     if scancode == 0x1c || letter != '0' {
+                        queue_input(self.input_byte(scancode, letter));
                         WINDOW_MANAGER.lock().handle_keyboard(letter, scancode);
                         WINDOW_MANAGER.free();
                     }
@@ -96,6 +109,17 @@ impl Keyboard {
         }
     }
 
+    // Gives the byte a key sends to the tty (None for keys without a character)
+    fn input_byte(&self, scancode: u8, letter: char) -> Option<u8> {
+        match scancode {
+            0x1c => Some(b'\n'), // Enter
+            0x0E => Some(0x7f),  // Backspace sends DEL, the erase character of the tty
+            _ if letter == '\0' => None,
+            _ if self.is_ctrl && letter as u8 >= 0x40 => Some(letter as u8 & 0x1f),
+            _ => Some(letter as u8),
+        }
+    }
+
     // Enables keyboard
     fn enable_scanning(&self) {
         ps2::ps2_write_device(0, 0xF4).unwrap();
@@ -123,3 +147,9 @@ impl Keyboard {
         };
     }
 }
+
+fn queue_input(byte: Option<u8>) {
+    if let Some(byte) = byte {
+        tty::receive(tty::CONSOLE_TTY, byte);
+    }
+}
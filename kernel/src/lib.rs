@@ -5,12 +5,20 @@
 #![feature(const_option)]
 
 mod allocator;
+mod ata;
+mod block;
+mod buffer_cache;
+mod char_device;
+mod devfs;
 mod elf;
+mod ext2;
 mod framebuffer;
 mod fs;
+mod futex;
 mod gdt;
 mod grub;
 mod hashmap;
+mod initrd;
 mod interrupts;
 mod keyboard;
 mod list;
@@ -18,15 +26,26 @@ mod mouse;
 mod multitask;
 mod page_frame_allocator;
 mod paging;
+mod path;
+mod pci;
 mod pic;
 mod pit;
 mod ports;
+mod procfs;
 mod ps2;
+mod pty;
+mod ring_buffer;
+mod signal;
 mod spinlock;
 mod string;
 mod syscalls;
+mod tmpfs;
+mod tty;
 mod uart;
+mod vfs;
 mod vga_text;
+mod virtio;
+mod virtio_blk;
 mod writer;
 
 extern crate multiboot2;
@@ -40,7 +59,7 @@ use crate::pit::PIT;
 use crate::uart::CONSOLE;
 use core::arch::asm;
 use core::panic::PanicInfo;
-use multiboot2::load;
+use multiboot2::{load, FramebufferType};
 
 #[no_mangle]
 pub extern "C" fn rust_main(multiboot_information_address: usize) {
@@ -58,11 +77,37 @@ pub extern "C" fn rust_main(multiboot_information_address: usize) {
     interrupts::init();
     PICS.lock().init();
 
-    grub::bga_set_video_mode();
+    /*
+        Graphics use the linear framebuffer which grub set up (through VBE), with BGA setting the mode when it's present
+        Otherwise (no framebuffer or grub left the screen in text mode) VGA text mode becomes the kernel console
+    */
+    match boot_info.framebuffer_tag() {
+        Some(tag) if matches!(tag.buffer_type, FramebufferType::RGB { .. }) => {
+            if grub::is_bga_available() {
+                grub::bga_set_video_mode();
+            }
+            framebuffer::init(tag);
+        }
+        _ => {
+            vga_text::init();
+            print_serial!("NO FRAMEBUFFER, USING VGA TEXT MODE\n");
+        }
+    }
 
-    framebuffer::init(boot_info.framebuffer_tag().unwrap());
+    multitask::init();
 
+    ata::init();
+    pci::register_driver(&virtio_blk::VIRTIO_BLK_DRIVER);
+    pci::init();
     grub::initialise_userland(&boot_info);
+    tty::init();
+    char_device::init();
+    pty::init();
+    uart::init_ttys();
+
+    // Disks are found first so a real disk is used over the ramdisk (unless the initial ramdisk is already the root)
+    vfs::init();
+    grub::run_programs(&boot_info);
 
     print_serial!("Execution Finished\n");
 
@@ -74,6 +119,11 @@ pub extern "C" fn rust_main(multiboot_information_address: usize) {
 #[panic_handler] // This function is called on panic.
 #[no_mangle]
 fn panic(info: &PanicInfo) -> ! {
-    print_serial!("Error: {}", info);
+    use core::fmt::Write;
+    CONSOLE
+        .lock()
+        .write_fmt(format_args!("Error: {}", info))
+        .unwrap();
+    vga_text::print_panic(format_args!("Error: {}", info));
     loop {}
 }
@@ -120,10 +120,12 @@ impl Mouse {
             self.mouse_y = self.mouse_y.wrapping_add(adjusted_y as u64);
         }
 
-        WINDOW_MANAGER
-            .lock()
-            .handle_mouse(self.mouse_x, self.mouse_y, is_left_clicked);
-        WINDOW_MANAGER.free();
+        if framebuffer::is_available() {
+            WINDOW_MANAGER
+                .lock()
+                .handle_mouse(self.mouse_x, self.mouse_y, is_left_clicked);
+            WINDOW_MANAGER.free();
+        }
     }
 
     fn enable_scanning(&self) {
@@ -8,13 +8,22 @@
     Communication link must exist between 2 processes like buffering, synchronisation,
 */
 
-use crate::allocator::kmalloc;
+/*
+    Threads are the unit which is actually scheduled and hold their own registers and kernel stack
+    Processes group threads together and own the resources they share (address space, file descriptors, messages)
+    Kernel threads belong to no process, run in ring 0 within the kernel address space and are used for deferred work
+*/
+
+use crate::allocator::{kfree, kmalloc};
 use crate::list::Stack;
 use crate::page_frame_allocator::{FrameAllocator, PAGE_FRAME_ALLOCATOR, PAGE_SIZE};
 use crate::paging::Table;
+use crate::path::Path;
 use crate::spinlock::Lock;
+use crate::syscalls::FileTable;
 use crate::CONSOLE;
 use crate::{paging, print_serial};
+use core::arch::asm;
 use core::mem::size_of;
 use core::prelude::v1::Some;
 
@@ -26,10 +35,56 @@ use core::prelude::v1::Some;
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Process {
     pub pid: u64,
-    pub rsp: *const u64,
     pub process_priority: ProcessPriority,
     pub cr3: *mut Table,
     pub messages: Stack<Message>,
+    pub file_table: *mut FileTable,
+    pub cwd: *mut Path, // Current working directory which relative paths are resolved against (kept outside so processes stay small)
+    pub credentials: Credentials,
+    pub umask: u32, // Permission bits which are removed from the mode of files the process creates
+    pub stopped: bool, // Set by a stop signal (such as ^Z) so none of its threads are picked until it is continued
+}
+
+/*
+    Users and groups which a process acts as (0 is root, which passes every permission check)
+    Real ids are who started the process whilst effective ids are used for permission checks
+    Saved ids let a process which has dropped its privileges switch back to them
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub uid: u16,
+    pub euid: u16,
+    pub suid: u16,
+    pub gid: u16,
+    pub egid: u16,
+    pub sgid: u16,
+}
+
+impl Credentials {
+    pub const fn root() -> Credentials {
+        Credentials {
+            uid: 0,
+            euid: 0,
+            suid: 0,
+            gid: 0,
+            egid: 0,
+            sgid: 0,
+        }
+    }
+}
+
+/*
+    Threads are a single flow of execution within a process (or the kernel)
+    Each thread has it's own saved registers (stored upon it's stack) and kernel stack
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Thread {
+    pub tid: u64,
+    pub pid: Option<u64>, // Kernel threads have no owning process
+    pub rsp: *const u64,
+    pub kernel_stack: u64,
+    kernel_stack_allocation: *mut u64, // Start of the memory holding the kernel stack (which is aligned within it)
+    pub state: ThreadState,
 }
 
 /*
@@ -65,102 +120,261 @@ pub enum ProcessPriority {
     Low,
 }
 
+/*
+    Blocked threads are skipped by the schedular until another thread wakes them
+    Exited threads are removed by the schedular once it switches away from them
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ThreadState {
+    Ready,
+    Blocked,
+    Exited,
+}
+
+/*
+    Wait queues hold the tids of threads which are blocked until an event occurs (eg a futex being released)
+    Threads are woken in the order they started waiting
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WaitQueue {
+    waiters: Stack<u64>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue {
+            waiters: Stack::<u64>::new(),
+        }
+    }
+
+    pub fn push(&mut self, tid: u64) {
+        self.waiters.push(tid);
+    }
+
+    // Removes the longest waiting thread from the queue and returns its tid
+    pub fn pop(&mut self) -> Option<u64> {
+        if self.waiters.is_empty() {
+            return None;
+        }
+        let node = self.waiters.remove_at(self.waiters.length - 1);
+        Some(unsafe { (*node).payload })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+}
+
 pub const MAX_PROCESS_NUM: usize = PAGE_SIZE / size_of::<Process>();
-pub static USER_PROCESS_START_ADDRESS: u64 = 0x5000000;
+pub const MAX_THREAD_NUM: usize = PAGE_SIZE / size_of::<Thread>();
+const KERNEL_STACK_SIZE: u64 = 4 * PAGE_SIZE as u64;
+const USER_STACK_SIZE: u64 = 8 * PAGE_SIZE as u64;
+const DEFAULT_UMASK: u32 = 0o022;
+
+pub const TOO_MANY_THREADS: &str = "Too many threads";
 
-// Processes schedular holds all tasks and decides which will be serviced
+const KERNEL_CODE_SELECTOR: u64 = 0x08;
+const KERNEL_DATA_SELECTOR: u64 = 0x10;
+const USER_CODE_SELECTOR: u64 = 0x18 | 0x3;
+const USER_DATA_SELECTOR: u64 = 0x20 | 0x3;
+
+// Processes schedular holds all threads (and the processes they belong to) and decides which will be serviced
 pub struct ProcessSchedular {
-    pub tasks: [Option<Process>; MAX_PROCESS_NUM],
+    pub tasks: [Option<Thread>; MAX_THREAD_NUM],
+    pub processes: [Option<Process>; MAX_PROCESS_NUM],
     is_from_kernel: bool,
-    process_count: usize,
-    pub current_process_index: usize,
+    tid_counter: u64,
+    pub current_thread_index: usize,
+    reaped_stack: Option<*mut u64>, // Kernel stack of the last exited thread, which is freed on the next switch
 }
 
 pub static mut KERNEL_STACK: u64 = 0;
 
+// Address space used by kernel threads which is the P4 set up at boot
+static mut KERNEL_P4: u64 = 0;
+
 impl ProcessSchedular {
     pub const fn new() -> ProcessSchedular {
         ProcessSchedular {
-            tasks: [None; MAX_PROCESS_NUM],
+            tasks: [None; MAX_THREAD_NUM],
+            processes: [None; MAX_PROCESS_NUM],
             is_from_kernel: true,
-            process_count: 0,
-            current_process_index: 0,
+            tid_counter: 0,
+            current_thread_index: 0,
+            reaped_stack: None,
         }
     }
 
     /*
-        Round robin in which there is a single queue
-        When timer interrupt is triggered the next process is selected
+        Round robin in which there is a single queue of threads
+        When timer interrupt is triggered the next thread is selected
     */
     pub fn schedule_process(&mut self, mut old_rsp: u64) -> Option<*const u64> {
         // print_serial!("Picking new process\n");
-        if self.tasks[self.current_process_index].is_none() {
+        if self.tasks.iter().all(|task| task.is_none()) {
             return None;
         }
 
+        // This runs upon the kernel stack of a different thread to the one which exited, so its stack is no longer in use
+        if let Some(stack) = self.reaped_stack.take() {
+            kfree(stack);
+        }
+
         if self.is_from_kernel == true {
-            // If this is the first process to be called, it stems from kernel and that stack need not be saved as it's not a usermode process
+            // If this is the first thread to be called, it stems from kernel and that stack need not be saved as it's not a usermode process
             unsafe {
                 KERNEL_STACK = old_rsp;
             }
             self.is_from_kernel = false;
         } else {
             // TODO: Find more efficient way
-            // Save the old RSP into the process but adjust the value as certain values are pushed
+            // Save the old RSP into the thread but adjust the value as certain values are pushed
             // print_serial!("OLD RSP 0x{:x}\n", old_rsp);
             old_rsp -= 168;
-            let updated_process = Process {
-                rsp: old_rsp as *const _,
-                ..self.tasks[self.current_process_index].unwrap()
-            };
-            self.tasks[self.current_process_index] = Some(updated_process);
+            if let Some(mut thread) = self.tasks[self.current_thread_index] {
+                if thread.state == ThreadState::Exited {
+                    // Nothing will return to this thread so it can be dropped
+                    // The interrupt handler is still running upon its kernel stack, so that is only freed after switching away
+                    self.reaped_stack = Some(thread.kernel_stack_allocation);
+                    self.tasks[self.current_thread_index] = None;
+                } else {
+                    thread.rsp = old_rsp as *const _;
+                    self.tasks[self.current_thread_index] = Some(thread);
+                }
+            }
             // print_serial!(
-            //     "Saving process {}, RSP = 0x{:x}\n",
-            //     self.current_process_index,
+            //     "Saving thread {}, RSP = 0x{:x}\n",
+            //     self.current_thread_index,
             //     old_rsp
             // );
-            self.current_process_index += 1;
+            self.current_thread_index += 1;
         }
 
-        // Select next process and ensure it's not empty
-        let mut current_task = self.tasks[self.current_process_index];
-        if current_task.is_none() {
-            self.current_process_index = 0;
-            current_task = self.tasks[self.current_process_index];
+        /*
+            Select next thread which is ready, wrapping round to the start when the end is reached
+            If every thread is blocked, one is picked regardless as blocked threads halt until they are woken
+            Threads of stopped processes are never picked
+        */
+        let mut fallback = None;
+        for i in 0..MAX_THREAD_NUM {
+            let index = (self.current_thread_index + i) % MAX_THREAD_NUM;
+            match self.tasks[index] {
+                Some(thread) if self.is_stopped(thread.pid) => {}
+                Some(thread) if thread.state == ThreadState::Ready => {
+                    fallback = Some(index);
+                    break;
+                }
+                Some(thread) if thread.state == ThreadState::Blocked && fallback.is_none() => {
+                    fallback = Some(index)
+                }
+                _ => {}
+            }
         }
 
-        // print_serial!("Picked task {}\n", self.current_process_index);
+        self.current_thread_index = fallback?;
 
-        return Some(current_task.unwrap().rsp);
-    }
+        // print_serial!("Picked task {}\n", self.current_thread_index);
 
-    pub fn add_process(&mut self, process: Process) {
-        assert!(self.process_count < MAX_PROCESS_NUM, "Memory maxed");
-        self.tasks[self.process_count] = Some(process);
-        self.process_count += 1;
+        Some(self.tasks[self.current_thread_index].unwrap().rsp)
     }
 
-    pub fn remove_process(&mut self, mut index: usize) {
-        // Remove index
-        self.tasks[index] = None;
+    // Adds a process along with the thread which starts it
+    pub fn add_process(&mut self, process: Process, main_thread: Thread) {
+        let index = self
+            .processes
+            .iter()
+            .position(|slot| slot.is_none())
+            .expect("Memory maxed");
+        self.processes[index] = Some(process);
+        self.add_thread(main_thread).expect("Memory maxed");
+    }
 
-        // Shift all further processes back one place
-        if index + 1 < MAX_PROCESS_NUM {
-            index += 1;
+    // Gives the thread a tid and adds it to the threads which can be scheduled
+    pub fn add_thread(&mut self, mut thread: Thread) -> Result<u64, &'static str> {
+        let index = self
+            .tasks
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(TOO_MANY_THREADS)?;
+        thread.tid = self.tid_counter;
+        self.tid_counter += 1;
+        self.tasks[index] = Some(thread);
+        Ok(thread.tid)
+    }
 
-            let mut current_task = self.tasks[index];
-            while current_task != None {
-                index += 1;
-                current_task = self.tasks[index];
-                self.tasks[index - 1] = current_task.clone();
+    /*
+        Removes a process along with all of it's threads
+        The current thread can't be removed straight away as it's still running, so it's marked as exited
+    */
+    pub fn remove_process(&mut self, pid: u64) {
+        for slot in self.processes.iter_mut() {
+            if let Some(process) = slot.filter(|process| process.pid == pid) {
+                kfree(process.file_table as *mut u64);
+                kfree(process.cwd as *mut u64);
+                *slot = None;
             }
+        }
 
-            self.tasks[index] = None;
+        for (i, slot) in self.tasks.iter_mut().enumerate() {
+            if let Some(thread) = slot {
+                if thread.pid == Some(pid) {
+                    if i == self.current_thread_index {
+                        thread.state = ThreadState::Exited;
+                    } else {
+                        thread.free_kernel_stack();
+                        *slot = None;
+                    }
+                }
+            }
         }
     }
 
+    pub fn get_current_thread(&self) -> Option<Thread> {
+        self.tasks[self.current_thread_index]
+    }
+
+    // Returns the process which owns the current thread (kernel threads have none)
     pub fn get_current_process(&self) -> Option<Process> {
-        self.tasks[self.current_process_index]
+        self.get_process(self.get_current_thread()?.pid?)
+    }
+
+    fn is_stopped(&self, pid: Option<u64>) -> bool {
+        pid.and_then(|pid| self.get_process(pid))
+            .is_some_and(|process| process.stopped)
+    }
+
+    pub fn get_process(&self, pid: u64) -> Option<Process> {
+        for process in self.processes.iter().flatten() {
+            if process.pid == pid {
+                return Some(*process);
+            }
+        }
+        None
+    }
+
+    pub fn update_process(&mut self, updated_process: Process) {
+        for slot in self.processes.iter_mut() {
+            if slot.is_some_and(|process| process.pid == updated_process.pid) {
+                *slot = Some(updated_process);
+            }
+        }
+    }
+
+    pub fn get_thread_state(&self, tid: u64) -> Option<ThreadState> {
+        for thread in self.tasks.iter().flatten() {
+            if thread.tid == tid {
+                return Some(thread.state);
+            }
+        }
+        None
+    }
+
+    pub fn set_thread_state(&mut self, tid: u64, state: ThreadState) {
+        for thread in self.tasks.iter_mut().flatten() {
+            if thread.tid == tid {
+                thread.state = state;
+            }
+        }
     }
 
     /*
@@ -169,31 +383,52 @@ impl ProcessSchedular {
     */
     pub fn send_message(&mut self, pid: u64, message_contents: &'static str, return_pid: u64) {
         // Get the process
-        for i in 0..MAX_PROCESS_NUM {
-            if i == (pid as usize) {
-                let mut messages = self.tasks[i].unwrap().messages;
-                messages.push(Message::new(message_contents, return_pid));
-                let updated_process = Process {
-                    messages,
-                    ..self.tasks[i].unwrap()
-                };
-                self.tasks[i] = Some(updated_process);
-            }
+        if let Some(mut process) = self.get_process(pid) {
+            process
+                .messages
+                .push(Message::new(message_contents, return_pid));
+            self.update_process(process);
         }
     }
 }
 
 impl Process {
-    // The entrypoint for each process is 0x800000 which has already been mapped into memory
     pub fn init(process_priority: ProcessPriority, pid: u64) -> Process {
-        let v_address =
-            unsafe { USER_PROCESS_START_ADDRESS + (0x1000000 * crate::grub::process_index) };
-
         // Copy current address space by creating a new P4
         let new_p4: *mut Table = paging::deep_clone();
 
-        // Create and setup a stack as though an interrupt has been fired
-        let mut rsp = kmalloc(8 * crate::page_frame_allocator::PAGE_SIZE as u64);
+        // Each process starts with it's own file table
+        let file_table = kmalloc(size_of::<FileTable>() as u64) as *mut FileTable;
+        unsafe {
+            *file_table = FileTable::new();
+        }
+
+        // Working directory is inherited from the process which created this one (or is / when the kernel did)
+        let parent = PROCESS_SCHEDULAR.lock().get_current_process();
+        PROCESS_SCHEDULAR.free();
+        let cwd = kmalloc(size_of::<Path>() as u64) as *mut Path;
+        unsafe {
+            *cwd = parent.map_or(Path::root(), |parent| *parent.cwd);
+        }
+
+        print_serial!("P4 ADDRESS = {:p}\n", new_p4);
+
+        Process {
+            pid,
+            process_priority,
+            cr3: new_p4,
+            messages: Stack::<Message>::new(),
+            file_table,
+            cwd,
+            credentials: Credentials::root(),
+            umask: DEFAULT_UMASK,
+            stopped: false,
+        }
+    }
+
+    // Entry point comes from the ELF header of the program, which has already been mapped into memory
+    pub fn create_main_thread(&self, entry: u64) -> Thread {
+        let user_stack = kmalloc(USER_STACK_SIZE) as u64;
 
         // Test argc and argv
         // let arguments = ["hey\0", "there\0"];
@@ -218,28 +453,119 @@ impl Process {
         //     *argv.offset(1) = string_locations.offset(4) as u64;
         // }
 
+        Thread::init_user(self, entry, (user_stack + USER_STACK_SIZE) & !0xF, 0)
+    }
+
+    /*
+        Recieve a message from another task and pop it off the stack of messages to analyse
+    */
+    pub fn receive_message(&mut self) -> Option<Message> {
+        // Get message or none
+        unsafe {
+            if self.messages.length > 0 {
+                let mut messages = self.messages;
+                let message = (*messages.pop()).payload;
+
+                let updated_process = Process { messages, ..*self };
+
+                PROCESS_SCHEDULAR.lock().update_process(updated_process);
+                PROCESS_SCHEDULAR.free();
+
+                Some(message)
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn analyse_message(&mut self, message: &Message) {
+        match message.command {
+            "test" => print_serial!("Message sent successfully\n"),
+            _ => print_serial!("Unknown message sent"),
+        }
+    }
+}
+
+// Registers which a new thread starts with, placed upon its kernel stack as though it had been interrupted
+struct InitialRegisters {
+    cr3: u64,
+    rip: u64,
+    rsp: u64,
+    rdi: u64,
+    cs: u64,
+    ss: u64,
+    rflags: u64,
+}
+
+impl Thread {
+    // Creates a usermode thread within a process which starts at entry with the given stack, passing arg in RDI
+    pub fn init_user(process: &Process, entry: u64, stack_top: u64, arg: u64) -> Thread {
+        Thread::init(
+            Some(process.pid),
+            InitialRegisters {
+                cr3: process.cr3 as u64,
+                rip: entry,
+                rsp: stack_top,
+                rdi: arg,
+                cs: USER_CODE_SELECTOR,
+                ss: USER_DATA_SELECTOR,
+                rflags: 0x202, // Interrupts enabled
+            },
+        )
+    }
+
+    /*
+        Creates a kernel thread which runs in ring 0 within the kernel address space
+        Spinlocks within the kernel can't be held across a switch, so kernel threads run with interrupts disabled and only give up the CPU when they block
+    */
+    pub fn init_kernel(entry: extern "C" fn() -> !) -> Thread {
+        let thread = Thread::init(
+            None,
+            InitialRegisters {
+                cr3: unsafe { KERNEL_P4 },
+                rip: entry as usize as u64,
+                rsp: 0,
+                rdi: 0,
+                cs: KERNEL_CODE_SELECTOR,
+                ss: KERNEL_DATA_SELECTOR,
+                rflags: 0x002, // Interrupts disabled
+            },
+        );
+
+        // Kernel threads run upon their kernel stack so the saved RSP is patched (leaving space as though entry was called)
         unsafe {
-            print_serial!("RSP = {:p} 0x{:x}\n", rsp, rsp.offset(4095) as u64);
-            print_serial!("P4 ADDRESS = {:p}\n", new_p4);
+            let frame = thread.rsp as *mut u64;
+            *frame.offset(19) = thread.kernel_stack - 8;
+        }
+        thread
+    }
+
+    fn init(pid: Option<u64>, registers: InitialRegisters) -> Thread {
+        // Each thread has its own kernel stack so a thread blocked within a syscall keeps its state
+        let kernel_stack_allocation = kmalloc(KERNEL_STACK_SIZE);
+        let kernel_stack = (kernel_stack_allocation as u64 + KERNEL_STACK_SIZE) & !0xF;
+
+        // Setup the kernel stack as though an interrupt has been fired, so the first switch to this thread simply returns into it
+        let mut rsp = kernel_stack as *mut u64;
 
-            rsp = rsp.offset(4095);
-            let stack_top = rsp as u64;
+        unsafe {
+            print_serial!("RSP = {:p} 0x{:x}\n", rsp, registers.rsp);
 
             /*
                When interrupt is called certain registers are pushed as follows: SS -> RSP -> RFLAGS -> CS -> RIP
                These registers are then pushed: RAX -> RBX -> RBC -> RDX -> RSI -> RDI
             */
-            *rsp.offset(-1) = 0x20 | 0x3; // SS
-            *rsp.offset(-2) = stack_top; // RSP
-            *rsp.offset(-3) = 0x202; // RFLAGS which enable interrupts
-            *rsp.offset(-4) = 0x18 | 0x3; // CS
-            *rsp.offset(-5) = v_address; // RIP
+            *rsp.offset(-1) = registers.ss; // SS
+            *rsp.offset(-2) = registers.rsp; // RSP
+            *rsp.offset(-3) = registers.rflags; // RFLAGS
+            *rsp.offset(-4) = registers.cs; // CS
+            *rsp.offset(-5) = registers.rip; // RIP
             *rsp.offset(-6) = 0x00; // RAX
             *rsp.offset(-7) = 0x00; // RBX
             *rsp.offset(-8) = 0x00; // RCX
             *rsp.offset(-9) = 0x00; // RDX
             *rsp.offset(-10) = 0; // RBP
-            *rsp.offset(-11) = 0; // RDI (argv)
+            *rsp.offset(-11) = registers.rdi; // RDI (argv)
             *rsp.offset(-12) = 0; // RSI (argc)
             *rsp.offset(-13) = 0; // R8
             *rsp.offset(-14) = 0; // R8
@@ -249,50 +575,132 @@ impl Process {
             *rsp.offset(-18) = 0; // R12
             *rsp.offset(-19) = 0; // R14
             *rsp.offset(-20) = 0; // R15
-            *rsp.offset(-21) = new_p4 as u64; // CR3
+            *rsp.offset(-21) = registers.cr3; // CR3
             rsp = rsp.offset(-21);
         }
 
-        Process {
-            pid: pid,
-            rsp: rsp,
-            process_priority: process_priority,
-            cr3: new_p4,
-            messages: Stack::<Message>::new(),
+        Thread {
+            tid: 0, // Assigned by the schedular
+            pid,
+            rsp,
+            kernel_stack,
+            kernel_stack_allocation,
+            state: ThreadState::Ready,
         }
     }
 
-    /*
-        Recieve a message from another task and pop it off the stack of messages to analyse
-    */
-    pub fn receive_message(&mut self) -> Option<Message> {
-        // Get message or none
-        unsafe {
-            if self.messages.length > 0 {
-                let mut messages = self.messages;
-                let message = (*messages.pop()).payload;
+    // Only for threads which will never run again (such as those which couldn't be added to the schedular)
+    pub fn free_kernel_stack(&self) {
+        kfree(self.kernel_stack_allocation);
+    }
+}
 
-                let updated_process = Process {
-                    messages,
-                    ..self.clone()
-                };
+pub static PROCESS_SCHEDULAR: Lock<ProcessSchedular> = Lock::new(ProcessSchedular::new());
 
-                PROCESS_SCHEDULAR.lock().tasks[self.pid as usize] = Some(updated_process);
-                PROCESS_SCHEDULAR.free();
+/*
+    Work which should happen outside of the current context (eg flushing the filesystem) is queued for the kernel worker thread
+    The worker sleeps whilst the queue is empty
+*/
+static WORK_QUEUE: Lock<Stack<fn()>> = Lock::new(Stack::<fn()>::new());
+static mut KWORKER_TID: Option<u64> = None;
 
-                Some(message)
-            } else {
+pub fn init() {
+    unsafe {
+        asm!("mov {}, cr3", out(reg) KERNEL_P4);
+    }
+
+    let tid = spawn_kernel_thread(kworker);
+    unsafe {
+        KWORKER_TID = Some(tid);
+    }
+}
+
+pub fn spawn_kernel_thread(entry: extern "C" fn() -> !) -> u64 {
+    let thread = Thread::init_kernel(entry);
+    let tid = PROCESS_SCHEDULAR.lock().add_thread(thread);
+    PROCESS_SCHEDULAR.free();
+    tid.expect(TOO_MANY_THREADS)
+}
+
+// Queues a function to be run later by the kernel worker thread
+pub fn schedule_work(work: fn()) {
+    WORK_QUEUE.lock().push(work);
+    WORK_QUEUE.free();
+
+    if let Some(tid) = unsafe { KWORKER_TID } {
+        wake_thread(tid);
+    }
+}
+
+extern "C" fn kworker() -> ! {
+    loop {
+        loop {
+            let queue = WORK_QUEUE.lock();
+            let work = if queue.is_empty() {
                 None
+            } else {
+                Some(unsafe { (*queue.remove_at(queue.length - 1)).payload })
+            };
+            WORK_QUEUE.free();
+
+            match work {
+                Some(work) => work(),
+                None => break,
             }
         }
+
+        block_current_thread();
     }
+}
 
-    pub fn analyse_message(&mut self, message: &Message) {
-        match message.command {
-            "test" => print_serial!("Message sent successfully\n"),
-            _ => print_serial!("Unknown message sent"),
+/*
+    Puts the current thread to sleep until another thread wakes it
+    Must be called with interrupts disabled (eg within a syscall) and every lock freed
+    Interrupts are enabled whilst halting so the PIT can switch to other threads
+*/
+pub fn block_current_thread() {
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+
+    let tid = match current_thread {
+        Some(thread) => thread.tid,
+        None => return,
+    };
+
+    PROCESS_SCHEDULAR
+        .lock()
+        .set_thread_state(tid, ThreadState::Blocked);
+    PROCESS_SCHEDULAR.free();
+
+    loop {
+        let state = PROCESS_SCHEDULAR.lock().get_thread_state(tid);
+        PROCESS_SCHEDULAR.free();
+
+        if state != Some(ThreadState::Blocked) {
+            break;
+        }
+
+        // sti only takes effect after the next instruction so an interrupt can't be missed before the hlt
+        unsafe {
+            asm!("sti", "hlt", "cli");
         }
     }
 }
 
-pub static PROCESS_SCHEDULAR: Lock<ProcessSchedular> = Lock::new(ProcessSchedular::new());
+// Marks a blocked thread as ready so the schedular will pick it again
+pub fn wake_thread(tid: u64) {
+    let schedular = PROCESS_SCHEDULAR.lock();
+    if schedular.get_thread_state(tid) == Some(ThreadState::Blocked) {
+        schedular.set_thread_state(tid, ThreadState::Ready);
+    }
+    PROCESS_SCHEDULAR.free();
+}
+
+// Halts the current thread forever once it has been marked as exited, until the schedular drops it
+pub fn wait_for_exit() -> ! {
+    loop {
+        unsafe {
+            asm!("sti", "hlt", "cli");
+        }
+    }
+}
@@ -12,10 +12,18 @@ use multiboot2::BootInformation;
 pub struct PageFrameAllocator {
     pub free_frames: Stack<u64>,
     pub current_page: u64,
+    memory_start: u64,
     memory_end: u64,
     page_count: u64,
 }
 
+// Amounts of physical memory in bytes
+pub struct MemoryStatistics {
+    pub total: u64,
+    pub used: u64,
+    pub free_frames: u64, // Frames which have been freed and are waiting to be reused
+}
+
 pub trait FrameAllocator {
     fn alloc_frame(&mut self) -> *mut u64;
     fn free_frame(&mut self, frame_address: *mut u64) -> ();
@@ -78,6 +86,7 @@ impl PageFrameAllocator {
         PageFrameAllocator {
             free_frames: Stack::<u64>::new(),
             current_page: 0,
+            memory_start: 0,
             memory_end: 0,
             page_count: 0,
         }
@@ -103,8 +112,19 @@ impl PageFrameAllocator {
         // TODO: Fix this fix - very large modules seem to confuse the multiboot2 package
 
         self.current_page = memory_start;
+        self.memory_start = memory_start;
         self.memory_end = memory_end;
     }
+
+    // Frames below the current page have been handed out, apart from those which have been freed since
+    pub fn statistics(&self) -> MemoryStatistics {
+        let free_frames = self.free_frames.length as u64;
+        MemoryStatistics {
+            total: self.memory_end - self.memory_start,
+            used: self.current_page - self.memory_start - free_frames * PAGE_SIZE as u64,
+            free_frames,
+        }
+    }
 }
 
 pub fn round_to_nearest_page(size: u64) -> u64 {
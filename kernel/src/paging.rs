@@ -145,6 +145,14 @@ impl Table {
 
 pub const P4: *mut Table = 0xffffffff_fffff000 as *mut _;
 
+// Entries within a P3/P2 which map a 1GB/2MB page rather than pointing to a table
+const HUGE_PAGE: u64 = 1 << 7;
+
+const USER_ACCESSIBLE: u64 = 1 << 2;
+
+// Addresses from here onwards are the higher half (or non canonical) which userland never owns
+const HIGHER_HALF: u64 = 0x0000_8000_0000_0000;
+
 // The index from the address is used to go to or create tables
 pub fn map_page(physical_address: u64, virtual_address: u64, is_user: bool) {
     assert!(
@@ -199,6 +207,40 @@ pub fn unmap_page(virtual_address: u64) {
     }
 }
 
+// Walks the current page tables to find the physical address which a virtual address is mapped to
+pub fn translate(virtual_address: u64) -> Option<u64> {
+    let p4 = unsafe { &mut *P4 };
+
+    let (p1_index, p2_index, p3_index, p4_index) = Table::get_indexes(virtual_address);
+
+    let p3 = p4.get_table(p4_index)?;
+    let p2 = p3.get_table(p3_index)?;
+    let p1 = p2.get_table(p2_index)?;
+
+    let page = p1.entries[p1_index];
+    if page.is_unused() {
+        return None;
+    }
+
+    Some(page.get_physical_address() as u64 + (virtual_address & 0xfff))
+}
+
+// Whether an address is mapped within the current address space and can be accessed from usermode
+pub fn is_user_address(virtual_address: u64) -> bool {
+    if virtual_address >= HIGHER_HALF {
+        return false;
+    }
+
+    let p4 = unsafe { &mut *P4 };
+
+    let (p1_index, p2_index, p3_index, p4_index) = Table::get_indexes(virtual_address);
+
+    p4.get_table(p4_index)
+        .and_then(|p3| p3.get_table(p3_index))
+        .and_then(|p2| p2.get_table(p2_index))
+        .is_some_and(|p1| p1.entries[p1_index].entry & USER_ACCESSIBLE != 0)
+}
+
 /*
     Identity maps a specified amount of megabytes from address 0
     Usage - identity_map(16) would identity map the first 16 MB of memory
@@ -217,6 +259,81 @@ pub fn identity_map_from(physical_address: u64, virtual_address: u64, megabytes:
     }
 }
 
+/*
+    Calls the function with each region of virtual memory mapped within an address space as (start, end, flags)
+    Neighbouring pages are joined into a single region whilst they have the same flags (the lowest 3 bits of the entry)
+    The recursive mapping isn't included
+*/
+pub fn for_each_region(p4: *mut Table, mut function: impl FnMut(u64, u64, u64)) {
+    let mut region: Option<(u64, u64, u64)> = None;
+    let mut add_page = |address: u64, size: u64, entry: u64| {
+        let flags = entry & 0b111;
+        region = match region {
+            Some((start, end, region_flags)) if end == address && region_flags == flags => {
+                Some((start, end + size, flags))
+            }
+            Some((start, end, region_flags)) => {
+                function(start, end, region_flags);
+                Some((address, address + size, flags))
+            }
+            None => Some((address, address + size, flags)),
+        };
+    };
+
+    unsafe {
+        let p4 = &mut *p4;
+        for i in 0..p4.entries.len() - 1 {
+            let p3 = match p4.get_table(i) {
+                Some(table) => table,
+                None => continue,
+            };
+
+            // Upper half addresses must be sign extended
+            let p4_address = match i {
+                0..=255 => (i as u64) << 39,
+                _ => 0xffff0000_00000000 | ((i as u64) << 39),
+            };
+
+            for j in 0..p3.entries.len() {
+                let p3_address = p4_address | ((j as u64) << 30);
+                let p3_entry = p3.entries[j].entry;
+                if p3_entry & 1 == 0 {
+                    continue;
+                }
+                if p3_entry & HUGE_PAGE != 0 {
+                    add_page(p3_address, 1 << 30, p3_entry);
+                    continue;
+                }
+
+                let p2 = p3.get_table(j).unwrap();
+                for k in 0..p2.entries.len() {
+                    let p2_address = p3_address | ((k as u64) << 21);
+                    let p2_entry = p2.entries[k].entry;
+                    if p2_entry & 1 == 0 {
+                        continue;
+                    }
+                    if p2_entry & HUGE_PAGE != 0 {
+                        add_page(p2_address, 1 << 21, p2_entry);
+                        continue;
+                    }
+
+                    let p1 = p2.get_table(k).unwrap();
+                    for l in 0..p1.entries.len() {
+                        let p1_entry = p1.entries[l].entry;
+                        if p1_entry & 1 != 0 {
+                            add_page(p2_address | ((l as u64) << 12), PAGE_SIZE as u64, p1_entry);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some((start, end, flags)) = region {
+        function(start, end, flags);
+    }
+}
+
 pub fn map_pages(number_of_pages: u64, physical_address: u64, virtual_address: u64) {
     for i in 0..number_of_pages {
         let p_address = physical_address + (i * 4096);
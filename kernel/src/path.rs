@@ -0,0 +1,79 @@
+// src/path.rs
+
+/*
+    Paths are stored as absolute, normalised strings (no ".", ".." or repeated /'s) within a fixed size buffer
+    Relative paths given by processes are resolved against the process's current working directory
+*/
+
+pub const MAX_PATH_LENGTH: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Path {
+    buffer: [u8; MAX_PATH_LENGTH],
+    length: usize,
+}
+
+impl Path {
+    pub const fn root() -> Path {
+        let mut buffer = [0; MAX_PATH_LENGTH];
+        buffer[0] = b'/';
+        Path { buffer, length: 1 }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buffer[0..self.length]).unwrap_or("/")
+    }
+
+    /*
+        Resolves a path relative to this one (absolute paths start again from the root)
+        ".." within the root directory stays within the root directory
+    */
+    pub fn join(&self, filepath: &str) -> Result<Path, &'static str> {
+        let mut path = match filepath.starts_with('/') {
+            true => Path::root(),
+            false => *self,
+        };
+
+        for component in filepath.split('/') {
+            match component {
+                "" | "." => continue,
+                ".." => path.pop(),
+                _ => path.push(component)?,
+            }
+        }
+
+        Ok(path)
+    }
+
+    fn push(&mut self, component: &str) -> Result<(), &'static str> {
+        let separator = match self.length {
+            1 => 0, // Root already ends with a /
+            _ => 1,
+        };
+
+        if self.length + separator + component.len() > MAX_PATH_LENGTH {
+            return Err("Path is too long");
+        }
+
+        if separator == 1 {
+            self.buffer[self.length] = b'/';
+            self.length += 1;
+        }
+        self.buffer[self.length..self.length + component.len()]
+            .copy_from_slice(component.as_bytes());
+        self.length += component.len();
+        Ok(())
+    }
+
+    // Removes the final component
+    fn pop(&mut self) {
+        while self.length > 1 && self.buffer[self.length - 1] != b'/' {
+            self.length -= 1;
+        }
+
+        // Remove the separator unless this is the root
+        if self.length > 1 {
+            self.length -= 1;
+        }
+    }
+}
@@ -0,0 +1,577 @@
+// src/pci.rs
+
+/*
+    PCI devices each have a 256 byte configuration space which describes the device (vendor, class, BARs, etc)
+    Configuration space is accessed through 2 IO ports: an address is written to 0xCF8 and the value is read/written at 0xCFC
+    Buses are scanned recursively, starting from bus 0 and following PCI-to-PCI bridges into the bus behind them
+    Base address registers (BARs) give the IO ports or physical memory which the device's registers are within
+    Drivers register which devices they handle (by vendor/device id or by class) and are bound to them once scanning is finished
+*/
+
+#![allow(dead_code)]
+
+use crate::page_frame_allocator::PAGE_SIZE;
+use crate::paging;
+use crate::ports::{inl, outl};
+use crate::spinlock::Lock;
+use crate::{print_serial, CONSOLE};
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+// Offsets of fields within the configuration space header
+const VENDOR_ID: u8 = 0x00;
+const DEVICE_ID: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+const STATUS: u8 = 0x06;
+const REVISION: u8 = 0x08;
+const PROG_IF: u8 = 0x09;
+const SUBCLASS: u8 = 0x0A;
+const CLASS: u8 = 0x0B;
+const HEADER_TYPE: u8 = 0x0E;
+const BAR0: u8 = 0x10;
+const SECONDARY_BUS: u8 = 0x19; // Only within bridge headers
+const CAPABILITIES_POINTER: u8 = 0x34;
+const INTERRUPT_LINE: u8 = 0x3C;
+
+const COMMAND_IO_SPACE: u16 = 0x01;
+const COMMAND_MEMORY_SPACE: u16 = 0x02;
+const COMMAND_BUS_MASTER: u16 = 0x04; // Allows the device to use DMA
+
+const STATUS_CAPABILITIES: u16 = 0x10; // Device has a linked list of capabilities
+
+const HEADER_TYPE_BRIDGE: u8 = 0x01;
+const HEADER_TYPE_MULTIFUNCTION: u8 = 0x80;
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+const CAPABILITY_MSI: u8 = 0x05;
+
+// MSI message control bits
+const MSI_ENABLE: u16 = 0x01;
+const MSI_64_BIT: u16 = 0x80;
+const MSI_PER_VECTOR_MASKING: u16 = 0x100;
+
+// Messages are written to the local APIC of the CPU which should receive the interrupt
+const MSI_ADDRESS_BASE: u32 = 0xFEE0_0000;
+
+const NO_DEVICE: u16 = 0xFFFF;
+
+const MAX_DEVICES: usize = 64;
+const MAX_DRIVERS: usize = 16;
+const BAR_COUNT: usize = 6;
+const BRIDGE_BAR_COUNT: usize = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BarType {
+    Io,
+    Memory32,
+    Memory64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bar {
+    pub bar_type: BarType,
+    pub address: u64, // Physical address (or IO port)
+    pub size: u64,
+    pub prefetchable: bool,
+}
+
+impl Bar {
+    /*
+        Identity maps the memory of the BAR so the registers can be accessed by the kernel
+        Returns the address which the registers are at
+    */
+    pub fn map(&self) -> Result<u64, &'static str> {
+        if self.bar_type == BarType::Io {
+            return Err("IO BARs are accessed through ports rather than memory");
+        }
+
+        let mut page = self.address & !(PAGE_SIZE as u64 - 1);
+        while page < self.address + self.size {
+            paging::map_page(page, page, false);
+            page += PAGE_SIZE as u64;
+        }
+        Ok(self.address)
+    }
+}
+
+// Message signalled interrupts are sent by the device writing to memory instead of using an interrupt pin
+#[derive(Copy, Clone, Debug)]
+pub struct Msi {
+    pub offset: u8, // Offset of the capability within the configuration space
+    pub is_64_bit: bool,
+    pub per_vector_masking: bool,
+    pub vector_count: u8,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    pub interrupt_line: u8,
+    pub bars: [Option<Bar>; BAR_COUNT],
+    pub msi: Option<Msi>,
+    depth: u8, // Number of bridges between this device and the host bridge
+    driver: Option<&'static str>, // Name of the driver which has been bound to the device
+}
+
+impl PciDevice {
+    fn new(bus: u8, device: u8, function: u8, depth: u8) -> Option<PciDevice> {
+        let vendor_id = read_config_16(bus, device, function, VENDOR_ID);
+        if vendor_id == NO_DEVICE {
+            return None;
+        }
+
+        let mut pci_device = PciDevice {
+            bus,
+            device,
+            function,
+            vendor_id,
+            device_id: read_config_16(bus, device, function, DEVICE_ID),
+            class: read_config_8(bus, device, function, CLASS),
+            subclass: read_config_8(bus, device, function, SUBCLASS),
+            prog_if: read_config_8(bus, device, function, PROG_IF),
+            revision: read_config_8(bus, device, function, REVISION),
+            header_type: read_config_8(bus, device, function, HEADER_TYPE),
+            interrupt_line: read_config_8(bus, device, function, INTERRUPT_LINE),
+            bars: [None; BAR_COUNT],
+            msi: None,
+            depth,
+            driver: None,
+        };
+
+        pci_device.decode_bars();
+        pci_device.msi = pci_device.find_msi();
+        Some(pci_device)
+    }
+
+    pub fn read_config_32(&self, offset: u8) -> u32 {
+        read_config_32(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write_config_32(&self, offset: u8, value: u32) {
+        write_config_32(self.bus, self.device, self.function, offset, value)
+    }
+
+    pub fn read_config_16(&self, offset: u8) -> u16 {
+        read_config_16(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write_config_16(&self, offset: u8, value: u16) {
+        write_config_16(self.bus, self.device, self.function, offset, value)
+    }
+
+    pub fn read_config_8(&self, offset: u8) -> u8 {
+        read_config_8(self.bus, self.device, self.function, offset)
+    }
+
+    // Raw value of a base address register (IO BARs have bit 0 set)
+    pub fn bar(&self, index: u8) -> u32 {
+        self.read_config_32(BAR0 + index * 4)
+    }
+
+    // Lets the device respond to IO/memory accesses and use DMA
+    pub fn enable_bus_mastering(&self) {
+        let command = self.read_config_16(COMMAND);
+        self.write_config_16(
+            COMMAND,
+            command | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER,
+        );
+    }
+
+    fn is_bridge(&self) -> bool {
+        self.header_type & 0x7F == HEADER_TYPE_BRIDGE
+            || (self.class == CLASS_BRIDGE && self.subclass == SUBCLASS_PCI_BRIDGE)
+    }
+
+    /*
+        The size of a BAR is found by writing all 1's to it and reading back which bits the device kept as 0
+        Decoding is turned off whilst doing this so the device doesn't respond at the temporary address
+    */
+    fn decode_bars(&mut self) {
+        let bar_count = match self.header_type & 0x7F {
+            0 => BAR_COUNT,
+            HEADER_TYPE_BRIDGE => BRIDGE_BAR_COUNT,
+            _ => 0,
+        };
+
+        let command = self.read_config_16(COMMAND);
+        self.write_config_16(
+            COMMAND,
+            command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE),
+        );
+
+        let mut index = 0;
+        while index < bar_count {
+            let offset = BAR0 + (index as u8) * 4;
+            let value = self.read_config_32(offset);
+            let mask = self.size_bar(offset);
+
+            if mask == 0 {
+                index += 1;
+                continue;
+            }
+
+            if value & 1 != 0 {
+                self.bars[index] = Some(Bar {
+                    bar_type: BarType::Io,
+                    address: (value & 0xFFFF_FFFC) as u64,
+                    size: ((!(mask & 0xFFFF_FFFC)).wrapping_add(1) & 0xFFFF) as u64,
+                    prefetchable: false,
+                });
+                index += 1;
+            } else if (value >> 1) & 0x3 == 0x2 && index + 1 < bar_count {
+                // 64 bit BARs use the following BAR for the top half of the address
+                let high_value = self.read_config_32(offset + 4);
+                let high_mask = self.size_bar(offset + 4);
+
+                let mask = ((high_mask as u64) << 32) | (mask & 0xFFFF_FFF0) as u64;
+                self.bars[index] = Some(Bar {
+                    bar_type: BarType::Memory64,
+                    address: ((high_value as u64) << 32) | (value & 0xFFFF_FFF0) as u64,
+                    size: (!mask).wrapping_add(1),
+                    prefetchable: value & 0x8 != 0,
+                });
+                index += 2;
+            } else {
+                self.bars[index] = Some(Bar {
+                    bar_type: BarType::Memory32,
+                    address: (value & 0xFFFF_FFF0) as u64,
+                    size: (!(mask & 0xFFFF_FFF0)).wrapping_add(1) as u64,
+                    prefetchable: value & 0x8 != 0,
+                });
+                index += 1;
+            }
+        }
+
+        self.write_config_16(COMMAND, command);
+    }
+
+    // Returns the bits of a BAR which the device lets be changed, leaving the BAR as it was
+    fn size_bar(&self, offset: u8) -> u32 {
+        let value = self.read_config_32(offset);
+        self.write_config_32(offset, 0xFFFF_FFFF);
+        let mask = self.read_config_32(offset);
+        self.write_config_32(offset, value);
+        mask
+    }
+
+    // Walks the capability list looking for the MSI capability
+    fn find_msi(&self) -> Option<Msi> {
+        if self.read_config_16(STATUS) & STATUS_CAPABILITIES == 0 {
+            return None;
+        }
+
+        let mut offset = self.read_config_8(CAPABILITIES_POINTER) & 0xFC;
+        let mut remaining = 48; // Stops broken lists from looping forever
+
+        while offset != 0 && remaining > 0 {
+            if self.read_config_8(offset) == CAPABILITY_MSI {
+                let control = self.read_config_16(offset + 2);
+                return Some(Msi {
+                    offset,
+                    is_64_bit: control & MSI_64_BIT != 0,
+                    per_vector_masking: control & MSI_PER_VECTOR_MASKING != 0,
+                    vector_count: 1 << ((control >> 1) & 0x7),
+                });
+            }
+
+            offset = self.read_config_8(offset + 1) & 0xFC;
+            remaining -= 1;
+        }
+        None
+    }
+
+    /*
+        Has the device send the interrupt vector to the local APIC given rather than using its interrupt pin
+        A single message is used
+    */
+    pub fn enable_msi(&self, vector: u8, apic_id: u8) -> Result<(), &'static str> {
+        let msi = self.msi.ok_or("Device doesn't support MSI")?;
+
+        let address = MSI_ADDRESS_BASE | ((apic_id as u32) << 12);
+        self.write_config_32(msi.offset + 4, address);
+
+        // Data register is after the top half of the address for 64 bit capable devices
+        let data_offset = match msi.is_64_bit {
+            true => {
+                self.write_config_32(msi.offset + 8, 0);
+                msi.offset + 12
+            }
+            false => msi.offset + 8,
+        };
+        self.write_config_16(data_offset, vector as u16);
+
+        // Clearing multiple message enable (bits 4-6) uses a single vector
+        let control = self.read_config_16(msi.offset + 2);
+        self.write_config_16(msi.offset + 2, (control & !0x70) | MSI_ENABLE);
+        Ok(())
+    }
+}
+
+// Devices which a driver handles
+pub enum PciMatch {
+    Device(u16, u16), // Vendor id, device id
+    Class(u8, u8),    // Class, subclass
+}
+
+impl PciMatch {
+    fn matches(&self, pci_device: &PciDevice) -> bool {
+        match *self {
+            PciMatch::Device(vendor_id, device_id) => {
+                pci_device.vendor_id == vendor_id && pci_device.device_id == device_id
+            }
+            PciMatch::Class(class, subclass) => {
+                pci_device.class == class && pci_device.subclass == subclass
+            }
+        }
+    }
+}
+
+pub struct PciDriver {
+    pub name: &'static str,
+    pub matches: &'static [PciMatch],
+    // Sets up the device, returning whether the driver is now using it
+    pub probe: fn(&PciDevice) -> bool,
+}
+
+static PCI_DEVICES: Lock<[Option<PciDevice>; MAX_DEVICES]> = Lock::new([None; MAX_DEVICES]);
+static PCI_DRIVERS: Lock<[Option<&'static PciDriver>; MAX_DRIVERS]> =
+    Lock::new([None; MAX_DRIVERS]);
+
+// Drivers must be registered before init so they are bound to the devices as they are found
+pub fn register_driver(driver: &'static PciDriver) {
+    let drivers = PCI_DRIVERS.lock();
+    match drivers.iter().position(|slot| slot.is_none()) {
+        Some(index) => drivers[index] = Some(driver),
+        None => print_serial!("TOO MANY PCI DRIVERS, {} IGNORED\n", driver.name),
+    }
+    PCI_DRIVERS.free();
+}
+
+// Scans every bus, logs the devices found and binds drivers to them
+pub fn init() {
+    let mut scanned_buses: [bool; 256] = [false; 256];
+
+    // Multiple host bridges are functions of the device at 0:0 (function number is the bus they control)
+    if read_config_8(0, 0, 0, HEADER_TYPE) & HEADER_TYPE_MULTIFUNCTION == 0 {
+        scan_bus(0, 0, &mut scanned_buses);
+    } else {
+        for function in 0..8 {
+            if read_config_16(0, 0, function, VENDOR_ID) != NO_DEVICE {
+                scan_bus(function, 0, &mut scanned_buses);
+            }
+        }
+    }
+
+    log_devices();
+    bind_drivers();
+}
+
+fn scan_bus(bus: u8, depth: u8, scanned_buses: &mut [bool; 256]) {
+    if scanned_buses[bus as usize] {
+        return;
+    }
+    scanned_buses[bus as usize] = true;
+
+    for device in 0..32 {
+        if read_config_16(bus, device, 0, VENDOR_ID) == NO_DEVICE {
+            continue;
+        }
+
+        let function_count =
+            match read_config_8(bus, device, 0, HEADER_TYPE) & HEADER_TYPE_MULTIFUNCTION {
+                0 => 1,
+                _ => 8,
+            };
+
+        for function in 0..function_count {
+            if let Some(pci_device) = PciDevice::new(bus, device, function, depth) {
+                add_device(pci_device);
+
+                // Devices behind a bridge are on its secondary bus
+                if pci_device.is_bridge() {
+                    let secondary_bus = pci_device.read_config_8(SECONDARY_BUS);
+                    if secondary_bus != 0 {
+                        scan_bus(secondary_bus, depth + 1, scanned_buses);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn add_device(pci_device: PciDevice) {
+    let devices = PCI_DEVICES.lock();
+    match devices.iter().position(|slot| slot.is_none()) {
+        Some(index) => devices[index] = Some(pci_device),
+        None => print_serial!("TOO MANY PCI DEVICES\n"),
+    }
+    PCI_DEVICES.free();
+}
+
+fn get_device(index: usize) -> Option<PciDevice> {
+    let pci_device = PCI_DEVICES.lock().get(index).copied().flatten();
+    PCI_DEVICES.free();
+    pci_device
+}
+
+fn get_driver(index: usize) -> Option<&'static PciDriver> {
+    let driver = PCI_DRIVERS.lock().get(index).copied().flatten();
+    PCI_DRIVERS.free();
+    driver
+}
+
+/*
+    Each device is bound to the first driver which matches it and accepts it
+    Locks are free whilst probing as drivers may use the PCI functions
+*/
+fn bind_drivers() {
+    let mut index = 0;
+    while let Some(pci_device) = get_device(index) {
+        let mut driver_index = 0;
+        while let Some(driver) = get_driver(driver_index) {
+            driver_index += 1;
+
+            if !driver.matches.iter().any(|m| m.matches(&pci_device))
+                || !(driver.probe)(&pci_device)
+            {
+                continue;
+            }
+
+            print_serial!(
+                "PCI {:02x}:{:02x}.{} BOUND TO {}\n",
+                pci_device.bus,
+                pci_device.device,
+                pci_device.function,
+                driver.name
+            );
+            if let Some(device) = PCI_DEVICES.lock()[index].as_mut() {
+                device.driver = Some(driver.name);
+            }
+            PCI_DEVICES.free();
+            break;
+        }
+        index += 1;
+    }
+}
+
+// Prints every device (indented by how many bridges it is behind) along with its BARs
+fn log_devices() {
+    print_serial!("PCI DEVICES:\n");
+
+    let mut index = 0;
+    while let Some(pci_device) = get_device(index) {
+        index += 1;
+
+        for _i in 0..pci_device.depth {
+            print_serial!("    ");
+        }
+        print_serial!(
+            "{:02x}:{:02x}.{} {:04x}:{:04x} CLASS {:02x}:{:02x}:{:02x}{}{}\n",
+            pci_device.bus,
+            pci_device.device,
+            pci_device.function,
+            pci_device.vendor_id,
+            pci_device.device_id,
+            pci_device.class,
+            pci_device.subclass,
+            pci_device.prog_if,
+            if pci_device.is_bridge() {
+                " BRIDGE"
+            } else {
+                ""
+            },
+            if pci_device.msi.is_some() { " MSI" } else { "" }
+        );
+
+        for (bar_index, bar) in pci_device.bars.iter().enumerate() {
+            if let Some(bar) = bar {
+                for _i in 0..pci_device.depth + 1 {
+                    print_serial!("    ");
+                }
+                print_serial!(
+                    "BAR{} {:?} 0x{:x} SIZE 0x{:x}{}\n",
+                    bar_index,
+                    bar.bar_type,
+                    bar.address,
+                    bar.size,
+                    if bar.prefetchable {
+                        " PREFETCHABLE"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+    }
+}
+
+// Returns the first device with a matching vendor and any of the device ids
+pub fn find_device(vendor_id: u16, device_ids: &[u16]) -> Option<PciDevice> {
+    let mut index = 0;
+    while let Some(pci_device) = get_device(index) {
+        if pci_device.vendor_id == vendor_id && device_ids.contains(&pci_device.device_id) {
+            return Some(pci_device);
+        }
+        index += 1;
+    }
+    None
+}
+
+// Bit 31 enables the access, followed by the bus, device, function and the (4 byte aligned) register
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    0x8000_0000
+        | ((bus as u32) << 16)
+        | ((device as u32) << 11)
+        | ((function as u32) << 8)
+        | ((offset as u32) & 0xFC)
+}
+
+fn read_config_32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    outl(
+        CONFIG_ADDRESS,
+        config_address(bus, device, function, offset),
+    );
+    inl(CONFIG_DATA)
+}
+
+fn write_config_32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    outl(
+        CONFIG_ADDRESS,
+        config_address(bus, device, function, offset),
+    );
+    outl(CONFIG_DATA, value);
+}
+
+fn read_config_16(bus: u8, device: u8, function: u8, offset: u8) -> u16 {
+    (read_config_32(bus, device, function, offset) >> ((offset & 2) * 8)) as u16
+}
+
+/*
+    Smaller registers are written by changing part of the 4 byte register they are within
+    Status bits are cleared by writing 1's so the status register is written as 0 when changing the command register
+*/
+fn write_config_16(bus: u8, device: u8, function: u8, offset: u8, value: u16) {
+    let shift = (offset & 2) * 8;
+    let mut register = read_config_32(bus, device, function, offset);
+    if offset & 0xFC == COMMAND {
+        register &= 0xFFFF;
+    }
+    register = (register & !(0xFFFF << shift)) | ((value as u32) << shift);
+    write_config_32(bus, device, function, offset, register);
+}
+
+fn read_config_8(bus: u8, device: u8, function: u8, offset: u8) -> u8 {
+    (read_config_32(bus, device, function, offset) >> ((offset & 3) * 8)) as u8
+}
@@ -7,6 +7,7 @@
 */
 
 use crate::ports::outb;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
 
 pub struct Pit {
@@ -15,10 +16,22 @@ pub struct Pit {
 }
 
 const INPUT_CLOCK: u64 = 1193180;
-const FREQUENCY: u64 = 100;
+pub const FREQUENCY: u64 = 100;
 
 pub static PIT: Mutex<Pit> = Mutex::new(Pit::new(FREQUENCY));
 
+// Ticks since the PIT was started (FREQUENCY ticks per second)
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// Called on every PIT interrupt
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn get_ticks() -> u64 {
+    TICKS.load(Ordering::SeqCst)
+}
+
 impl Pit {
     pub const fn new(frequency: u64) -> Pit {
         Pit {
@@ -19,6 +19,13 @@ pub fn inpw(port: u16) -> u16 {
     unsafe { inpw_raw(port) }
 }
 
+pub fn outl(port: u16, value: u32) {
+    unsafe { outl_raw(port, value) };
+}
+pub fn inl(port: u16) -> u32 {
+    unsafe { inl_raw(port) }
+}
+
 pub fn io_wait() {
     outb(0x80, 0);
 }
@@ -29,4 +36,7 @@ extern "C" {
 
     fn outpw_raw(port: u16, value: u16);
     fn inpw_raw(port: u16) -> u16;
+
+    fn outl_raw(port: u16, value: u32);
+    fn inl_raw(port: u16) -> u32;
 }
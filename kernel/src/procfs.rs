@@ -0,0 +1,405 @@
+// src/procfs.rs
+
+/*
+    Process filesystem exposes the state of the kernel as text files (mounted at /proc)
+    Nothing is stored, as each file is generated whenever it is read so it is always up to date
+    Global files:
+        meminfo - physical memory (page frames) and the kernel heap
+        interrupts - number of times each interrupt vector has been triggered
+        uptime - seconds since the PIT was started
+    Each process has a directory named by its pid which holds:
+        status - priority, threads, messages waiting and working directory
+        maps - regions of virtual memory mapped within its address space
+        fd - open file descriptors
+    Inodes encode what they refer to, with the lowest 8 bits being the file and the rest being the pid plus 1 (0 for global files)
+*/
+
+use crate::allocator::{self, kfree, kmalloc};
+use crate::interrupts;
+use crate::multitask::{Process, ThreadState, PROCESS_SCHEDULAR};
+use crate::page_frame_allocator::{PAGE_FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::paging;
+use crate::pit::{self, FREQUENCY};
+use crate::vfs::{
+    DirectoryEntry, FileSystem, FileType, Inode, Stat, FILE_NOT_FOUND, IS_A_DIRECTORY,
+    NOT_A_DIRECTORY, S_IFDIR, S_IFREG,
+};
+use core::fmt::{self, Write};
+
+const ROOT_INODE: Inode = 0;
+const FILE_BITS: u64 = 8;
+const FILE_MASK: u64 = (1 << FILE_BITS) - 1;
+
+const GLOBAL_FILES: [&str; 3] = ["meminfo", "interrupts", "uptime"];
+const PROCESS_FILES: [&str; 3] = ["status", "maps", "fd"];
+
+pub struct Procfs;
+
+static mut PROCFS: Procfs = Procfs;
+
+// Text of a file which grows as it is written to with write!
+struct Text {
+    data: *mut u8,
+    length: usize,
+    capacity: usize,
+}
+
+impl Text {
+    fn new() -> Text {
+        Text {
+            data: core::ptr::null_mut(),
+            length: 0,
+            capacity: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.data, self.length) }
+    }
+
+    fn free(&mut self) {
+        if !self.data.is_null() {
+            kfree(self.data as *mut u64);
+        }
+        self.data = core::ptr::null_mut();
+    }
+}
+
+impl fmt::Write for Text {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let length = self.length + string.len();
+        if length > self.capacity {
+            let capacity = length.max(self.capacity * 2).max(PAGE_SIZE);
+            let data = kmalloc(capacity as u64) as *mut u8;
+            unsafe {
+                if !self.data.is_null() {
+                    core::ptr::copy_nonoverlapping(self.data, data, self.length);
+                    kfree(self.data as *mut u64);
+                }
+            }
+            self.data = data;
+            self.capacity = capacity;
+        }
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                string.as_ptr(),
+                self.data.add(self.length),
+                string.len(),
+            );
+        }
+        self.length = length;
+        Ok(())
+    }
+}
+
+// Splits an inode into the pid (None for global files) and the file (0 for directories)
+fn split_inode(inode: Inode) -> (Option<u64>, u64) {
+    let pid = match inode >> FILE_BITS {
+        0 => None,
+        pid => Some(pid - 1),
+    };
+    (pid, inode & FILE_MASK)
+}
+
+fn process_inode(pid: u64, file: u64) -> Inode {
+    ((pid + 1) << FILE_BITS) | file
+}
+
+fn get_process(pid: u64) -> Result<Process, &'static str> {
+    let process = PROCESS_SCHEDULAR.lock().get_process(pid);
+    PROCESS_SCHEDULAR.free();
+    process.ok_or("Process doesn't exist")
+}
+
+// Finds the first process which is held at or after a slot of the schedular, returning its pid and slot
+fn next_process(slot: usize) -> Option<(u64, usize)> {
+    let schedular = PROCESS_SCHEDULAR.lock();
+    let process = schedular
+        .processes
+        .iter()
+        .enumerate()
+        .skip(slot)
+        .find_map(|(i, process)| Some((process.as_ref()?.pid, i)));
+    PROCESS_SCHEDULAR.free();
+    process
+}
+
+// Gives the file numbers (starting from 1) of a name within a list of files
+fn find_file(files: &[&str], name: &str) -> Option<u64> {
+    files
+        .iter()
+        .position(|&file| file == name)
+        .map(|index| index as u64 + 1)
+}
+
+fn generate(inode: Inode, text: &mut Text) -> Result<(), &'static str> {
+    let result = match split_inode(inode) {
+        (None, 1) => write_meminfo(text),
+        (None, 2) => write_interrupts(text),
+        (None, 3) => write_uptime(text),
+        (Some(pid), 1) => write_status(text, get_process(pid)?),
+        (Some(pid), 2) => write_maps(text, get_process(pid)?),
+        (Some(pid), 3) => write_fds(text, get_process(pid)?),
+        (_, 0) => return Err(IS_A_DIRECTORY),
+        _ => return Err(FILE_NOT_FOUND),
+    };
+    result.map_err(|_| "Failed to generate file")
+}
+
+fn write_meminfo(text: &mut Text) -> fmt::Result {
+    // Statistics are copied first as the locks can't be held whilst the text allocates memory
+    let statistics = PAGE_FRAME_ALLOCATOR.lock().statistics();
+    PAGE_FRAME_ALLOCATOR.free();
+    let (heap_free_blocks, heap_free) = allocator::heap_statistics();
+
+    writeln!(text, "MemTotal:       {:>10} kB", statistics.total / 1024)?;
+    writeln!(text, "MemUsed:        {:>10} kB", statistics.used / 1024)?;
+    writeln!(
+        text,
+        "MemFree:        {:>10} kB",
+        (statistics.total - statistics.used) / 1024
+    )?;
+    writeln!(text, "FreeFrames:     {:>10}", statistics.free_frames)?;
+    writeln!(text, "HeapFree:       {:>10} kB", heap_free / 1024)?;
+    writeln!(text, "HeapFreeBlocks: {:>10}", heap_free_blocks)
+}
+
+// Only vectors which have been triggered are listed
+fn write_interrupts(text: &mut Text) -> fmt::Result {
+    for vector in 0..256 {
+        let count = interrupts::get_interrupt_count(vector);
+        if count == 0 {
+            continue;
+        }
+
+        let name = match vector {
+            0..=31 => "exception",
+            0x20 => "timer",
+            0x21 => "keyboard",
+            0x2c => "mouse",
+            0x80 => "syscall",
+            _ => "",
+        };
+        writeln!(text, "{:>4}: {:>10}  {}", vector, count, name)?;
+    }
+    Ok(())
+}
+
+fn write_uptime(text: &mut Text) -> fmt::Result {
+    let ticks = pit::get_ticks();
+    writeln!(
+        text,
+        "{}.{:02}",
+        ticks / FREQUENCY,
+        (ticks % FREQUENCY) * 100 / FREQUENCY
+    )
+}
+
+fn write_status(text: &mut Text, process: Process) -> fmt::Result {
+    let mut threads = 0;
+    let mut blocked = 0;
+    let schedular = PROCESS_SCHEDULAR.lock();
+    for thread in schedular.tasks.iter().flatten() {
+        if thread.pid == Some(process.pid) && thread.state != ThreadState::Exited {
+            threads += 1;
+            if thread.state == ThreadState::Blocked {
+                blocked += 1;
+            }
+        }
+    }
+    PROCESS_SCHEDULAR.free();
+
+    writeln!(text, "Pid:\t\t{}", process.pid)?;
+    writeln!(text, "Priority:\t{:?}", process.process_priority)?;
+    writeln!(text, "Threads:\t{}", threads)?;
+    writeln!(text, "Blocked:\t{}", blocked)?;
+    writeln!(text, "Messages:\t{}", process.messages.length)?;
+    writeln!(text, "Cwd:\t\t{}", unsafe { (*process.cwd).as_str() })?;
+    // Real, effective and saved ids (as within Linux)
+    let credentials = process.credentials;
+    writeln!(
+        text,
+        "Uid:\t\t{}\t{}\t{}",
+        credentials.uid, credentials.euid, credentials.suid
+    )?;
+    writeln!(
+        text,
+        "Gid:\t\t{}\t{}\t{}",
+        credentials.gid, credentials.egid, credentials.sgid
+    )?;
+    writeln!(text, "Umask:\t\t{:04o}", process.umask)?;
+    writeln!(text, "PageTable:\t0x{:x}", process.cr3 as u64)
+}
+
+// Each region is given as start-end followed by whether it is readable, writable and accessible from userland
+fn write_maps(text: &mut Text, process: Process) -> fmt::Result {
+    let mut result = Ok(());
+    paging::for_each_region(process.cr3, |start, end, flags| {
+        if result.is_ok() {
+            result = writeln!(
+                text,
+                "{:016x}-{:016x} r{}{}",
+                start,
+                end,
+                if flags & 0b10 != 0 { 'w' } else { '-' },
+                if flags & 0b100 != 0 { 'u' } else { '-' }
+            );
+        }
+    });
+    result
+}
+
+// Standard streams which haven't been replaced refer to the console, whilst files are given as mount:inode and the offset
+fn write_fds(text: &mut Text, process: Process) -> fmt::Result {
+    let file_table = unsafe { &*process.file_table };
+    for fd in (0..=2).filter(|&fd| file_table.is_console(fd)) {
+        writeln!(text, "{}\tconsole", fd)?;
+    }
+
+    for (fd, file) in file_table.open_files() {
+        writeln!(
+            text,
+            "{}\t{}:{}\t{}",
+            fd, file.vnode.mount, file.vnode.inode, file.offset
+        )?;
+    }
+    Ok(())
+}
+
+fn directory_stat(inode: Inode) -> Stat {
+    Stat {
+        st_ino: inode as u16,
+        st_mode: S_IFDIR | 0o555,
+        st_nlink: 2,
+        ..Stat::default()
+    }
+}
+
+impl FileSystem for Procfs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str> {
+        match split_inode(directory) {
+            (None, 0) => {
+                if let Some(file) = find_file(&GLOBAL_FILES, name) {
+                    return Ok(file);
+                }
+
+                let pid = name.parse::<u64>().map_err(|_| FILE_NOT_FOUND)?;
+                get_process(pid).map_err(|_| FILE_NOT_FOUND)?;
+                Ok(process_inode(pid, 0))
+            }
+            (Some(pid), 0) => {
+                get_process(pid)?;
+                match find_file(&PROCESS_FILES, name) {
+                    Some(file) => Ok(process_inode(pid, file)),
+                    None => Err(FILE_NOT_FOUND),
+                }
+            }
+            _ => Err(NOT_A_DIRECTORY),
+        }
+    }
+
+    // Files are always empty until they are read (as they're generated)
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str> {
+        let (pid, file) = split_inode(inode);
+        if let Some(pid) = pid {
+            get_process(pid)?;
+        }
+
+        match file {
+            0 => Ok(directory_stat(inode)),
+            _ => Ok(Stat {
+                st_ino: inode as u16,
+                st_mode: S_IFREG | 0o444,
+                st_nlink: 1,
+                ..Stat::default()
+            }),
+        }
+    }
+
+    fn read(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let mut text = Text::new();
+        let result = generate(inode, &mut text).map(|_| {
+            let contents = text.as_bytes();
+            let start = (offset as usize).min(contents.len());
+            let count = buffer.len().min(contents.len() - start);
+            buffer[0..count].copy_from_slice(&contents[start..start + count]);
+            count
+        });
+        text.free();
+        result
+    }
+
+    /*
+        Indexes of the root are the global files followed by the slots of the schedular's process table
+        Indexes of a process directory are its files
+    */
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+        match split_inode(directory) {
+            (None, 0) => {
+                if let Some(name) = GLOBAL_FILES.get(index as usize) {
+                    let entry = DirectoryEntry::new(index + 1, FileType::File, 0, name.as_bytes());
+                    return Ok(Some((entry, index + 1)));
+                }
+
+                let slot = index as usize - GLOBAL_FILES.len();
+                match next_process(slot) {
+                    Some((pid, slot)) => {
+                        let mut name = Text::new();
+                        let _ = write!(name, "{}", pid);
+                        let entry = DirectoryEntry::new(
+                            process_inode(pid, 0),
+                            FileType::Directory,
+                            0,
+                            name.as_bytes(),
+                        );
+                        name.free();
+                        Ok(Some((entry, (GLOBAL_FILES.len() + slot + 1) as u64)))
+                    }
+                    None => Ok(None),
+                }
+            }
+            (Some(pid), 0) => {
+                get_process(pid)?;
+                match PROCESS_FILES.get(index as usize) {
+                    Some(name) => {
+                        let entry = DirectoryEntry::new(
+                            process_inode(pid, index + 1),
+                            FileType::File,
+                            0,
+                            name.as_bytes(),
+                        );
+                        Ok(Some((entry, index + 1)))
+                    }
+                    None => Ok(None),
+                }
+            }
+            _ => Err(NOT_A_DIRECTORY),
+        }
+    }
+}
+
+// There is only ever one procfs as it always shows the whole kernel
+pub fn mount() -> *mut dyn FileSystem {
+    core::ptr::addr_of_mut!(PROCFS)
+}
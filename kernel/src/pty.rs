@@ -0,0 +1,208 @@
+// src/pty.rs
+
+/*
+    Pseudo-terminals (ptys) are pairs of devices which let a program (such as the terminal) be the device of a tty
+        Master (/dev/ptmN) - bytes written are input to the tty (as if typed), whilst reads give what the tty outputs
+        Slave (/dev/ptsN) - a full tty with its own line discipline and termios, used as the terminal of a shell
+    Pairs are made at boot, so posix_openpt opens the first master which isn't in use
+    A master can only be held by one process at a time, and its slave can only be opened whilst it is
+    Closing the master hangs up the slave, so its readers see the end of the file and its foreground process gets SIGHUP
+    Reads of the master never wait, giving 0 when there is no output
+*/
+
+use crate::char_device::{self, CharDevice};
+use crate::multitask::PROCESS_SCHEDULAR;
+use crate::ring_buffer::RingBuffer;
+use crate::spinlock::Lock;
+use crate::tty;
+use crate::vfs::DEVICE_BUSY;
+
+const MAX_PTYS: usize = 8; // Also defined in userland/syscalls/syscalls.h (used by posix_openpt)
+const OUTPUT_BUFFER_SIZE: usize = 4096;
+
+const MASTER_NAMES: [&str; MAX_PTYS] = [
+    "ptm0", "ptm1", "ptm2", "ptm3", "ptm4", "ptm5", "ptm6", "ptm7",
+];
+const SLAVE_NAMES: [&str; MAX_PTYS] = [
+    "pts0", "pts1", "pts2", "pts3", "pts4", "pts5", "pts6", "pts7",
+];
+
+// Gives the number of the pty (the N of /dev/ptsN) through a master, matching Linux
+pub const TIOCGPTN: u64 = 0x80045430;
+
+#[derive(Copy, Clone)]
+struct Pty {
+    tty: usize,
+    output: RingBuffer<OUTPUT_BUFFER_SIZE>, // Output of the tty waiting to be read through the master
+    owner: Option<u64>,                     // Process which has the master open
+}
+
+impl Pty {
+    // Processes which are killed never close their files, so the owner must still be running
+    fn is_master_open(&self) -> bool {
+        self.owner.is_some_and(|pid| {
+            let process = PROCESS_SCHEDULAR.lock().get_process(pid);
+            PROCESS_SCHEDULAR.free();
+            process.is_some()
+        })
+    }
+}
+
+static PTYS: Lock<[Option<Pty>; MAX_PTYS]> = Lock::new([None; MAX_PTYS]);
+
+// Creates every pair and registers the masters and slaves as character devices
+pub fn init() {
+    for number in 0..MAX_PTYS {
+        let tty = match tty::register(write_output, None) {
+            Some(tty) => tty,
+            None => break,
+        };
+
+        PTYS.lock()[number] = Some(Pty {
+            tty,
+            output: RingBuffer::new(),
+            owner: None,
+        });
+        PTYS.free();
+
+        unsafe {
+            MASTERS[number].number = number;
+            SLAVES[number].number = number;
+            char_device::register(core::ptr::addr_of_mut!(MASTERS[number]));
+            char_device::register(core::ptr::addr_of_mut!(SLAVES[number]));
+        }
+    }
+}
+
+// Output of the slave's tty is held until the master is read (and dropped once the buffer is full)
+fn write_output(index: usize, buffer: &[u8]) {
+    let ptys = PTYS.lock();
+    if let Some(pty) = ptys.iter_mut().flatten().find(|pty| pty.tty == index) {
+        for &byte in buffer {
+            pty.output.push(byte);
+        }
+    }
+    PTYS.free();
+}
+
+// The lock is never held whilst calling into the tty, as the tty calls write_output
+fn get_tty(number: usize) -> Result<usize, &'static str> {
+    let tty = PTYS.lock()[number].map(|pty| pty.tty);
+    PTYS.free();
+    tty.ok_or("Device doesn't exist")
+}
+
+fn current_pid() -> Option<u64> {
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+    current_thread.and_then(|thread| thread.pid)
+}
+
+#[derive(Copy, Clone)]
+pub struct PtyMaster {
+    number: usize,
+}
+
+static mut MASTERS: [PtyMaster; MAX_PTYS] = [PtyMaster { number: 0 }; MAX_PTYS];
+
+impl CharDevice for PtyMaster {
+    fn name(&self) -> &'static str {
+        MASTER_NAMES[self.number]
+    }
+
+    // Claims the pair, whose tty starts afresh with the default settings
+    fn open(&mut self) -> Result<(), &'static str> {
+        let owner = current_pid();
+        let ptys = PTYS.lock();
+        let tty = match ptys[self.number].as_mut() {
+            Some(pty) if pty.is_master_open() => Err(DEVICE_BUSY),
+            Some(pty) => {
+                pty.owner = owner;
+                pty.output.clear();
+                Ok(pty.tty)
+            }
+            None => Err("Device doesn't exist"),
+        };
+        PTYS.free();
+
+        tty::reset(tty?)
+    }
+
+    fn close(&mut self) {
+        let ptys = PTYS.lock();
+        let tty = ptys[self.number].as_mut().map(|pty| {
+            pty.owner = None;
+            pty.tty
+        });
+        PTYS.free();
+
+        if let Some(tty) = tty {
+            tty::hangup(tty);
+        }
+    }
+
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let ptys = PTYS.lock();
+        let count = ptys[self.number]
+            .as_mut()
+            .map(|pty| pty.output.read(buffer));
+        PTYS.free();
+        count.ok_or("Device doesn't exist")
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let tty = get_tty(self.number)?;
+        for &byte in buffer {
+            tty::receive(tty, byte);
+        }
+        Ok(buffer.len())
+    }
+
+    // Other requests (such as setting the window size) act on the slave's tty
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<i64, &'static str> {
+        match request {
+            TIOCGPTN => {
+                unsafe {
+                    *(argument as *mut u32) = self.number as u32;
+                }
+                Ok(0)
+            }
+            _ => tty::ioctl(get_tty(self.number)?, request, argument),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PtySlave {
+    number: usize,
+}
+
+static mut SLAVES: [PtySlave; MAX_PTYS] = [PtySlave { number: 0 }; MAX_PTYS];
+
+impl CharDevice for PtySlave {
+    fn name(&self) -> &'static str {
+        SLAVE_NAMES[self.number]
+    }
+
+    fn open(&mut self) -> Result<(), &'static str> {
+        let is_master_open = PTYS.lock()[self.number].is_some_and(|pty| pty.is_master_open());
+        PTYS.free();
+
+        match is_master_open {
+            true => Ok(()),
+            false => Err("Input/output error"),
+        }
+    }
+
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        tty::read(get_tty(self.number)?, buffer)
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        tty::write(get_tty(self.number)?, buffer)
+    }
+
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<i64, &'static str> {
+        tty::ioctl(get_tty(self.number)?, request, argument)
+    }
+}
@@ -0,0 +1,76 @@
+// src/ring_buffer.rs
+
+/*
+    Fixed size queue of bytes which wraps around to the start of its array
+    Holds input which arrives within interrupt handlers until a process reads it
+    Once full, new bytes are dropped so the oldest input is kept
+*/
+
+#[derive(Copy, Clone)]
+pub struct RingBuffer<const N: usize> {
+    data: [u8; N],
+    start: usize,  // Index of the oldest byte
+    length: usize, // Number of bytes held
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> RingBuffer<N> {
+        RingBuffer {
+            data: [0; N],
+            start: 0,
+            length: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.length == N
+    }
+
+    // Returns false if the byte was dropped as the buffer is full
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.data[(self.start + self.length) % N] = byte;
+        self.length += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let byte = self.data[self.start];
+        self.start = (self.start + 1) % N;
+        self.length -= 1;
+        Some(byte)
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.length = 0;
+    }
+
+    // Moves as many bytes as fit into the buffer and returns how many were moved
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let mut count = 0;
+        while count < buffer.len() {
+            match self.pop() {
+                Some(byte) => buffer[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,89 @@
+// src/signal.rs
+
+/*
+    Signals tell a process that an event has happened (such as ^C being typed at its terminal)
+    Processes can't install handlers yet, so every signal takes its default action:
+        Stop signals stop every thread of the process until it is sent SIGCONT
+        SIGCONT, SIGCHLD and SIGWINCH are ignored (apart from SIGCONT continuing a stopped process)
+        Every other signal terminates the process
+    Signal numbers match newlib
+*/
+
+use crate::multitask::{self, PROCESS_SCHEDULAR};
+use crate::{print_serial, CONSOLE};
+
+pub const SIGHUP: u64 = 1;
+pub const SIGINT: u64 = 2;
+pub const SIGQUIT: u64 = 3;
+pub const SIGSTOP: u64 = 17;
+pub const SIGTSTP: u64 = 18;
+pub const SIGCONT: u64 = 19;
+pub const SIGCHLD: u64 = 20;
+pub const SIGWINCH: u64 = 28;
+
+const NSIG: u64 = 32;
+
+pub const INVALID_SIGNAL: &str = "Invalid signal";
+pub const NO_SUCH_PROCESS: &str = "No such process";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    Terminate,
+    Stop,
+    Continue,
+    Ignore,
+}
+
+fn default_action(signal: u64) -> Action {
+    match signal {
+        SIGSTOP | SIGTSTP => Action::Stop,
+        SIGCONT => Action::Continue,
+        SIGCHLD | SIGWINCH => Action::Ignore,
+        _ => Action::Terminate,
+    }
+}
+
+/*
+    Carries out the default action of a signal on a process
+    Signal 0 only checks that the process exists
+    Can be called from interrupt handlers, so a process which terminates itself keeps running until the schedular switches away
+*/
+pub fn send(pid: u64, signal: u64) -> Result<(), &'static str> {
+    if signal >= NSIG {
+        return Err(INVALID_SIGNAL);
+    }
+
+    let schedular = PROCESS_SCHEDULAR.lock();
+    let result = match schedular.get_process(pid) {
+        Some(mut process) if signal != 0 => {
+            match default_action(signal) {
+                Action::Terminate => schedular.remove_process(pid),
+                Action::Stop => {
+                    process.stopped = true;
+                    schedular.update_process(process);
+                }
+                Action::Continue => {
+                    process.stopped = false;
+                    schedular.update_process(process);
+                }
+                Action::Ignore => {}
+            }
+            Ok(())
+        }
+        Some(_) => Ok(()),
+        None => Err(NO_SUCH_PROCESS),
+    };
+    PROCESS_SCHEDULAR.free();
+
+    if result.is_ok() && signal != 0 {
+        print_serial!("SIGNAL {} SENT TO PROCESS {}\n", signal, pid);
+    }
+    result
+}
+
+// Whether the calling process has been terminated (so shouldn't return to userland)
+pub fn is_current_exited() -> bool {
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+    current_thread.is_some_and(|thread| thread.state == multitask::ThreadState::Exited)
+}
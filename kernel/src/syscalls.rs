@@ -8,17 +8,25 @@
 */
 
 use crate::framebuffer::{self, Event, FramebuffferEntity, Rectangle, Window, WINDOW_MANAGER};
-use crate::fs::File;
+use crate::futex;
 use crate::hashmap::HashMap;
-use crate::interrupts::Registers;
+use crate::interrupts::{self, Registers};
 use crate::list::Stack;
-use crate::multitask::PROCESS_SCHEDULAR;
+use crate::multitask::{self, Credentials, Process, Thread, ThreadState, PROCESS_SCHEDULAR};
 use crate::page_frame_allocator::{FrameAllocator, PAGE_FRAME_ALLOCATOR};
+use crate::paging;
+use crate::path::Path;
 use crate::print_serial;
-use crate::spinlock::Lock;
+use crate::signal;
+use crate::tty;
+use crate::vfs::{
+    self, FileType, OpenFile, Stat, CROSS_DEVICE_LINK, DEVICE_BUSY, DIRECTORY_NOT_EMPTY,
+    FILE_EXISTS, FILE_NOT_FOUND, INAPPROPRIATE_IOCTL, INVALID_FILENAME, INVALID_SYMLINK,
+    IS_A_DIRECTORY, LINK_TO_DIRECTORY, NOT_A_DIRECTORY, NOT_A_SYMLINK, NOT_PERMITTED,
+    PERMISSION_DENIED, READ_ONLY, R_OK, SYMLINK_LOOP, S_IFCHR, S_IFDIR, S_IFMT, W_OK, X_OK,
+};
 use crate::CONSOLE;
 use bitflags::bitflags;
-use core::panic;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(C)]
@@ -33,9 +41,74 @@ pub struct CondensedWindow {
 /*
     File descriptor table is hashmap of file descriptors which point to actual files
     File table entries are created when a process requests to open a file and this maintains its validity and is used
+    Each process owns a file table which is shared between all of its threads
 */
-pub static FILE_TABLE: Lock<HashMap<File>> = Lock::new(HashMap::<File>::new());
-pub static mut FILE_TABLE_COUNTER: i64 = 0;
+#[derive(Copy, Clone, Debug)]
+pub struct FileTable {
+    files: HashMap<OpenFile>,
+    counter: i64,
+}
+
+impl FileTable {
+    pub const fn new() -> FileTable {
+        FileTable {
+            files: HashMap::<OpenFile>::new(),
+            counter: 2, // 0, 1, 2 are reserved for stdin, stdout, stderr
+        }
+    }
+
+    // Adds a file to the table and returns its file descriptor
+    pub fn add(&mut self, file: OpenFile) -> i64 {
+        self.counter += 1;
+        self.files.set(self.counter as usize, file);
+        self.counter
+    }
+
+    pub fn get(&self, fd: usize) -> Option<OpenFile> {
+        self.files.get(fd)
+    }
+
+    // Later files are added after fd, so it is never reused whilst open
+    pub fn set(&mut self, fd: usize, file: OpenFile) {
+        self.files.set(fd, file);
+        self.counter = self.counter.max(fd as i64);
+    }
+
+    pub fn remove(&mut self, fd: usize) {
+        self.files.remove(fd);
+    }
+
+    // Standard streams refer to the console tty until dup2 replaces them
+    pub fn is_console(&self, fd: usize) -> bool {
+        fd <= 2 && self.get(fd).is_none()
+    }
+
+    // Every open file along with its file descriptor (in order)
+    pub fn open_files(&self) -> impl Iterator<Item = (usize, OpenFile)> + '_ {
+        (0..=self.counter as usize).filter_map(move |fd| Some((fd, self.get(fd)?)))
+    }
+}
+
+// Returns the process which made the syscall
+fn current_process() -> Process {
+    let current_process = PROCESS_SCHEDULAR.lock().get_current_process();
+    PROCESS_SCHEDULAR.free();
+    current_process.expect("Syscall made outside a process")
+}
+
+// Returns the file table of the process which made the syscall
+fn file_table() -> &'static mut FileTable {
+    unsafe { &mut *current_process().file_table }
+}
+
+// Returns the working directory of the process which made the syscall
+fn cwd() -> &'static mut Path {
+    unsafe { &mut *current_process().cwd }
+}
+
+fn is_console(file: u64) -> bool {
+    file_table().is_console(file as usize)
+}
 
 bitflags! {
     struct Flags: u32 {
@@ -46,27 +119,26 @@ bitflags! {
     }
 }
 
+const O_ACCMODE: u32 = 0x0003; // Bits of the flags which give the access mode
+
 #[no_mangle]
 pub extern "C" fn syscall_handler(registers: Registers) -> i64 {
     let syscall_id = registers.rax;
+    interrupts::count_interrupt(0x80);
 
     // print_serial!("SYSCALL {}\n", syscall_id);
 
     return match syscall_id {
+        // Window syscalls draw to the framebuffer, which isn't there in VGA text mode
+        11..=14 | 17 | 18 if !framebuffer::is_available() => -ENODEV,
         0 => _exit(),
         1 => close(registers.rbx),
-        2 => {
-            // fstat
-            return -1;
-        }
+        2 => fstat(registers.rbx, registers.rcx as *mut Stat),
         3 => getpid(),
         4 => isatty(registers.rbx),
         5 => kill(registers.rbx, registers.rcx),
-        6 => {
-            // link
-            return -1;
-        }
-        7 => open(registers.rbx as *const u8, registers.rcx),
+        6 => link(registers.rbx as *const u8, registers.rcx as *const u8),
+        7 => open(registers.rbx as *const u8, registers.rcx, registers.rdx),
         8 => allocate_pages(registers.rbx),
         9 => write(registers.rbx, registers.rcx as *mut u8, registers.rdx),
         10 => read(registers.rbx, registers.rcx as *mut u8, registers.rdx),
@@ -85,66 +157,169 @@ pub extern "C" fn syscall_handler(registers: Registers) -> i64 {
         18 => copy_to_buffer(registers.rbx, registers.rcx as *mut u32, registers.rdx),
         19 => free_pages(registers.rbx as *mut u64, registers.rcx),
         20 => send_message(registers.rbx, registers.rcx, registers.rdx as *const u8),
-        _ => panic!("Unknown Syscall {}\n", syscall_id),
+        21 => futex(registers.rbx as *const u32, registers.rcx, registers.rdx),
+        22 => thread_create(registers.rbx, registers.rcx, registers.rdx),
+        23 => thread_exit(),
+        24 => gettid(),
+        25 => unlink(registers.rbx as *const u8),
+        26 => rmdir(registers.rbx as *const u8),
+        27 => rename(registers.rbx as *const u8, registers.rcx as *const u8),
+        28 => stat(registers.rbx as *const u8, registers.rcx as *mut Stat),
+        29 => lstat(registers.rbx as *const u8, registers.rcx as *mut Stat),
+        30 => getdents(registers.rbx, registers.rcx as *mut u8, registers.rdx),
+        31 => chdir(registers.rbx as *const u8),
+        32 => getcwd(registers.rbx as *mut u8, registers.rcx),
+        33 => sync(),
+        34 => fsync(registers.rbx),
+        35 => ioctl(registers.rbx, registers.rcx, registers.rdx),
+        36 => symlink(registers.rbx as *const u8, registers.rcx as *const u8),
+        37 => readlink(
+            registers.rbx as *const u8,
+            registers.rcx as *mut u8,
+            registers.rdx,
+        ),
+        38 => getuid(),
+        39 => geteuid(),
+        40 => getgid(),
+        41 => getegid(),
+        42 => setuid(registers.rbx),
+        43 => seteuid(registers.rbx),
+        44 => setgid(registers.rbx),
+        45 => setegid(registers.rbx),
+        46 => umask(registers.rbx),
+        47 => chmod(registers.rbx as *const u8, registers.rcx),
+        48 => chown(registers.rbx as *const u8, registers.rcx, registers.rdx),
+        49 => dup2(registers.rbx, registers.rcx),
+        _ => -ENOSYS,
     };
 }
 
-// Terminates process without cleaning files
+// Terminates process (along with every thread within it), closing its files so devices (such as ptys) are released
 fn _exit() -> i64 {
     // Get current pid
-    let pid = PROCESS_SCHEDULAR.lock().current_process_index;
+    let current_process = PROCESS_SCHEDULAR.lock().get_current_process();
     PROCESS_SCHEDULAR.free();
 
-    // Remove from array
-    PROCESS_SCHEDULAR.lock().remove_process(pid);
-    PROCESS_SCHEDULAR.free();
+    if let Some(process) = current_process {
+        let file_table = unsafe { &*process.file_table };
+        while let Some((fd, _)) = file_table.open_files().next() {
+            close(fd as u64);
+        }
 
-    print_serial!("TASK {} EXITED\n", pid);
+        // Remove from array
+        PROCESS_SCHEDULAR.lock().remove_process(process.pid);
+        PROCESS_SCHEDULAR.free();
 
-    return 0;
+        print_serial!("TASK {} EXITED\n", process.pid);
+    }
+
+    multitask::wait_for_exit();
 }
 
-// Closes a file which is pointed by fd
+// Closes a file which is pointed by fd (the file is told once no other fd of the process refers to it)
 fn close(file: u64) -> i64 {
-    FILE_TABLE.lock().remove(file as usize);
-    FILE_TABLE.free();
-    return 0; // Successful (-1 unsuccessful)
+    let file_table = file_table();
+    if let Some(fd) = file_table.get(file as usize) {
+        file_table.remove(file as usize);
+        if !file_table
+            .open_files()
+            .any(|(_, other)| other.vnode == fd.vnode)
+        {
+            vfs::close(fd.vnode);
+        }
+    }
+    0
+}
+
+// Adds a file to the file table (the filesystem is only told the first time the process opens it, matching close)
+fn add_open_file(vnode: vfs::Vnode) -> i64 {
+    let file_table = file_table();
+    if !file_table.open_files().any(|(_, fd)| fd.vnode == vnode) {
+        if let Err(error) = vfs::open(vnode) {
+            return -error_number(error);
+        }
+    }
+    file_table.add(OpenFile::new(vnode))
+}
+
+/*
+    Makes new refer to the same file as old, closing whatever new referred to first
+    Used to connect the standard streams of a process to another file (such as the slave of a pty)
+*/
+fn dup2(old: u64, new: u64) -> i64 {
+    let file = match file_table().get(old as usize) {
+        Some(file) => file,
+        None => return -EBADF,
+    };
+
+    if old != new {
+        close(new);
+        file_table().set(new as usize, file);
+    }
+    new as i64
 }
 
 // Query to check if file is a terminal
 fn isatty(file: u64) -> i64 {
-    if file == 0 || file == 1 || file == 2 {
+    if is_console(file) {
         return 1;
     }
-    return -1;
+
+    // Other files are ttys when they support getting terminal settings (such as /dev/tty)
+    let mut termios = core::mem::MaybeUninit::<tty::Termios>::uninit();
+    match file_table().get(file as usize) {
+        Some(fd) if vfs::ioctl(fd.vnode, tty::TCGETS, termios.as_mut_ptr() as u64).is_ok() => 1,
+        _ => 0,
+    }
 }
 
 // Returns the process id of the current process
 fn getpid() -> i64 {
     // Get current process and return its pid
     let wrapped_process = PROCESS_SCHEDULAR.lock().get_current_process();
+    PROCESS_SCHEDULAR.free();
     if let Some(process) = wrapped_process {
         return process.pid as i64;
     }
-    return -1;
+    -ESRCH
 }
 
-// Sends signals to process group or process - may require IPC
+/*
+    Sends a signal to a process (signal 0 only checks that the process exists)
+    There are no process groups, so pid must be positive
+    Only root or a process with the same user id as the target can signal it
+*/
 fn kill(pid: u64, sig: u64) -> i64 {
-    if sig == 0 {
-        // If sig is 0, no signal is sent
-        return -1;
+    let pid = pid as i32;
+    if pid <= 0 {
+        return -EINVAL;
     }
 
-    // If pid is positive, signal sig is sent to the process
-
-    // If pid is 0, signal is sent to every process in process group
-
-    // If pid is -1, signal is sent to every process in which the calling process has permission to send
+    let sender = current_process();
+    let target = match PROCESS_SCHEDULAR.lock().get_process(pid as u64) {
+        Some(target) => target,
+        None => {
+            PROCESS_SCHEDULAR.free();
+            return -ESRCH;
+        }
+    };
+    PROCESS_SCHEDULAR.free();
 
-    // If pid is under -1, sig is sent to every process in process group whose ID is -pid
+    let permitted = sender.credentials.euid == 0
+        || sender.credentials.uid == target.credentials.uid
+        || sender.credentials.euid == target.credentials.uid;
+    if !permitted {
+        return -EPERM;
+    }
+    if let Err(error) = signal::send(pid as u64, sig) {
+        return -error_number(error);
+    }
 
-    return 0;
+    // Process may have just terminated itself
+    if signal::is_current_exited() {
+        multitask::wait_for_exit();
+    }
+    0
 }
 
 // Allocates a number of pages for liballoc_alloc
@@ -163,89 +338,92 @@ fn free_pages(memory_address: *mut u64, pages_required: u64) -> i64 {
     0
 }
 
-// Used to open a file for reading/writing and returns the file number
-fn open(name: *const u8, flags: u64) -> i64 {
-    // Get name of file
-    let mut filepath = crate::string::get_string_from_ptr(name);
-    filepath = &filepath[0..filepath.len() - 1];
+// Gets a filepath passed in by a process as an absolute path (relative paths start from the current working directory)
+fn get_filepath(name: *const u8) -> Result<Path, &'static str> {
+    let filepath = crate::string::get_string_from_ptr(name);
+    let filepath = &filepath[0..filepath.len() - 1]; // Remove the null terminator
 
-    // For now ignore relative and absolute filepaths
-    match crate::fs::parse_absolute_filepath(filepath) {
-        Ok(file) => unsafe {
-            FILE_TABLE_COUNTER += 1;
+    cwd().join(filepath)
+}
 
-            FILE_TABLE.lock().set(FILE_TABLE_COUNTER as usize, file);
-            FILE_TABLE.free();
+/*
+    Used to open a file for reading/writing and returns the file number
+    The process must have the access which the flags ask for, or be able to write to the directory to create the file
+    Created files are owned by the effective ids of the process and given the mode without the bits of the umask
+*/
+fn open(name: *const u8, flags: u64, mode: u64) -> i64 {
+    // Get name of file
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+    let process = current_process();
+
+    match vfs::resolve(&filepath) {
+        Ok(vnode) => {
+            let access = match Flags::from_bits_truncate(flags as u32 & O_ACCMODE) {
+                Flags::O_WRONLY => W_OK,
+                Flags::O_RDWR => R_OK | W_OK,
+                _ => R_OK,
+            };
+            if let Err(error) = vfs::check_access(vnode, &process.credentials, access) {
+                return -error_number(error);
+            }
 
-            return FILE_TABLE_COUNTER;
-        },
+            // Devices can refuse to be opened (such as a pty master which is already in use)
+            add_open_file(vnode)
+        }
         Err(error) => {
             let file_flags = Flags::from_bits_truncate(flags as u32);
+            if !file_flags.contains(Flags::O_CREAT) {
+                return -error_number(error);
+            }
 
-            if file_flags.contains(Flags::O_CREAT) {
-                let file = crate::fs::create_new_root_file(filepath);
-                unsafe {
-                    FILE_TABLE_COUNTER += 1;
-                    FILE_TABLE.lock().set(FILE_TABLE_COUNTER as usize, file);
-                    FILE_TABLE.free();
+            let credentials = process.credentials;
+            let created = vfs::check_creatable(&filepath, &credentials)
+                .and_then(|_| vfs::create(&filepath, FileType::File));
 
-                    return FILE_TABLE_COUNTER as i64;
+            match created {
+                Ok(vnode) => {
+                    // Filesystems without owners or permissions (such as FAT) keep their own
+                    let _ = vfs::chmod(vnode, mode as u32 & !process.umask);
+                    let _ = vfs::chown(vnode, credentials.euid, credentials.egid);
+                    add_open_file(vnode)
                 }
+                Err(error) => -error_number(error),
             }
-
-            1
         }
     }
-
-    // match filepath.as_bytes()[0] {
-    //     0x2F => {
-    //         panic!("Absolute");
-    //     }
-    //     _ => {
-    //         // Relative path within directory and are used when files are within same directory
-    //         panic!("Relative");
-    //     }
-    // }
 }
-/*
-    Writes given length of bytes from buffer to the file specified
-    Length must be above 0 and under max value
-*/
+// Writes given length of bytes from buffer to the file specified
 fn write(file: u64, buffer: *mut u8, length: u64) -> i64 {
     if length == 0 {
         return 0;
     }
-    if length > u64::max_value() {
-        return -1;
-    }
-
     match file {
-        1 => {
-            // 1 refers to stdout and writes to the console
-            for i in 0..(length) {
-                let character = unsafe { *buffer.offset(i as isize) };
-                print_serial!("{}", character as char);
-            }
-        }
-        2 => {
-            // 2 refers to stderr and writes to the console
-            for i in 0..(length) {
-                let character = unsafe { *buffer.offset(i as isize) };
-                print_serial!("{}", character as char);
+        1 | 2 if is_console(file) => {
+            // 1 refers to stdout and 2 to stderr, which both write to the console tty
+            let buffer = unsafe { core::slice::from_raw_parts(buffer, length as usize) };
+            if let Err(error) = tty::write(tty::CONSOLE_TTY, buffer) {
+                return -error_number(error);
             }
         }
         _ => {
-            // Other files can be written to through the fs
-            let wrapped_fd = FILE_TABLE.lock().get(file as usize);
-            FILE_TABLE.free();
+            // Other files can be written to through the vfs
+            let wrapped_fd = file_table().get(file as usize);
             match wrapped_fd {
                 Some(mut fd) => {
-                    fd.write(buffer, length as usize).unwrap();
-                    FILE_TABLE.lock().set(file as usize, fd.clone());
-                    FILE_TABLE.free();
+                    let buffer = unsafe { core::slice::from_raw_parts(buffer, length as usize) };
+                    return match fd.write(buffer) {
+                        Ok(count) => {
+                            file_table().set(file as usize, fd);
+                            count as i64
+                        }
+                        Err(error) => -error_number(error),
+                    };
                 }
                 None => {
-                    return -1;
+                    return -EBADF;
                 }
             }
         }
@@ -257,67 +435,69 @@ fn write(file: u64, buffer: *mut u8, length: u64) -> i64 {
 // Reads given length of bytes into the buffer
 fn read(file: u64, buffer: *mut u8, length: u64) -> i64 {
     match file {
-        0 => {
-            // stdin
-            panic!("STDIN\n");
+        0 if is_console(file) => {
+            // 0 refers to stdin which reads from the console tty (waiting for a whole line in canonical mode)
+            let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, length as usize) };
+            match tty::read(tty::CONSOLE_TTY, buffer) {
+                Ok(count) => count as i64,
+                Err(error) => -error_number(error),
+            }
         }
         _ => {
-            let wrapped_fd = FILE_TABLE.lock().get(file as usize);
-            FILE_TABLE.free();
+            let wrapped_fd = file_table().get(file as usize);
             match wrapped_fd {
                 Some(mut fd) => {
-                    fd.read(buffer, length as usize).unwrap();
-                    FILE_TABLE.lock().set(file as usize, fd.clone());
-                    FILE_TABLE.free();
-                }
-                None => {
-                    return -1;
+                    let buffer =
+                        unsafe { core::slice::from_raw_parts_mut(buffer, length as usize) };
+                    match fd.read(buffer) {
+                        Ok(count) => {
+                            file_table().set(file as usize, fd);
+                            count as i64
+                        }
+                        Err(error) => -error_number(error),
+                    }
                 }
+                None => -EBADF,
             }
         }
     }
-
-    length as i64
 }
 
 // Repositions the file offset for an open file depending on whence
 fn lseek(file: u64, offset: i64, whence: u64) -> i64 {
     if offset < 0 {
-        panic!("oh dear");
+        return -EINVAL;
     }
 
-    let wrapped_fd = FILE_TABLE.lock().get(file as usize);
-    FILE_TABLE.free();
+    let wrapped_fd = file_table().get(file as usize);
 
     match wrapped_fd {
         Some(mut fd) => {
             match whence {
                 0 => {
                     // SEEK_SET (beginning of file)
-                    fd.set_offset(offset);
-                    FILE_TABLE.lock().set(file as usize, fd.clone());
-                    FILE_TABLE.free();
+                    fd.offset = offset;
+                    file_table().set(file as usize, fd);
                 }
                 1 => {
                     // SEEK_CUR (current location of file)
-                    fd.set_offset(offset + fd.get_offset());
-                    FILE_TABLE.lock().set(file as usize, fd.clone());
-                    FILE_TABLE.free();
+                    fd.offset += offset;
+                    file_table().set(file as usize, fd);
                 }
                 2 => {
                     // SEEK_END (end of file)
-                    fd.set_offset(offset + fd.get_size() as i64);
-                    FILE_TABLE.lock().set(file as usize, fd.clone());
-                    FILE_TABLE.free();
+                    match fd.stat() {
+                        Ok(stat) => fd.offset = offset + stat.st_size,
+                        Err(error) => return -error_number(error),
+                    }
+                    file_table().set(file as usize, fd);
                 }
-                _ => panic!("Unknown Whence"),
+                _ => return -EINVAL,
             }
 
-            return fd.get_offset();
-        }
-        None => {
-            return -1;
+            fd.offset
         }
+        None => -EBADF,
     }
 }
 
@@ -459,3 +639,509 @@ fn send_message(cpid: u64, pid: u64, string_ptr: *const u8) -> i64 {
 
     0
 }
+
+/*
+    Waits upon or wakes processes sleeping on a futex word which is used by userland to implement mutexes/condition variables
+    FUTEX_WAIT sleeps if the word still equals value, FUTEX_WAKE wakes up to value processes
+*/
+fn futex(address: *const u32, op: u64, value: u64) -> i64 {
+    let result = match op {
+        futex::FUTEX_WAIT => futex::wait(address, value as u32),
+        futex::FUTEX_WAKE => futex::wake(address, value),
+        _ => Err(futex::UNKNOWN_OPERATION),
+    };
+
+    match result {
+        Ok(result) => result as i64,
+        Err(error) => -error_number(error),
+    }
+}
+
+/*
+    Creates a new thread within the current process which shares its address space and files
+    The thread starts at entry using the stack provided by userland and recieves arg as its first argument
+*/
+fn thread_create(entry: u64, stack_top: u64, arg: u64) -> i64 {
+    let current_process = PROCESS_SCHEDULAR.lock().get_current_process();
+    PROCESS_SCHEDULAR.free();
+
+    // The thread starts running at entry and its first push is just below the top of the stack
+    let stack_top = stack_top & !0xF;
+    if !paging::is_user_address(entry)
+        || !stack_top
+            .checked_sub(8)
+            .is_some_and(paging::is_user_address)
+    {
+        return -EFAULT;
+    }
+
+    match current_process {
+        Some(process) => {
+            let thread = Thread::init_user(&process, entry, stack_top, arg);
+            let result = PROCESS_SCHEDULAR.lock().add_thread(thread);
+            PROCESS_SCHEDULAR.free();
+
+            match result {
+                Ok(tid) => tid as i64,
+                Err(error) => {
+                    thread.free_kernel_stack();
+                    -error_number(error)
+                }
+            }
+        }
+        None => -ESRCH,
+    }
+}
+
+// Terminates the current thread, leaving the rest of the process running
+fn thread_exit() -> i64 {
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+
+    if let Some(thread) = current_thread {
+        PROCESS_SCHEDULAR
+            .lock()
+            .set_thread_state(thread.tid, ThreadState::Exited);
+        PROCESS_SCHEDULAR.free();
+    }
+
+    multitask::wait_for_exit();
+}
+
+// Returns the thread id of the current thread
+fn gettid() -> i64 {
+    let wrapped_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+    if let Some(thread) = wrapped_thread {
+        return thread.tid as i64;
+    }
+    -ESRCH
+}
+
+// Deletes a file
+fn unlink(name: *const u8) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    match vfs::check_removable(&filepath, &credentials).and_then(|_| vfs::unlink(&filepath)) {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Deletes an empty directory
+fn rmdir(name: *const u8) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    match vfs::check_removable(&filepath, &credentials).and_then(|_| vfs::rmdir(&filepath)) {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Moves a file or directory to a new path
+fn rename(old_name: *const u8, new_name: *const u8) -> i64 {
+    let (old_filepath, new_filepath) = match (get_filepath(old_name), get_filepath(new_name)) {
+        (Ok(old_filepath), Ok(new_filepath)) => (old_filepath, new_filepath),
+        (Err(error), _) | (_, Err(error)) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    let result = vfs::check_removable(&old_filepath, &credentials)
+        .and_then(|_| vfs::check_creatable(&new_filepath, &credentials))
+        .and_then(|_| vfs::rename(&old_filepath, &new_filepath));
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Gets metadata on an open file (the terminal fds are character devices)
+fn fstat(file: u64, stat: *mut Stat) -> i64 {
+    let metadata = match file {
+        0..=2 if is_console(file) => Stat {
+            st_mode: S_IFCHR | 0o620,
+            st_nlink: 1,
+            st_blksize: 1024,
+            ..Stat::default()
+        },
+        _ => match file_table().get(file as usize).map(|fd| fd.stat()) {
+            Some(Ok(metadata)) => metadata,
+            Some(Err(error)) => return -error_number(error),
+            None => return -EBADF,
+        },
+    };
+
+    unsafe {
+        *stat = metadata;
+    }
+    0
+}
+
+// Gets metadata on the file at a path (following symbolic links)
+fn stat(name: *const u8, stat: *mut Stat) -> i64 {
+    stat_path(name, stat, vfs::resolve)
+}
+
+// Same as stat, apart from a symbolic link itself being given rather than its target
+fn lstat(name: *const u8, stat: *mut Stat) -> i64 {
+    stat_path(name, stat, vfs::resolve_link)
+}
+
+fn stat_path(
+    name: *const u8,
+    stat: *mut Stat,
+    resolve: fn(&Path) -> Result<vfs::Vnode, &'static str>,
+) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    match resolve(&filepath).and_then(vfs::stat) {
+        Ok(metadata) => {
+            unsafe {
+                *stat = metadata;
+            }
+            0
+        }
+        Err(error) => -error_number(error),
+    }
+}
+
+// Reads directory records from an open directory into the buffer and returns the number of bytes read
+fn getdents(file: u64, buffer: *mut u8, length: u64) -> i64 {
+    match file_table().get(file as usize) {
+        Some(mut fd) => match fd.getdents(buffer, length as usize) {
+            Ok(count) => {
+                file_table().set(file as usize, fd);
+                count as i64
+            }
+            Err(error) => -error_number(error),
+        },
+        None => -EBADF,
+    }
+}
+
+// Changes the current working directory of the process (shared by all of its threads)
+fn chdir(name: *const u8) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let vnode = match vfs::resolve(&filepath) {
+        Ok(vnode) => vnode,
+        Err(error) => return -error_number(error),
+    };
+    match vfs::stat(vnode) {
+        Ok(metadata) if metadata.st_mode & S_IFMT == S_IFDIR => {}
+        Ok(_) => return -ENOTDIR,
+        Err(error) => return -error_number(error),
+    }
+
+    // Directories can only be entered by processes which can search them
+    if let Err(error) = vfs::check_access(vnode, &current_process().credentials, X_OK) {
+        return -error_number(error);
+    }
+
+    *cwd() = filepath;
+    0
+}
+
+// Copies the current working directory (null terminated) into the buffer and returns its length
+fn getcwd(buffer: *mut u8, size: u64) -> i64 {
+    let cwd = cwd().as_str();
+
+    if cwd.len() + 1 > size as usize {
+        return -ERANGE;
+    }
+
+    unsafe {
+        core::ptr::copy(cwd.as_ptr(), buffer, cwd.len());
+        *buffer.add(cwd.len()) = 0;
+    }
+    cwd.len() as i64
+}
+
+// Syncs every mounted filesystem and then writes every dirty sector within the buffer cache back to its disk
+fn sync() -> i64 {
+    let result = vfs::sync(None);
+    match result.and(crate::buffer_cache::sync(None)) {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Writes the filesystem holding an open file back to the disk (the cache doesn't track sectors per file)
+fn fsync(file: u64) -> i64 {
+    match file {
+        0..=2 if is_console(file) => 0,
+        _ => match file_table().get(file as usize) {
+            Some(fd) => match vfs::sync(Some(fd.vnode.mount)) {
+                Ok(()) => 0,
+                Err(error) => -error_number(error),
+            },
+            None => -EBADF,
+        },
+    }
+}
+
+// Sends a device specific request to an open device (such as getting the size of the framebuffer)
+fn ioctl(file: u64, request: u64, argument: u64) -> i64 {
+    let result = match file {
+        0..=2 if is_console(file) => tty::ioctl(tty::CONSOLE_TTY, request, argument),
+        _ => match file_table().get(file as usize) {
+            Some(fd) => vfs::ioctl(fd.vnode, request, argument),
+            None => return -EBADF,
+        },
+    };
+
+    match result {
+        Ok(result) => result,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Error numbers (matching newlib) which every syscall returns negated on failure
+const EPERM: i64 = 1;
+const ENOENT: i64 = 2;
+const ESRCH: i64 = 3;
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const EAGAIN: i64 = 11;
+const ENOMEM: i64 = 12;
+const EACCES: i64 = 13;
+const EFAULT: i64 = 14;
+const EBUSY: i64 = 16;
+const EEXIST: i64 = 17;
+const EXDEV: i64 = 18;
+const ENODEV: i64 = 19;
+const ENOTDIR: i64 = 20;
+const EISDIR: i64 = 21;
+const EINVAL: i64 = 22;
+const ENOTTY: i64 = 25;
+const EROFS: i64 = 30;
+const ERANGE: i64 = 34;
+const ENOSYS: i64 = 88;
+const ENOTEMPTY: i64 = 90;
+const ELOOP: i64 = 92;
+
+fn error_number(error: &'static str) -> i64 {
+    match error {
+        NOT_PERMITTED | LINK_TO_DIRECTORY => EPERM,
+        FILE_NOT_FOUND => ENOENT,
+        signal::NO_SUCH_PROCESS => ESRCH,
+        futex::VALUE_CHANGED | multitask::TOO_MANY_THREADS => EAGAIN,
+        futex::NO_FREE_QUEUES => ENOMEM,
+        PERMISSION_DENIED => EACCES,
+        futex::BAD_ADDRESS => EFAULT,
+        DEVICE_BUSY => EBUSY,
+        FILE_EXISTS => EEXIST,
+        CROSS_DEVICE_LINK => EXDEV,
+        NOT_A_DIRECTORY => ENOTDIR,
+        IS_A_DIRECTORY => EISDIR,
+        NOT_A_SYMLINK | INVALID_SYMLINK | INVALID_FILENAME | signal::INVALID_SIGNAL => EINVAL,
+        futex::MISALIGNED_ADDRESS | futex::UNKNOWN_OPERATION => EINVAL,
+        INAPPROPRIATE_IOCTL => ENOTTY,
+        READ_ONLY => EROFS,
+        DIRECTORY_NOT_EMPTY => ENOTEMPTY,
+        SYMLINK_LOOP => ELOOP,
+        _ => EIO,
+    }
+}
+
+// Adds a new name (hard link) for an existing file
+fn link(old_name: *const u8, new_name: *const u8) -> i64 {
+    let (old_filepath, new_filepath) = match (get_filepath(old_name), get_filepath(new_name)) {
+        (Ok(old_filepath), Ok(new_filepath)) => (old_filepath, new_filepath),
+        (Err(error), _) | (_, Err(error)) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    let result = vfs::check_creatable(&new_filepath, &credentials)
+        .and_then(|_| vfs::link(&old_filepath, &new_filepath));
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Creates a symbolic link at a path which points to the target (which is stored as given)
+fn symlink(target: *const u8, name: *const u8) -> i64 {
+    let target = crate::string::get_string_from_ptr(target);
+    let target = &target[0..target.len() - 1]; // Remove the null terminator
+
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    let result =
+        vfs::check_creatable(&filepath, &credentials).and_then(|_| vfs::symlink(target, &filepath));
+
+    match result {
+        Ok(_) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Copies the target of a symbolic link into the buffer (without a null terminator) and returns its length
+fn readlink(name: *const u8, buffer: *mut u8, size: u64) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, size as usize) };
+    match vfs::resolve_link(&filepath).and_then(|vnode| vfs::readlink(vnode, buffer)) {
+        Ok(length) => length as i64,
+        Err(error) => -error_number(error),
+    }
+}
+
+fn getuid() -> i64 {
+    current_process().credentials.uid as i64
+}
+
+fn geteuid() -> i64 {
+    current_process().credentials.euid as i64
+}
+
+fn getgid() -> i64 {
+    current_process().credentials.gid as i64
+}
+
+fn getegid() -> i64 {
+    current_process().credentials.egid as i64
+}
+
+// Replaces the credentials of the process (shared by all of its threads)
+fn set_credentials(credentials: Credentials) {
+    let mut process = current_process();
+    process.credentials = credentials;
+    PROCESS_SCHEDULAR.lock().update_process(process);
+    PROCESS_SCHEDULAR.free();
+}
+
+/*
+    Root sets the real, effective and saved user ids (so can never get root back)
+    Anyone else can only set the effective user id to their real or saved user id
+*/
+fn setuid(uid: u64) -> i64 {
+    let mut credentials = current_process().credentials;
+    let uid = uid as u16;
+
+    if credentials.euid == 0 {
+        credentials.uid = uid;
+        credentials.suid = uid;
+    } else if uid != credentials.uid && uid != credentials.suid {
+        return -EPERM;
+    }
+
+    credentials.euid = uid;
+    set_credentials(credentials);
+    0
+}
+
+// Only changes the effective user id, so a process can drop root and get it back later through the saved user id
+fn seteuid(euid: u64) -> i64 {
+    let mut credentials = current_process().credentials;
+    let euid = euid as u16;
+
+    if credentials.euid != 0 && euid != credentials.uid && euid != credentials.suid {
+        return -EPERM;
+    }
+
+    credentials.euid = euid;
+    set_credentials(credentials);
+    0
+}
+
+// Same as setuid but for group ids (which root is still needed for)
+fn setgid(gid: u64) -> i64 {
+    let mut credentials = current_process().credentials;
+    let gid = gid as u16;
+
+    if credentials.euid == 0 {
+        credentials.gid = gid;
+        credentials.sgid = gid;
+    } else if gid != credentials.gid && gid != credentials.sgid {
+        return -EPERM;
+    }
+
+    credentials.egid = gid;
+    set_credentials(credentials);
+    0
+}
+
+fn setegid(egid: u64) -> i64 {
+    let mut credentials = current_process().credentials;
+    let egid = egid as u16;
+
+    if credentials.euid != 0 && egid != credentials.gid && egid != credentials.sgid {
+        return -EPERM;
+    }
+
+    credentials.egid = egid;
+    set_credentials(credentials);
+    0
+}
+
+// Sets the permission bits which are removed from files the process creates and returns the old mask
+fn umask(mask: u64) -> i64 {
+    let mut process = current_process();
+    let old_mask = process.umask;
+    process.umask = mask as u32 & 0o777;
+    PROCESS_SCHEDULAR.lock().update_process(process);
+    PROCESS_SCHEDULAR.free();
+    old_mask as i64
+}
+
+// Changes the permission bits of a file (only its owner or root can)
+fn chmod(name: *const u8, mode: u64) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    let credentials = current_process().credentials;
+    let result = vfs::resolve(&filepath).and_then(|vnode| {
+        if credentials.euid != 0 && vfs::stat(vnode)?.st_uid != credentials.euid {
+            return Err(NOT_PERMITTED);
+        }
+        vfs::chmod(vnode, mode as u32)
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
+
+// Changes the owner and group of a file (only root can)
+fn chown(name: *const u8, uid: u64, gid: u64) -> i64 {
+    let filepath = match get_filepath(name) {
+        Ok(filepath) => filepath,
+        Err(error) => return -error_number(error),
+    };
+
+    if current_process().credentials.euid != 0 {
+        return -EPERM;
+    }
+
+    match vfs::resolve(&filepath).and_then(|vnode| vfs::chown(vnode, uid as u16, gid as u16)) {
+        Ok(()) => 0,
+        Err(error) => -error_number(error),
+    }
+}
@@ -0,0 +1,585 @@
+// src/tmpfs.rs
+
+/*
+    Temporary filesystem which is held entirely within memory (nothing is ever written to a disk)
+    Nodes are kept within a table which is indexed by inode number (inode 0 is never used)
+    Each node owns a single kernel heap buffer which is grown as it is written to:
+        Files hold their contents
+        Symbolic links hold the path of their target
+        Directories hold an array of entries (unused entries have an inode of 0 so indexes stay the same whilst reading)
+    Nodes count the links to them (directory entries, plus "." and the ".." of subdirectories for directories)
+    Nodes also count the processes which have them open, so a file which is unlinked whilst open can still be used
+    Once the last link to a node is removed and nothing has it open, its buffer is freed and the inode can be reused
+    New nodes are owned by root, and whoever creates them sets their owner and permissions afterwards
+*/
+
+use crate::allocator::{kfree, kmalloc};
+use crate::vfs::{
+    DirectoryEntry, FileSystem, FileType, Inode, Stat, DIRECTORY_NOT_EMPTY, FILE_EXISTS,
+    FILE_NOT_FOUND, INVALID_FILENAME, IS_A_DIRECTORY, LINK_TO_DIRECTORY, NOT_A_DIRECTORY,
+    NOT_A_SYMLINK, S_IFDIR, S_IFLNK, S_IFREG, S_IPERM,
+};
+use core::mem::size_of;
+
+const ROOT_INODE: Inode = 1;
+const INITIAL_NODE_CAPACITY: usize = 64;
+const MAX_NAME_LENGTH: usize = 255;
+
+// Reported as the block size to userland (buffers aren't actually split into blocks)
+const BLOCK_SIZE: i64 = 4096;
+
+#[derive(Copy, Clone)]
+struct Node {
+    file_type: FileType,
+    links: u16,
+    open_count: u16, // Number of processes with the node open
+    mode: u32,       // Permission bits (the file type bits come from the file type)
+    uid: u16,
+    gid: u16,
+    parent: Inode, // Directory which holds a directory (unused for files as they can have many)
+    size: u64,     // Number of bytes used within the buffer
+    capacity: u64, // Number of bytes allocated for the buffer
+    data: *mut u8,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct TmpfsDirectoryEntry {
+    inode: Inode, // 0 for unused entries
+    name_length: usize,
+    name: [u8; MAX_NAME_LENGTH],
+}
+
+pub struct Tmpfs {
+    nodes: *mut Option<Node>,
+    capacity: usize, // Number of nodes the table can hold before it must grow
+}
+
+impl Node {
+    const fn new(file_type: FileType, parent: Inode) -> Node {
+        Node {
+            file_type,
+            links: match file_type {
+                FileType::Directory => 2,
+                _ => 1,
+            },
+            open_count: 0,
+            mode: match file_type {
+                FileType::Directory => 0o755,
+                FileType::Symlink => 0o777,
+                _ => 0o644,
+            },
+            uid: 0,
+            gid: 0,
+            parent,
+            size: 0,
+            capacity: 0,
+            data: core::ptr::null_mut(),
+        }
+    }
+
+    // Makes sure the buffer can hold size bytes, doubling the capacity so the buffer isn't copied on every write
+    fn reserve(&mut self, size: u64) {
+        if size <= self.capacity {
+            return;
+        }
+
+        let capacity = size.max(self.capacity * 2).max(64);
+        let data = kmalloc(capacity) as *mut u8;
+        unsafe {
+            core::ptr::write_bytes(data, 0, capacity as usize);
+            if !self.data.is_null() {
+                core::ptr::copy_nonoverlapping(self.data, data, self.size as usize);
+                kfree(self.data as *mut u64);
+            }
+        }
+
+        self.data = data;
+        self.capacity = capacity;
+    }
+
+    fn free(&mut self) {
+        if !self.data.is_null() {
+            kfree(self.data as *mut u64);
+        }
+        self.data = core::ptr::null_mut();
+        self.size = 0;
+        self.capacity = 0;
+    }
+
+    fn entries(&self) -> &[TmpfsDirectoryEntry] {
+        if self.data.is_null() {
+            return &[];
+        }
+
+        unsafe {
+            core::slice::from_raw_parts(
+                self.data as *const TmpfsDirectoryEntry,
+                self.size as usize / size_of::<TmpfsDirectoryEntry>(),
+            )
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut [TmpfsDirectoryEntry] {
+        if self.data.is_null() {
+            return &mut [];
+        }
+
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.data as *mut TmpfsDirectoryEntry,
+                self.size as usize / size_of::<TmpfsDirectoryEntry>(),
+            )
+        }
+    }
+
+    fn find_entry(&self, name: &str) -> Option<&TmpfsDirectoryEntry> {
+        self.entries()
+            .iter()
+            .find(|entry| entry.inode != 0 && entry.name() == name.as_bytes())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries().iter().all(|entry| entry.inode == 0)
+    }
+
+    // Uses the first unused entry, or adds one onto the end of the directory
+    fn add_entry(&mut self, name: &str, inode: Inode) {
+        let mut entry = TmpfsDirectoryEntry {
+            inode,
+            name_length: name.len(),
+            name: [0; MAX_NAME_LENGTH],
+        };
+        entry.name[0..name.len()].copy_from_slice(name.as_bytes());
+
+        if let Some(unused_entry) = self.entries_mut().iter_mut().find(|entry| entry.inode == 0) {
+            *unused_entry = entry;
+            return;
+        }
+
+        let size = self.size + size_of::<TmpfsDirectoryEntry>() as u64;
+        self.reserve(size);
+        self.size = size;
+        let entries = self.entries_mut();
+        entries[entries.len() - 1] = entry;
+    }
+
+    // Entry is marked as unused rather than removed so the indexes of later entries don't change
+    fn remove_entry(&mut self, name: &str) -> Option<Inode> {
+        let entry = self
+            .entries_mut()
+            .iter_mut()
+            .find(|entry| entry.inode != 0 && entry.name() == name.as_bytes())?;
+        let inode = entry.inode;
+        entry.inode = 0;
+        Some(inode)
+    }
+}
+
+impl TmpfsDirectoryEntry {
+    fn name(&self) -> &[u8] {
+        &self.name[0..self.name_length]
+    }
+}
+
+impl Tmpfs {
+    // Creates an empty filesystem which only holds the root directory
+    pub fn new() -> *mut Tmpfs {
+        let tmpfs = kmalloc(size_of::<Tmpfs>() as u64) as *mut Tmpfs;
+        unsafe {
+            tmpfs.write(Tmpfs {
+                nodes: core::ptr::null_mut(),
+                capacity: 0,
+            });
+            (*tmpfs).allocate_node(FileType::Directory, ROOT_INODE); // Inode 0 is never used
+            (*tmpfs).allocate_node(FileType::Directory, ROOT_INODE);
+        }
+        tmpfs
+    }
+
+    fn get_node(&self, inode: Inode) -> Result<&Node, &'static str> {
+        if inode == 0 || inode as usize >= self.capacity {
+            return Err("Inode doesn't exist");
+        }
+
+        match unsafe { &*self.nodes.add(inode as usize) } {
+            Some(node) => Ok(node),
+            None => Err("Inode doesn't exist"),
+        }
+    }
+
+    fn get_node_mut(&mut self, inode: Inode) -> Result<&mut Node, &'static str> {
+        if inode == 0 || inode as usize >= self.capacity {
+            return Err("Inode doesn't exist");
+        }
+
+        match unsafe { &mut *self.nodes.add(inode as usize) } {
+            Some(node) => Ok(node),
+            None => Err("Inode doesn't exist"),
+        }
+    }
+
+    fn get_directory(&self, inode: Inode) -> Result<&Node, &'static str> {
+        let node = self.get_node(inode)?;
+        if node.file_type != FileType::Directory {
+            return Err(NOT_A_DIRECTORY);
+        }
+        Ok(node)
+    }
+
+    fn get_directory_mut(&mut self, inode: Inode) -> Result<&mut Node, &'static str> {
+        self.get_directory(inode)?;
+        self.get_node_mut(inode)
+    }
+
+    // Contents of directories and symbolic links can't be read/written like a file
+    fn get_file(&self, inode: Inode) -> Result<&Node, &'static str> {
+        let node = self.get_node(inode)?;
+        match node.file_type {
+            FileType::Directory => Err(IS_A_DIRECTORY),
+            FileType::Symlink => Err("Is a symbolic link"),
+            _ => Ok(node),
+        }
+    }
+
+    fn get_file_mut(&mut self, inode: Inode) -> Result<&mut Node, &'static str> {
+        self.get_file(inode)?;
+        self.get_node_mut(inode)
+    }
+
+    // Finds an unused inode (doubling the size of the table when it's full)
+    fn allocate_node(&mut self, file_type: FileType, parent: Inode) -> Inode {
+        let free_inode = (0..self.capacity).find(|&i| unsafe { (*self.nodes.add(i)).is_none() });
+
+        let inode = match free_inode {
+            Some(inode) => inode,
+            None => {
+                let capacity = (self.capacity * 2).max(INITIAL_NODE_CAPACITY);
+                let nodes =
+                    kmalloc((capacity * size_of::<Option<Node>>()) as u64) as *mut Option<Node>;
+                unsafe {
+                    for i in 0..capacity {
+                        nodes.add(i).write(None);
+                    }
+                    if !self.nodes.is_null() {
+                        core::ptr::copy_nonoverlapping(self.nodes, nodes, self.capacity);
+                        kfree(self.nodes as *mut u64);
+                    }
+                }
+
+                let inode = self.capacity;
+                self.nodes = nodes;
+                self.capacity = capacity;
+                inode
+            }
+        };
+
+        unsafe {
+            *self.nodes.add(inode) = Some(Node::new(file_type, parent));
+        }
+        inode as Inode
+    }
+
+    // Removes a link to a node, freeing the node once nothing links to it or has it open
+    fn release_node(&mut self, inode: Inode) -> Result<(), &'static str> {
+        self.get_node_mut(inode)?.links -= 1;
+        self.free_if_unused(inode)
+    }
+
+    fn free_if_unused(&mut self, inode: Inode) -> Result<(), &'static str> {
+        let node = self.get_node_mut(inode)?;
+        if node.links == 0 && node.open_count == 0 {
+            node.free();
+            unsafe {
+                *self.nodes.add(inode as usize) = None;
+            }
+        }
+        Ok(())
+    }
+
+    // Checks whether a directory is the given directory or is somewhere within it
+    fn is_within(&self, directory: Inode, ancestor: Inode) -> Result<bool, &'static str> {
+        let mut current = directory;
+        loop {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if current == ROOT_INODE {
+                return Ok(false);
+            }
+            current = self.get_node(current)?.parent;
+        }
+    }
+}
+
+fn check_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name == "." || name == ".." || name.len() > MAX_NAME_LENGTH {
+        return Err(INVALID_FILENAME);
+    }
+    Ok(())
+}
+
+impl FileSystem for Tmpfs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> Inode {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str> {
+        match self.get_directory(directory)?.find_entry(name) {
+            Some(entry) => Ok(entry.inode),
+            None => Err(FILE_NOT_FOUND),
+        }
+    }
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str> {
+        let node = self.get_node(inode)?;
+
+        Ok(Stat {
+            st_ino: inode as u16,
+            st_mode: node.mode
+                | match node.file_type {
+                    FileType::Directory => S_IFDIR,
+                    FileType::Symlink => S_IFLNK,
+                    _ => S_IFREG,
+                },
+            st_nlink: node.links,
+            st_uid: node.uid,
+            st_gid: node.gid,
+            st_size: node.size as i64,
+            st_blksize: BLOCK_SIZE,
+            st_blocks: (node.capacity as i64 + 511) / 512,
+            ..Stat::default()
+        })
+    }
+
+    fn read(
+        &mut self,
+        inode: Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let node = self.get_file(inode)?;
+        if offset >= node.size {
+            return Ok(0);
+        }
+
+        let count = buffer.len().min((node.size - offset) as usize);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                node.data.add(offset as usize),
+                buffer.as_mut_ptr(),
+                count,
+            );
+        }
+        Ok(count)
+    }
+
+    // Writes past the end of the file grow it (any gap is filled with zeroes)
+    fn write(&mut self, inode: Inode, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        let node = self.get_file_mut(inode)?;
+
+        let end = offset + buffer.len() as u64;
+        node.reserve(end);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buffer.as_ptr(),
+                node.data.add(offset as usize),
+                buffer.len(),
+            );
+        }
+        node.size = node.size.max(end);
+        Ok(buffer.len())
+    }
+
+    // Index is the position of the entry within the directory's array of entries
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+        let entries = self.get_directory(directory)?.entries();
+
+        for (i, entry) in entries.iter().enumerate().skip(index as usize) {
+            if entry.inode == 0 {
+                continue;
+            }
+
+            let node = self.get_node(entry.inode)?;
+            let directory_entry =
+                DirectoryEntry::new(entry.inode, node.file_type, node.size, entry.name());
+            return Ok(Some((directory_entry, i as u64 + 1)));
+        }
+
+        Ok(None)
+    }
+
+    fn create(
+        &mut self,
+        directory: Inode,
+        name: &str,
+        file_type: FileType,
+    ) -> Result<Inode, &'static str> {
+        check_name(name)?;
+        if file_type != FileType::File && file_type != FileType::Directory {
+            return Err("tmpfs can only hold files and directories");
+        }
+        if self.get_directory(directory)?.find_entry(name).is_some() {
+            return Err(FILE_EXISTS);
+        }
+
+        // The node table may move when a node is allocated, so the directory is found again afterwards
+        let inode = self.allocate_node(file_type, directory);
+        let parent = self.get_directory_mut(directory)?;
+        parent.add_entry(name, inode);
+        if file_type == FileType::Directory {
+            parent.links += 1;
+        }
+        Ok(inode)
+    }
+
+    fn symlink(
+        &mut self,
+        directory: Inode,
+        name: &str,
+        target: &str,
+    ) -> Result<Inode, &'static str> {
+        check_name(name)?;
+        if self.get_directory(directory)?.find_entry(name).is_some() {
+            return Err(FILE_EXISTS);
+        }
+
+        let inode = self.allocate_node(FileType::Symlink, directory);
+        let node = self.get_node_mut(inode)?;
+        node.reserve(target.len() as u64);
+        unsafe {
+            core::ptr::copy_nonoverlapping(target.as_ptr(), node.data, target.len());
+        }
+        node.size = target.len() as u64;
+
+        self.get_directory_mut(directory)?.add_entry(name, inode);
+        Ok(inode)
+    }
+
+    // Target is truncated if the buffer is too small (it isn't null terminated)
+    fn readlink(&mut self, inode: Inode, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let node = self.get_node(inode)?;
+        if node.file_type != FileType::Symlink {
+            return Err(NOT_A_SYMLINK);
+        }
+
+        let count = buffer.len().min(node.size as usize);
+        unsafe {
+            core::ptr::copy_nonoverlapping(node.data, buffer.as_mut_ptr(), count);
+        }
+        Ok(count)
+    }
+
+    fn open(&mut self, inode: Inode) -> Result<(), &'static str> {
+        self.get_node_mut(inode)?.open_count += 1;
+        Ok(())
+    }
+
+    // Unlinked nodes are only freed once the last process using them closes them
+    fn close(&mut self, inode: Inode) {
+        if let Ok(node) = self.get_node_mut(inode) {
+            node.open_count = node.open_count.saturating_sub(1);
+            let _ = self.free_if_unused(inode);
+        }
+    }
+
+    fn chmod(&mut self, inode: Inode, mode: u32) -> Result<(), &'static str> {
+        self.get_node_mut(inode)?.mode = mode & S_IPERM;
+        Ok(())
+    }
+
+    fn chown(&mut self, inode: Inode, uid: u16, gid: u16) -> Result<(), &'static str> {
+        let node = self.get_node_mut(inode)?;
+        node.uid = uid;
+        node.gid = gid;
+        Ok(())
+    }
+
+    fn link(&mut self, directory: Inode, name: &str, inode: Inode) -> Result<(), &'static str> {
+        check_name(name)?;
+        if self.get_node(inode)?.file_type == FileType::Directory {
+            return Err(LINK_TO_DIRECTORY);
+        }
+        if self.get_directory(directory)?.find_entry(name).is_some() {
+            return Err(FILE_EXISTS);
+        }
+
+        self.get_directory_mut(directory)?.add_entry(name, inode);
+        self.get_node_mut(inode)?.links += 1;
+        Ok(())
+    }
+
+    // Storage is only freed once every link has been removed
+    fn unlink(&mut self, directory: Inode, name: &str) -> Result<(), &'static str> {
+        let inode = self.lookup(directory, name)?;
+        if self.get_node(inode)?.file_type == FileType::Directory {
+            return Err(IS_A_DIRECTORY);
+        }
+
+        self.get_directory_mut(directory)?.remove_entry(name);
+        self.release_node(inode)
+    }
+
+    fn rmdir(&mut self, directory: Inode, name: &str) -> Result<(), &'static str> {
+        let inode = self.lookup(directory, name)?;
+        if !self.get_directory(inode)?.is_empty() {
+            return Err(DIRECTORY_NOT_EMPTY);
+        }
+
+        // Directory loses the ".." link whilst the removed directory loses its entry and "."
+        let parent = self.get_directory_mut(directory)?;
+        parent.remove_entry(name);
+        parent.links -= 1;
+        self.get_node_mut(inode)?.links = 1;
+        self.release_node(inode)
+    }
+
+    // Anything already at the new name is replaced (directories can only replace empty directories)
+    fn rename(
+        &mut self,
+        old_directory: Inode,
+        old_name: &str,
+        new_directory: Inode,
+        new_name: &str,
+    ) -> Result<(), &'static str> {
+        check_name(new_name)?;
+        let inode = self.lookup(old_directory, old_name)?;
+        let is_directory = self.get_node(inode)?.file_type == FileType::Directory;
+        if is_directory && self.is_within(new_directory, inode)? {
+            return Err("Cannot move a directory within itself");
+        }
+
+        if let Ok(existing) = self.lookup(new_directory, new_name) {
+            if existing == inode {
+                return Ok(());
+            }
+
+            match (is_directory, self.get_node(existing)?.file_type) {
+                (true, FileType::Directory) => self.rmdir(new_directory, new_name)?,
+                (true, _) => return Err(NOT_A_DIRECTORY),
+                (false, FileType::Directory) => return Err(IS_A_DIRECTORY),
+                (false, _) => self.unlink(new_directory, new_name)?,
+            }
+        }
+
+        self.get_directory_mut(old_directory)?
+            .remove_entry(old_name);
+        self.get_directory_mut(new_directory)?
+            .add_entry(new_name, inode);
+
+        if is_directory && old_directory != new_directory {
+            self.get_node_mut(old_directory)?.links -= 1;
+            self.get_node_mut(new_directory)?.links += 1;
+            self.get_node_mut(inode)?.parent = new_directory;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,589 @@
+// src/tty.rs
+
+/*
+    Terminals (ttys) sit between a device which gives input and shows output (such as the keyboard and serial console) and the processes using it
+    Input passes through the line discipline, which is controlled by the termios settings of the tty:
+        Canonical mode gathers input into lines which can be edited (erase, kill and word erase) and are only readable once complete
+        Non canonical (raw) mode passes each byte straight through, with reads waiting for VMIN bytes
+        Typed characters are echoed, whilst the interrupt, quit and suspend characters signal the foreground process
+    Output has each newline turned into a carriage return and newline (ONLCR) before being given to the device
+    Reads block until there is input, so a tty can only be read from within a syscall
+    Once a tty is hung up (its pty master was closed), reads give the end of the file and writes fail
+    Settings and flags match Linux, apart from speeds which are the baud rate itself
+*/
+
+use crate::multitask::{self, WaitQueue, PROCESS_SCHEDULAR};
+use crate::ring_buffer::RingBuffer;
+use crate::signal::{self, SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGWINCH};
+use crate::spinlock::Lock;
+use crate::vfs::INAPPROPRIATE_IOCTL;
+use crate::vga_text;
+use crate::writer::Writer;
+use crate::CONSOLE;
+
+const MAX_TTYS: usize = 16;
+const INPUT_BUFFER_SIZE: usize = 1024;
+const MAX_CANON: usize = 255; // Longest line (including its newline) which can be edited
+const MAX_LINES: usize = 64; // Number of complete lines which can wait to be read
+
+// Keyboard and serial console, which is stdin, stdout and stderr of every process
+pub const CONSOLE_TTY: usize = 0;
+
+// Requests which every tty supports through ioctl
+pub const TCGETS: u64 = 0x5401;
+pub const TCSETS: u64 = 0x5402;
+pub const TCSETSW: u64 = 0x5403; // Waits for output to drain first
+pub const TCSETSF: u64 = 0x5404; // Waits for output to drain and discards unread input first
+pub const TIOCGPGRP: u64 = 0x540f;
+pub const TIOCSPGRP: u64 = 0x5410;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const TIOCSWINSZ: u64 = 0x5414;
+
+// Input flags
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+
+// Output flags
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+
+// Control flags (the character size, stop bits and parity are used by serial ports)
+pub const CSIZE: u32 = 0o60;
+const CS8: u32 = 0o60;
+pub const CSTOPB: u32 = 0o100;
+const CREAD: u32 = 0o200;
+pub const PARENB: u32 = 0o400;
+pub const PARODD: u32 = 0o1000;
+
+// Local flags
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const IEXTEN: u32 = 0o100000;
+
+// Indexes of the special characters
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VWERASE: usize = 14;
+
+const NCCS: usize = 32;
+const DEFAULT_SPEED: u32 = 38400;
+
+// Matches struct termios within userland
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_cc: [u8; NCCS],
+    pub c_ispeed: u32,
+    pub c_ospeed: u32,
+}
+
+impl Termios {
+    // Canonical mode with echo and signals, where backspace sends DEL (^?)
+    const fn default() -> Termios {
+        let mut c_cc = [0; NCCS];
+        c_cc[VINTR] = 0x03; // ^C
+        c_cc[VQUIT] = 0x1c; // ^\
+        c_cc[VERASE] = 0x7f; // ^?
+        c_cc[VKILL] = 0x15; // ^U
+        c_cc[VEOF] = 0x04; // ^D
+        c_cc[VMIN] = 1;
+        c_cc[VSUSP] = 0x1a; // ^Z
+        c_cc[VWERASE] = 0x17; // ^W
+
+        Termios {
+            c_iflag: ICRNL,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: CS8 | CREAD,
+            c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | IEXTEN,
+            c_cc,
+            c_ispeed: DEFAULT_SPEED,
+            c_ospeed: DEFAULT_SPEED,
+        }
+    }
+}
+
+/*
+    Size of the terminal in characters (and pixels when known), which is 0 until it is set
+    Matches struct winsize within userland
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+#[derive(Copy, Clone)]
+struct Tty {
+    index: usize,
+    termios: Termios,
+    output: fn(usize, &[u8]), // Gives output to the device (along with the index of the tty)
+    configure: Option<fn(usize, &Termios)>, // Applies new settings to the hardware (such as the speed of a serial port)
+    line: [u8; MAX_CANON],                  // Line being edited in canonical mode
+    line_length: usize,
+    input: RingBuffer<INPUT_BUFFER_SIZE>, // Input which can be read
+    line_lengths: RingBuffer<MAX_LINES>, // Length of each complete line held within input (canonical mode)
+    partial_line: usize,                 // Bytes left of a line which was only partly read
+    readers: WaitQueue,
+    foreground: Option<u64>, // Process which is sent the signals typed at the terminal
+    window_size: WinSize,
+    hung_up: bool,
+}
+
+static TTYS: Lock<[Option<Tty>; MAX_TTYS]> = Lock::new([None; MAX_TTYS]);
+
+impl Tty {
+    fn new(index: usize, output: fn(usize, &[u8]), configure: Option<fn(usize, &Termios)>) -> Tty {
+        Tty {
+            index,
+            termios: Termios::default(),
+            output,
+            configure,
+            line: [0; MAX_CANON],
+            line_length: 0,
+            input: RingBuffer::new(),
+            line_lengths: RingBuffer::new(),
+            partial_line: 0,
+            readers: WaitQueue::new(),
+            foreground: None,
+            window_size: WinSize {
+                ws_row: 0,
+                ws_col: 0,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            },
+            hung_up: false,
+        }
+    }
+
+    // Processes output (which includes echoes) and gives it to the device
+    fn write(&self, buffer: &[u8]) {
+        let oflag = self.termios.c_oflag;
+        if oflag & OPOST == 0 || oflag & ONLCR == 0 {
+            (self.output)(self.index, buffer);
+            return;
+        }
+
+        for line in buffer.split_inclusive(|&byte| byte == b'\n') {
+            match line.split_last() {
+                Some((b'\n', text)) => {
+                    (self.output)(self.index, text);
+                    (self.output)(self.index, b"\r\n");
+                }
+                _ => (self.output)(self.index, line),
+            }
+        }
+    }
+
+    // Control characters (apart from tabs and newlines) are echoed as ^ followed by a letter
+    fn is_shown_as_control(&self, byte: u8) -> bool {
+        self.termios.c_lflag & ECHOCTL != 0
+            && (byte < 0x20 && byte != b'\t' && byte != b'\n' || byte == 0x7f)
+    }
+
+    fn echo(&self, byte: u8) {
+        let lflag = self.termios.c_lflag;
+        if byte == b'\n' {
+            if lflag & (ECHO | ECHONL) != 0 {
+                self.write(b"\n");
+            }
+        } else if lflag & ECHO != 0 {
+            match self.is_shown_as_control(byte) {
+                true => self.write(&[b'^', byte ^ 0x40]),
+                false => self.write(&[byte]),
+            }
+        }
+    }
+
+    // Removes the last character of the line being edited, rubbing it out on the screen when ECHOE is set
+    fn erase_character(&mut self) -> Option<u8> {
+        if self.line_length == 0 {
+            return None;
+        }
+
+        self.line_length -= 1;
+        let byte = self.line[self.line_length];
+
+        if self.termios.c_lflag & (ECHO | ECHOE) == ECHO | ECHOE {
+            self.write(b"\x08 \x08");
+            if self.is_shown_as_control(byte) {
+                self.write(b"\x08 \x08");
+            }
+        }
+        Some(byte)
+    }
+
+    // Makes the line being edited readable (lines which don't fit are dropped)
+    fn complete_line(&mut self) {
+        if !self.line_lengths.is_full() && INPUT_BUFFER_SIZE - self.input.len() >= self.line_length
+        {
+            for &byte in &self.line[0..self.line_length] {
+                self.input.push(byte);
+            }
+            self.line_lengths.push(self.line_length as u8);
+        }
+        self.line_length = 0;
+    }
+
+    fn flush_input(&mut self) {
+        self.input.clear();
+        self.line_lengths.clear();
+        self.partial_line = 0;
+        self.line_length = 0;
+    }
+
+    // Passes a byte of input through the line discipline, returning the signal it generates
+    fn receive(&mut self, mut byte: u8) -> Option<u64> {
+        let Termios {
+            c_iflag: iflag,
+            c_lflag: lflag,
+            c_cc: cc,
+            ..
+        } = self.termios;
+
+        // Serial terminals send a carriage return for the enter key
+        if byte == b'\r' {
+            if iflag & IGNCR != 0 {
+                return None;
+            }
+            if iflag & ICRNL != 0 {
+                byte = b'\n';
+            }
+        } else if byte == b'\n' && iflag & INLCR != 0 {
+            byte = b'\r';
+        }
+
+        if lflag & ISIG != 0 {
+            let signal = match byte {
+                _ if byte == cc[VINTR] => Some(SIGINT),
+                _ if byte == cc[VQUIT] => Some(SIGQUIT),
+                _ if byte == cc[VSUSP] => Some(SIGTSTP),
+                _ => None,
+            };
+
+            if signal.is_some() {
+                if lflag & NOFLSH == 0 {
+                    self.flush_input();
+                }
+                self.echo(byte);
+                return signal;
+            }
+        }
+
+        if lflag & ICANON == 0 {
+            self.input.push(byte);
+            self.echo(byte);
+            return None;
+        }
+
+        match byte {
+            _ if byte == cc[VERASE] => {
+                self.erase_character();
+            }
+            _ if byte == cc[VKILL] => {
+                while self.erase_character().is_some() {}
+                if lflag & ECHOE == 0 && lflag & ECHOK != 0 {
+                    self.echo(byte);
+                    self.write(b"\n");
+                }
+            }
+            // Trailing spaces are erased along with the word before them
+            _ if byte == cc[VWERASE] && lflag & IEXTEN != 0 => {
+                while self.line[..self.line_length].ends_with(b" ") {
+                    self.erase_character();
+                }
+                while self.line_length > 0 && self.line[self.line_length - 1] != b' ' {
+                    self.erase_character();
+                }
+            }
+            // End of file gives the line without a newline (so an empty line reads as the end of the file)
+            _ if byte == cc[VEOF] => self.complete_line(),
+            b'\n' => {
+                self.line[self.line_length] = byte;
+                self.line_length += 1;
+                self.echo(byte);
+                self.complete_line();
+            }
+            // Room is always left for the newline
+            _ if self.line_length < MAX_CANON - 1 => {
+                self.line[self.line_length] = byte;
+                self.line_length += 1;
+                self.echo(byte);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // Moves input into the buffer, or returns None if the reader must wait for more
+    fn take_input(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        if self.termios.c_lflag & ICANON != 0 {
+            // Reads never go past the end of a line
+            let length = match self.partial_line {
+                0 => self.line_lengths.pop()? as usize,
+                partial => partial,
+            };
+            let count = length.min(buffer.len());
+            let count = self.input.read(&mut buffer[0..count]);
+            self.partial_line = length - count;
+            return Some(count);
+        }
+
+        // VMIN of 0 never waits (VTIME isn't supported)
+        let minimum = (self.termios.c_cc[VMIN] as usize).min(buffer.len());
+        match self.input.len() >= minimum {
+            true => Some(self.input.read(buffer)),
+            false => None,
+        }
+    }
+
+    // Input which is waiting stays readable when switching between canonical and raw mode
+    fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.termios.c_lflag & ICANON != 0;
+        let is_canonical = termios.c_lflag & ICANON != 0;
+
+        if was_canonical && !is_canonical {
+            self.complete_line();
+            self.line_lengths.clear();
+            self.partial_line = 0;
+        } else if !was_canonical && is_canonical {
+            let mut remaining = self.input.len();
+            while remaining > 0 {
+                let length = remaining.min(MAX_CANON);
+                self.line_lengths.push(length as u8);
+                remaining -= length;
+            }
+        }
+
+        self.termios = termios;
+        if let Some(configure) = self.configure {
+            configure(self.index, &self.termios);
+        }
+    }
+
+    // Carries out a request, returning the signal it generates
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<Option<u64>, &'static str> {
+        match request {
+            TCGETS => unsafe {
+                *(argument as *mut Termios) = self.termios;
+            },
+            // Output is given to the device straight away so there is never any to drain
+            TCSETS | TCSETSW | TCSETSF => {
+                if request == TCSETSF {
+                    self.flush_input();
+                }
+                self.set_termios(unsafe { *(argument as *const Termios) });
+            }
+            // There are no process groups, so the group is the pid of the foreground process
+            TIOCGPGRP => unsafe {
+                *(argument as *mut i32) = self.foreground.unwrap_or(0) as i32;
+            },
+            TIOCSPGRP => {
+                self.foreground = Some(unsafe { *(argument as *const i32) } as u64);
+            }
+            TIOCGWINSZ => unsafe {
+                *(argument as *mut WinSize) = self.window_size;
+            },
+            // Resizing the terminal tells the foreground process so it can redraw
+            TIOCSWINSZ => {
+                let window_size = unsafe { *(argument as *const WinSize) };
+                if window_size != self.window_size {
+                    self.window_size = window_size;
+                    return Ok(Some(SIGWINCH));
+                }
+            }
+            _ => return Err(INAPPROPRIATE_IOCTL),
+        }
+        Ok(None)
+    }
+}
+
+/*
+    Adds a tty whose output is given to a device and returns its index
+    Devices with settings of their own (such as serial ports) are given the termios whenever it is set
+*/
+pub fn register(output: fn(usize, &[u8]), configure: Option<fn(usize, &Termios)>) -> Option<usize> {
+    let ttys = TTYS.lock();
+    let index = ttys.iter().position(|slot| slot.is_none());
+    if let Some(index) = index {
+        ttys[index] = Some(Tty::new(index, output, configure));
+    }
+    TTYS.free();
+    index
+}
+
+// The console tty is always the first
+pub fn init() {
+    register(write_console, None);
+}
+
+// Console is shown on the serial port, and on the screen when VGA text mode is used
+fn write_console(_index: usize, buffer: &[u8]) {
+    let mut console = CONSOLE.lock();
+    for &byte in buffer {
+        console.put_char(byte as char);
+    }
+    drop(console);
+    vga_text::write(buffer);
+}
+
+fn get_tty(ttys: &mut [Option<Tty>; MAX_TTYS], index: usize) -> Result<&mut Tty, &'static str> {
+    ttys.get_mut(index)
+        .and_then(|slot| slot.as_mut())
+        .ok_or("Tty doesn't exist")
+}
+
+/*
+    Passes a byte from the device through the line discipline (called within interrupt handlers)
+    Every waiting reader is woken to check whether there is now enough input for it
+*/
+pub fn receive(index: usize, byte: u8) {
+    let ttys = TTYS.lock();
+    let (signal, foreground) = match get_tty(ttys, index) {
+        Ok(tty) => {
+            let signal = tty.receive(byte);
+            while let Some(tid) = tty.readers.pop() {
+                multitask::wake_thread(tid);
+            }
+            (signal, tty.foreground)
+        }
+        Err(_) => (None, None),
+    };
+    TTYS.free();
+
+    if let (Some(signal), Some(pid)) = (signal, foreground) {
+        let _ = signal::send(pid, signal);
+    }
+}
+
+/*
+    Reads input into the buffer, blocking until there is enough (a whole line in canonical mode)
+    The process reading becomes the foreground process when there isn't one, so ^C reaches whatever is using the terminal
+*/
+pub fn read(index: usize, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let current_thread = PROCESS_SCHEDULAR.lock().get_current_thread();
+    PROCESS_SCHEDULAR.free();
+
+    loop {
+        let ttys = TTYS.lock();
+        let tty = match get_tty(ttys, index) {
+            Ok(tty) => tty,
+            Err(error) => {
+                TTYS.free();
+                return Err(error);
+            }
+        };
+
+        let reader = current_thread.and_then(|thread| thread.pid);
+        let has_foreground = tty.foreground.is_some_and(|pid| {
+            let process = PROCESS_SCHEDULAR.lock().get_process(pid);
+            PROCESS_SCHEDULAR.free();
+            process.is_some()
+        });
+        if !has_foreground && reader.is_some() {
+            tty.foreground = reader;
+        }
+
+        if let Some(count) = tty.take_input(buffer) {
+            TTYS.free();
+            return Ok(count);
+        }
+
+        if tty.hung_up {
+            TTYS.free();
+            return Ok(0);
+        }
+
+        // Kernel threads have nothing to block
+        let tid = match current_thread {
+            Some(thread) => thread.tid,
+            None => {
+                TTYS.free();
+                return Ok(0);
+            }
+        };
+        tty.readers.push(tid);
+        TTYS.free();
+
+        multitask::block_current_thread();
+
+        // Process may have been terminated (such as by ^C) whilst waiting
+        if signal::is_current_exited() {
+            multitask::wait_for_exit();
+        }
+    }
+}
+
+pub fn write(index: usize, buffer: &[u8]) -> Result<usize, &'static str> {
+    let ttys = TTYS.lock();
+    let result = get_tty(ttys, index).and_then(|tty| {
+        if tty.hung_up {
+            return Err("Input/output error");
+        }
+        tty.write(buffer);
+        Ok(buffer.len())
+    });
+    TTYS.free();
+    result
+}
+
+pub fn ioctl(index: usize, request: u64, argument: u64) -> Result<i64, &'static str> {
+    let ttys = TTYS.lock();
+    let result =
+        get_tty(ttys, index).and_then(|tty| Ok((tty.ioctl(request, argument)?, tty.foreground)));
+    TTYS.free();
+
+    if let Ok((Some(signal), Some(pid))) = result {
+        let _ = signal::send(pid, signal);
+    }
+    result.map(|_| 0)
+}
+
+/*
+    Hangs up a tty once its device has gone (such as when a pty master is closed)
+    Waiting readers are woken to see the end of the file and the foreground process is sent SIGHUP
+*/
+pub fn hangup(index: usize) {
+    let ttys = TTYS.lock();
+    let foreground = match get_tty(ttys, index) {
+        Ok(tty) => {
+            tty.hung_up = true;
+            while let Some(tid) = tty.readers.pop() {
+                multitask::wake_thread(tid);
+            }
+            tty.foreground.take()
+        }
+        Err(_) => None,
+    };
+    TTYS.free();
+
+    if let Some(pid) = foreground {
+        let _ = signal::send(pid, SIGHUP);
+    }
+}
+
+// Gives a tty its default settings again with no input waiting (such as when a pty is reused)
+pub fn reset(index: usize) -> Result<(), &'static str> {
+    let ttys = TTYS.lock();
+    let result = get_tty(ttys, index).map(|tty| *tty = Tty::new(index, tty.output, tty.configure));
+    TTYS.free();
+    result
+}
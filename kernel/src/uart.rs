@@ -4,33 +4,81 @@
     Physical serial ports provide a connector to attach devices (trasmits 1 byte at a time through a single channel)
     Serial ports are bi-directional (half duplex) and are controlled by uart (chip which encodes and decodes data)
     Must supply speed used for sending data (baud rate), error checking, data bits
+    COM1 is the kernel's console, which is written to by polling so it works before interrupts (and within panics)
+    Every port which is found (COM1 to COM4) is also a tty (/dev/ttyS0 to /dev/ttyS3):
+        Received bytes raise an interrupt (IRQ 4 for COM1 and COM3, IRQ 3 for COM2 and COM4)
+        Handler moves them from the uart's FIFO into a ring buffer before passing them to the tty
+        Speed, character size, stop bits and parity follow the termios of the tty
+    /dev/serial is kept as another name for /dev/ttyS0 (so it is now a tty rather than the raw port)
 */
 
 use spin::Mutex;
 
+use crate::char_device::{self, CharDevice};
+use crate::pic::{PicFunctions, PICS};
 use crate::ports::inb;
 use crate::ports::outb;
+use crate::ring_buffer::RingBuffer;
+use crate::spinlock::Lock;
+use crate::tty::{self, Termios, CSIZE, CSTOPB, PARENB, PARODD};
 use crate::writer::Writer;
 use core::fmt;
 
 const PORT: u16 = 0x3f8; // COM1
 
+// Base port and IRQ of COM1 to COM4
+const COM_PORTS: [(u16, u8); MAX_PORTS] = [(0x3f8, 4), (0x2f8, 3), (0x3e8, 4), (0x2e8, 3)];
+const MAX_PORTS: usize = 4;
+const NAMES: [&str; MAX_PORTS] = ["ttyS0", "ttyS1", "ttyS2", "ttyS3"];
+
+const RECEIVE_BUFFER_SIZE: usize = 1024;
+
+// Registers (offsets from the base port)
+const DATA: u16 = 0; // Divisor low byte whilst DLAB is set
+const INTERRUPT_ENABLE: u16 = 1; // Divisor high byte whilst DLAB is set
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const DLAB: u8 = 0x80; // Divisor latch access bit (of the line control register)
+const DATA_READY: u8 = 0x01;
+const TRANSMIT_EMPTY: u8 = 0x20;
+const RECEIVED_DATA_INTERRUPT: u8 = 0x01;
+
+const BASE_BAUD: u32 = 115200; // Speed given by a divisor of 1
+const DEFAULT_DIVISOR: u16 = 3; // 38400 baud (the default speed of a tty)
+const DEFAULT_LINE_CONTROL: u8 = 0x03; // 8 bits, no parity, one stop bit
+
 pub fn init() {
-    outb(PORT + 1, 0x00); // Disable interrupts
-    outb(PORT + 3, 0x80); // Enable DLAB
-    outb(PORT + 0, 0x03); // Set divisor to 3
-    outb(PORT + 1, 0x00);
-    outb(PORT + 3, 0x03); // 8 Bits, no parity, one stop bit
-    outb(PORT + 2, 0xc7); // Enable FIFO
-    outb(PORT + 4, 0x0b); // IRQ's enabled
-    outb(PORT + 4, 0x1e); // Set in loopback mode
-    outb(PORT + 0, 0xae); // Test serial chip
-
-    if inb(PORT + 0) != 0xae {
+    if !probe(PORT) {
         panic!("Faulty serial!");
     }
+    configure(PORT, DEFAULT_DIVISOR, DEFAULT_LINE_CONTROL);
+}
+
+// Checks that a uart is present by sending a byte to itself in loopback mode
+fn probe(port: u16) -> bool {
+    outb(port + INTERRUPT_ENABLE, 0x00); // Disable interrupts
+    outb(port + MODEM_CONTROL, 0x1e); // Set in loopback mode
+    outb(port + DATA, 0xae); // Test serial chip
 
-    outb(PORT + 4, 0x0f); // Set to normal operation mode
+    let is_present = inb(port + DATA) == 0xae;
+    outb(port + MODEM_CONTROL, 0x0f); // Set to normal operation mode (OUT2 lets the uart raise interrupts)
+    is_present
+}
+
+fn configure(port: u16, divisor: u16, line_control: u8) {
+    outb(port + LINE_CONTROL, DLAB);
+    outb(port + DATA, divisor as u8);
+    outb(port + INTERRUPT_ENABLE, (divisor >> 8) as u8);
+    outb(port + LINE_CONTROL, line_control);
+    outb(port + FIFO_CONTROL, 0xc7); // Enable FIFO (interrupting once 14 bytes are waiting)
+}
+
+fn transmit(port: u16, byte: u8) {
+    while inb(port + LINE_STATUS) & TRANSMIT_EMPTY == 0 {}
+    outb(port + DATA, byte);
 }
 
 pub struct Console {
@@ -39,11 +87,17 @@ pub struct Console {
 
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console { port: PORT });
 
+// Kernel messages are also shown on the screen when VGA text mode is the console
 #[macro_export]
 macro_rules! print_serial {
     ($($arg:tt)*) => ({
         use core::fmt::Write;
-        CONSOLE.lock().write_fmt(format_args!($($arg)*)).unwrap();
+        match format_args!($($arg)*) {
+            arguments => {
+                CONSOLE.lock().write_fmt(arguments).unwrap();
+                $crate::vga_text::print(arguments);
+            }
+        }
     });
 }
 
@@ -57,29 +111,15 @@ impl fmt::Write for Console {
 
 impl Console {
     fn write_serial(&mut self, character: char) {
-        while self.is_transmit_empty() == 0 {}
-        outb(self.port, character as u8);
+        transmit(self.port, character as u8);
 
         self.write_e9_hack(character);
     }
 
     fn write_e9_hack(&mut self, character: char) {
-        while self.is_transmit_empty() == 0 {}
+        while inb(self.port + LINE_STATUS) & TRANSMIT_EMPTY == 0 {}
         outb(0xe9, character as u8);
     }
-
-    fn read_serial(&self) -> u8 {
-        while self.serial_recieved() == 0 {}
-        return inb(self.port);
-    }
-
-    fn is_transmit_empty(&self) -> u8 {
-        return inb(self.port + 5) & 0x20;
-    }
-
-    fn serial_recieved(&self) -> u8 {
-        return inb(self.port + 5) & 1;
-    }
 }
 
 impl Writer for Console {
@@ -93,3 +133,169 @@ impl Writer for Console {
         self.write_serial(character);
     }
 }
+
+#[derive(Copy, Clone)]
+struct SerialPort {
+    port: u16,
+    irq: u8,
+    tty: usize,
+    received: RingBuffer<RECEIVE_BUFFER_SIZE>, // Bytes taken from the FIFO which the tty hasn't been given yet
+}
+
+static PORTS: Lock<[Option<SerialPort>; MAX_PORTS]> = Lock::new([None; MAX_PORTS]);
+
+/*
+    Gives every port which is present a tty and a device, then enables its receive interrupt
+    Must be called once the PIC is set up and ttys can be registered
+*/
+pub fn init_ttys() {
+    for (number, &(port, irq)) in COM_PORTS.iter().enumerate() {
+        if port != PORT && !probe(port) {
+            continue;
+        }
+
+        let tty = match tty::register(write_output, Some(configure_tty)) {
+            Some(tty) => tty,
+            None => break,
+        };
+        if port != PORT {
+            configure(port, DEFAULT_DIVISOR, DEFAULT_LINE_CONTROL);
+        }
+
+        PORTS.lock()[number] = Some(SerialPort {
+            port,
+            irq,
+            tty,
+            received: RingBuffer::new(),
+        });
+        PORTS.free();
+
+        unsafe {
+            SERIAL_TTYS[number].number = number;
+            char_device::register(core::ptr::addr_of_mut!(SERIAL_TTYS[number]));
+            if number == 0 {
+                char_device::register(core::ptr::addr_of_mut!(SERIAL_DEVICE));
+            }
+        }
+
+        outb(port + INTERRUPT_ENABLE, RECEIVED_DATA_INTERRUPT);
+        PICS.lock().clean_mask(0x20 + irq);
+    }
+}
+
+/*
+    Handles IRQ 3 and 4, which are each shared by 2 ports
+    The lock is freed before passing bytes to the tty, as echoes are written back through write_output
+*/
+pub fn handle_interrupt(irq: u8) {
+    let ports = PORTS.lock();
+    for serial_port in ports.iter_mut().flatten() {
+        if serial_port.irq != irq {
+            continue;
+        }
+        while inb(serial_port.port + LINE_STATUS) & DATA_READY != 0 {
+            serial_port.received.push(inb(serial_port.port + DATA));
+        }
+    }
+    PORTS.free();
+
+    loop {
+        let ports = PORTS.lock();
+        let next = ports
+            .iter_mut()
+            .flatten()
+            .find_map(|serial_port| Some((serial_port.tty, serial_port.received.pop()?)));
+        PORTS.free();
+
+        match next {
+            Some((tty, byte)) => tty::receive(tty, byte),
+            None => break,
+        }
+    }
+}
+
+fn get_port(tty: usize) -> Option<u16> {
+    let port = PORTS
+        .lock()
+        .iter()
+        .flatten()
+        .find(|serial_port| serial_port.tty == tty)
+        .map(|serial_port| serial_port.port);
+    PORTS.free();
+    port
+}
+
+fn write_output(tty: usize, buffer: &[u8]) {
+    if let Some(port) = get_port(tty) {
+        for &byte in buffer {
+            transmit(port, byte);
+        }
+    }
+}
+
+// Speeds which don't divide the base speed are rounded up to the next speed which does (a speed of 0 is ignored)
+fn configure_tty(tty: usize, termios: &Termios) {
+    let port = match get_port(tty) {
+        Some(port) if termios.c_ospeed != 0 => port,
+        _ => return,
+    };
+
+    let divisor = (BASE_BAUD / termios.c_ospeed).clamp(1, u16::MAX as u32) as u16;
+
+    let cflag = termios.c_cflag;
+    let mut line_control = ((cflag & CSIZE) >> 4) as u8;
+    if cflag & CSTOPB != 0 {
+        line_control |= 0x04;
+    }
+    if cflag & PARENB != 0 {
+        line_control |= 0x08;
+        if cflag & PARODD == 0 {
+            line_control |= 0x10; // Even parity
+        }
+    }
+
+    configure(port, divisor, line_control);
+}
+
+// Tty of a serial port, numbered by the port (so COM2 is always ttyS1)
+#[derive(Copy, Clone)]
+pub struct SerialTtyDevice {
+    number: usize,
+    name: Option<&'static str>, // Replaces the ttySN name (only used by /dev/serial)
+}
+
+static mut SERIAL_TTYS: [SerialTtyDevice; MAX_PORTS] = [SerialTtyDevice {
+    number: 0,
+    name: None,
+}; MAX_PORTS];
+
+static mut SERIAL_DEVICE: SerialTtyDevice = SerialTtyDevice {
+    number: 0,
+    name: Some("serial"),
+};
+
+impl SerialTtyDevice {
+    fn get_tty(&self) -> Result<usize, &'static str> {
+        let tty = PORTS.lock()[self.number].map(|serial_port| serial_port.tty);
+        PORTS.free();
+        tty.ok_or("Device doesn't exist")
+    }
+}
+
+impl CharDevice for SerialTtyDevice {
+    fn name(&self) -> &'static str {
+        self.name.unwrap_or(NAMES[self.number])
+    }
+
+    fn read(&mut self, _offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        tty::read(self.get_tty()?, buffer)
+    }
+
+    fn write(&mut self, _offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+        tty::write(self.get_tty()?, buffer)
+    }
+
+    fn ioctl(&mut self, request: u64, argument: u64) -> Result<i64, &'static str> {
+        tty::ioctl(self.get_tty()?, request, argument)
+    }
+}
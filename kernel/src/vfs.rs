@@ -0,0 +1,798 @@
+// src/vfs.rs
+
+/*
+    Virtual File System is an abstraction on top of filesystems which allows programs to work with any of them
+    Each filesystem is mounted at a path and refers to its files/directories by inode number (whatever that means to it)
+    Paths are resolved by finding the mount with the longest matching path, then looking up each remaining component within it
+    Files are identified by a vnode, which is the mount along with the inode within that mount
+*/
+
+#![allow(dead_code)]
+
+use crate::block;
+use crate::devfs;
+use crate::ext2;
+use crate::fs;
+use crate::multitask::{Credentials, PROCESS_SCHEDULAR};
+use crate::path::{Path, MAX_PATH_LENGTH};
+use crate::procfs;
+use crate::spinlock::Lock;
+use crate::tmpfs::Tmpfs;
+use crate::{print_serial, CONSOLE};
+
+pub type Inode = u64;
+
+const MAX_MOUNTS: usize = 8;
+
+// Where the disk is mounted when the root is the initial ramdisk
+const DISK_MOUNT_PATH: &str = "/mnt";
+
+// Temporary files are kept in memory so programs don't write to the disk image
+const TMPFS_MOUNT_PATH: &str = "/tmp";
+const DEVFS_MOUNT_PATH: &str = "/dev";
+const PROCFS_MOUNT_PATH: &str = "/proc";
+
+// Number of symbolic links which can be followed whilst resolving a single path (stops loops)
+const MAX_SYMBOLIC_LINKS: usize = 40;
+
+// Long names can be up to 255 UCS-2 characters (FAT), which take up to 3 bytes each as UTF-8
+const MAX_NAME_LENGTH: usize = 255 * 3;
+
+#[derive(Copy, PartialEq, Clone, Debug)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    CharDevice,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vnode {
+    pub mount: usize,
+    pub inode: Inode,
+}
+
+// Single entry within a directory
+pub struct DirectoryEntry {
+    pub inode: Inode,
+    pub file_type: FileType,
+    pub size: u64,
+    name: [u8; MAX_NAME_LENGTH],
+    name_length: usize,
+}
+
+impl DirectoryEntry {
+    pub fn new(inode: Inode, file_type: FileType, size: u64, name: &[u8]) -> DirectoryEntry {
+        let name_length = name.len().min(MAX_NAME_LENGTH);
+        let mut entry = DirectoryEntry {
+            inode,
+            file_type,
+            size,
+            name: [0; MAX_NAME_LENGTH],
+            name_length,
+        };
+        entry.name[0..name_length].copy_from_slice(&name[0..name_length]);
+        entry
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[0..self.name_length]
+    }
+}
+
+/*
+    Operations every filesystem provides on its inodes
+    Filesystems which can't be changed only need to implement the reads, as everything else fails by default
+*/
+pub trait FileSystem {
+    fn name(&self) -> &'static str;
+
+    fn root(&self) -> Inode;
+
+    // Finds a file within a directory by name
+    fn lookup(&mut self, directory: Inode, name: &str) -> Result<Inode, &'static str>;
+
+    fn stat(&mut self, inode: Inode) -> Result<Stat, &'static str>;
+
+    // Returns the number of bytes read (0 at the end of the file)
+    fn read(&mut self, inode: Inode, offset: u64, buffer: &mut [u8])
+        -> Result<usize, &'static str>;
+
+    fn write(
+        &mut self,
+        _inode: Inode,
+        _offset: u64,
+        _buffer: &[u8],
+    ) -> Result<usize, &'static str> {
+        Err(READ_ONLY)
+    }
+
+    /*
+        Gives the entry at an index within a directory along with the index of the entry after it
+        Indexes mean whatever the filesystem wants (such as a byte offset) as long as 0 is the first entry
+        Returns None once every entry has been read
+    */
+    fn read_directory(
+        &mut self,
+        directory: Inode,
+        index: u64,
+    ) -> Result<Option<(DirectoryEntry, u64)>, &'static str>;
+
+    fn create(
+        &mut self,
+        _directory: Inode,
+        _name: &str,
+        _file_type: FileType,
+    ) -> Result<Inode, &'static str> {
+        Err(READ_ONLY)
+    }
+
+    // Creates a symbolic link which holds the path of its target
+    fn symlink(
+        &mut self,
+        _directory: Inode,
+        _name: &str,
+        _target: &str,
+    ) -> Result<Inode, &'static str> {
+        Err(READ_ONLY)
+    }
+
+    // Adds another name for an existing file (a hard link)
+    fn link(&mut self, _directory: Inode, _name: &str, _inode: Inode) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    fn unlink(&mut self, _directory: Inode, _name: &str) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    fn rmdir(&mut self, _directory: Inode, _name: &str) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    fn rename(
+        &mut self,
+        _old_directory: Inode,
+        _old_name: &str,
+        _new_directory: Inode,
+        _new_name: &str,
+    ) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    // Copies the target of a symbolic link into the buffer and returns its length
+    fn readlink(&mut self, _inode: Inode, _buffer: &mut [u8]) -> Result<usize, &'static str> {
+        Err(NOT_A_SYMLINK)
+    }
+
+    // Changes the permission bits of a file (the file type bits are kept)
+    fn chmod(&mut self, _inode: Inode, _mode: u32) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    fn chown(&mut self, _inode: Inode, _uid: u16, _gid: u16) -> Result<(), &'static str> {
+        Err(READ_ONLY)
+    }
+
+    /*
+        Told when a process first opens a file and once it has closed every file descriptor which refers to it
+        Devices can refuse to be opened, and tmpfs keeps unlinked files until they are closed
+    */
+    fn open(&mut self, _inode: Inode) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn close(&mut self, _inode: Inode) {}
+
+    // Device specific request (only devices support any)
+    fn ioctl(&mut self, _inode: Inode, _request: u64, _argument: u64) -> Result<i64, &'static str> {
+        Err(INAPPROPRIATE_IOCTL)
+    }
+
+    // Writes any changes still held in memory back to the disk
+    fn sync(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Mount {
+    path: Path,
+    filesystem: *mut dyn FileSystem,
+}
+
+static MOUNTS: Lock<[Option<Mount>; MAX_MOUNTS]> = Lock::new([None; MAX_MOUNTS]);
+
+/*
+    Metadata on a file which matches the layout of struct stat within newlib
+    Filled in by the filesystem, apart from st_dev which is the mount
+*/
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub st_dev: i16,
+    pub st_ino: u16,
+    pub st_mode: u32,
+    pub st_nlink: u16,
+    pub st_uid: u16,
+    pub st_gid: u16,
+    pub st_rdev: i16,
+    pub st_size: i64,
+    pub st_atim: Timespec,
+    pub st_mtim: Timespec,
+    pub st_ctim: Timespec,
+    pub st_blksize: i64,
+    pub st_blocks: i64,
+    pub st_spare4: [i64; 2],
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+/*
+    Start of each record given by getdents, which is followed by the null terminated name
+    Matches the layout of struct dirent within userland (the name starts straight after d_type)
+*/
+#[repr(C)]
+struct DirentHeader {
+    d_ino: u64,
+    d_off: i64, // Offset of the next record
+    d_size: u64,
+    d_reclen: u16, // Length of this record
+    d_type: u8,
+}
+
+const DIRENT_NAME_OFFSET: usize = 27;
+
+// Types of directory records
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+// File type bits of st_mode
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+// Permission bits of st_mode (the other bits are read, write and execute for the owner, group and everyone else)
+pub const S_ISVTX: u32 = 0o1000; // Sticky bit (only owners can remove files from the directory)
+pub const S_IPERM: u32 = 0o7777;
+
+// Access which can be checked for (matches access() within userland)
+pub const R_OK: u32 = 4;
+pub const W_OK: u32 = 2;
+pub const X_OK: u32 = 1;
+
+/*
+    Errors which syscalls report to userland as error numbers (see syscalls::error_number)
+    Filesystems return these rather than their own wording so the error number is never lost
+*/
+pub const NOT_PERMITTED: &str = "Operation not permitted";
+pub const LINK_TO_DIRECTORY: &str = "Cannot link to a directory";
+pub const FILE_NOT_FOUND: &str = "File not found";
+pub const PERMISSION_DENIED: &str = "Permission denied";
+pub const FILE_EXISTS: &str = "File already exists";
+pub const CROSS_DEVICE_LINK: &str = "Cannot link between filesystems";
+pub const NOT_A_DIRECTORY: &str = "Not a directory";
+pub const NOT_A_SYMLINK: &str = "Not a symbolic link";
+pub const INVALID_SYMLINK: &str = "Invalid symbolic link";
+pub const INVALID_FILENAME: &str = "Invalid filename";
+pub const READ_ONLY: &str = "Read only filesystem";
+pub const SYMLINK_LOOP: &str = "Too many levels of symbolic links";
+pub const IS_A_DIRECTORY: &str = "Is a directory";
+pub const DIRECTORY_NOT_EMPTY: &str = "Directory is not empty";
+pub const INAPPROPRIATE_IOCTL: &str = "Inappropriate ioctl for device";
+pub const DEVICE_BUSY: &str = "Device or resource busy";
+
+/*
+    File which has been opened by a process (held within its file table)
+    The offset is a byte offset for files and a filesystem defined index for directories
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OpenFile {
+    pub vnode: Vnode,
+    pub offset: i64,
+}
+
+impl OpenFile {
+    pub fn new(vnode: Vnode) -> OpenFile {
+        OpenFile { vnode, offset: 0 }
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, &'static str> {
+        let count = read(self.vnode, self.offset as u64, buffer)?;
+        self.offset += count as i64;
+        Ok(count)
+    }
+
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, &'static str> {
+        let count = write(self.vnode, self.offset as u64, buffer)?;
+        self.offset += count as i64;
+        Ok(count)
+    }
+
+    pub fn stat(&self) -> Result<Stat, &'static str> {
+        stat(self.vnode)
+    }
+
+    /*
+        Fills the buffer with as many variable length directory records as fit, continuing from the current offset
+        Returns the number of bytes written (0 once every entry has been read)
+    */
+    pub fn getdents(&mut self, buffer: *mut u8, length: usize) -> Result<usize, &'static str> {
+        let mut position = 0;
+
+        while let Some((entry, next_index)) = read_directory(self.vnode, self.offset as u64)? {
+            let name = entry.name();
+
+            // Records hold the null terminated name and are padded to keep the next one aligned
+            let record_length = (DIRENT_NAME_OFFSET + name.len() + 1 + 7) & !7;
+            if position + record_length > length {
+                if position == 0 {
+                    return Err("Buffer is too small");
+                }
+                return Ok(position);
+            }
+
+            let header = DirentHeader {
+                d_ino: entry.inode,
+                d_off: next_index as i64,
+                d_size: entry.size,
+                d_reclen: record_length as u16,
+                d_type: match entry.file_type {
+                    FileType::File => DT_REG,
+                    FileType::Directory => DT_DIR,
+                    FileType::Symlink => DT_LNK,
+                    FileType::CharDevice => DT_CHR,
+                },
+            };
+
+            unsafe {
+                let record = buffer.add(position);
+                core::ptr::write_bytes(record, 0, record_length);
+                core::ptr::copy(
+                    &header as *const DirentHeader as *const u8,
+                    record,
+                    DIRENT_NAME_OFFSET,
+                );
+                core::ptr::copy(name.as_ptr(), record.add(DIRENT_NAME_OFFSET), name.len());
+            }
+
+            position += record_length;
+            self.offset = next_index as i64;
+        }
+
+        Ok(position)
+    }
+}
+
+/*
+    Mounts the first block device which holds a filesystem (ext2 or FAT) as the root
+    When the initial ramdisk is already the root, the disk is mounted under it instead
+    A tmpfs is always mounted at /tmp, devfs at /dev and procfs at /proc
+*/
+pub fn init() {
+    mount_disk();
+    mount_memory_filesystem(TMPFS_MOUNT_PATH, Tmpfs::new());
+
+    // Anyone can create files within /tmp but only remove their own
+    let tmp = Path::root().join(TMPFS_MOUNT_PATH).unwrap();
+    let _ = resolve(&tmp).and_then(|vnode| chmod(vnode, S_ISVTX | 0o777));
+
+    mount_memory_filesystem(DEVFS_MOUNT_PATH, devfs::mount());
+    mount_memory_filesystem(PROCFS_MOUNT_PATH, procfs::mount());
+}
+
+// Filesystems which aren't backed by a block device don't need a directory to mount over
+fn mount_memory_filesystem(path: &str, filesystem: *mut dyn FileSystem) {
+    let name = unsafe { (*filesystem).name() };
+    match mount(path, filesystem) {
+        Ok(_) => print_serial!("MOUNTED {} AT {}\n", name, path),
+        Err(error) => print_serial!("FAILED TO MOUNT {} AT {}: {}\n", name, path, error),
+    }
+}
+
+fn mount_disk() {
+    let mount_path = match resolve(&Path::root()) {
+        Ok(_) => {
+            let _ = create(
+                &Path::root().join(DISK_MOUNT_PATH).unwrap(),
+                FileType::Directory,
+            );
+            DISK_MOUNT_PATH
+        }
+        Err(_) => "/",
+    };
+
+    for device in 0.. {
+        if block::get(device).is_none() {
+            break;
+        }
+
+        let filesystem = match ext2::mount(device).or_else(|| fs::mount(device)) {
+            Some(filesystem) => filesystem,
+            None => continue,
+        };
+
+        if mount(mount_path, filesystem).is_ok() {
+            print_serial!(
+                "MOUNTED BLOCK DEVICE {} AT {} ({})\n",
+                device,
+                mount_path,
+                unsafe { (*filesystem).name() }
+            );
+            return;
+        }
+    }
+
+    print_serial!("NO FILESYSTEM FOUND\n");
+}
+
+// Attaches a filesystem at a path and returns the index of the mount
+pub fn mount(path: &str, filesystem: *mut dyn FileSystem) -> Result<usize, &'static str> {
+    let path = Path::root().join(path)?;
+
+    let mounts = MOUNTS.lock();
+    let result = if mounts
+        .iter()
+        .flatten()
+        .any(|mount| mount.path.as_str() == path.as_str())
+    {
+        Err("Path is already a mount point")
+    } else {
+        match mounts.iter().position(|mount| mount.is_none()) {
+            Some(index) => {
+                mounts[index] = Some(Mount { path, filesystem });
+                Ok(index)
+            }
+            None => Err("Too many mounts"),
+        }
+    };
+    MOUNTS.free();
+    result
+}
+
+fn get_filesystem(mount: usize) -> Result<*mut dyn FileSystem, &'static str> {
+    let filesystem = MOUNTS.lock().get(mount).copied().flatten();
+    MOUNTS.free();
+    filesystem
+        .map(|mount| mount.filesystem)
+        .ok_or("Filesystem isn't mounted")
+}
+
+/*
+    Finds the mount which holds a path (the one with the longest path which the path starts with)
+    Returns the index of the mount along with the rest of the path within that mount
+*/
+fn find_mount(path: &Path) -> Result<(usize, &str), &'static str> {
+    let path = path.as_str();
+    let mut best: Option<(usize, usize)> = None;
+
+    let mounts = MOUNTS.lock();
+    for (index, mount) in mounts.iter().enumerate() {
+        let mount_path = match mount {
+            Some(mount) => mount.path.as_str(),
+            None => continue,
+        };
+
+        // Mount paths must match whole components ("/tmp" holds "/tmp/a" but not "/tmpfile")
+        let matches = mount_path == "/"
+            || path == mount_path
+            || (path.starts_with(mount_path) && path.as_bytes()[mount_path.len()] == b'/');
+
+        if matches && best.is_none_or(|(_, length)| mount_path.len() > length) {
+            best = Some((index, mount_path.len()));
+        }
+    }
+    MOUNTS.free();
+
+    match best {
+        Some((index, length)) => Ok((index, &path[length..])),
+        None => Err("No filesystem is mounted"),
+    }
+}
+
+// Finds the vnode of the file at an absolute path, following any symbolic links
+pub fn resolve(path: &Path) -> Result<Vnode, &'static str> {
+    resolve_following(path, true)
+}
+
+// Finds the vnode of the file at an absolute path without following the final component if it's a symbolic link
+pub fn resolve_link(path: &Path) -> Result<Vnode, &'static str> {
+    resolve_following(path, false)
+}
+
+fn resolve_following(path: &Path, follow_final: bool) -> Result<Vnode, &'static str> {
+    let credentials = caller_credentials();
+    let mut path = *path;
+    for _ in 0..=MAX_SYMBOLIC_LINKS {
+        if let Some(vnode) = walk(&mut path, follow_final, &credentials)? {
+            return Ok(vnode);
+        }
+    }
+    Err(SYMLINK_LOOP)
+}
+
+/*
+    Looks up each component of a path in turn from the root of the mount which holds it
+    When a symbolic link is found, the path is rewritten with the link replaced by its target and None is returned so the walk starts again
+    Relative targets start from the directory which holds the link
+    Every directory which is searched must allow the caller to search it (execute permission)
+*/
+fn walk(
+    path: &mut Path,
+    follow_final: bool,
+    credentials: &Credentials,
+) -> Result<Option<Vnode>, &'static str> {
+    let (mount, remaining_path) = find_mount(path)?;
+    let filesystem = get_filesystem(mount)?;
+
+    let filepath = path.as_str();
+    let mut directory = Path::root().join(&filepath[..filepath.len() - remaining_path.len()])?;
+    let mut inode = unsafe { (*filesystem).root() };
+
+    let mut components = remaining_path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .peekable();
+    while let Some(component) = components.next() {
+        check_access(Vnode { mount, inode }, credentials, X_OK)?;
+        let next_inode = unsafe { (*filesystem).lookup(inode, component)? };
+        let is_final = components.peek().is_none();
+
+        if (follow_final || !is_final) && is_symlink(filesystem, next_inode)? {
+            let mut target = [0; MAX_PATH_LENGTH];
+            let length = unsafe { (*filesystem).readlink(next_inode, &mut target)? };
+            let target = core::str::from_utf8(&target[0..length]).map_err(|_| INVALID_SYMLINK)?;
+
+            let mut new_path = directory.join(target)?;
+            for component in components {
+                new_path = new_path.join(component)?;
+            }
+            *path = new_path;
+            return Ok(None);
+        }
+
+        directory = directory.join(component)?;
+        inode = next_inode;
+    }
+
+    Ok(Some(Vnode { mount, inode }))
+}
+
+// Paths are looked up as the process which made the syscall (the kernel itself acts as root)
+fn caller_credentials() -> Credentials {
+    let process = PROCESS_SCHEDULAR.lock().get_current_process();
+    PROCESS_SCHEDULAR.free();
+    process.map_or(Credentials::root(), |process| process.credentials)
+}
+
+fn is_symlink(filesystem: *mut dyn FileSystem, inode: Inode) -> Result<bool, &'static str> {
+    let stat = unsafe { (*filesystem).stat(inode)? };
+    Ok(stat.st_mode & S_IFMT == S_IFLNK)
+}
+
+/*
+    Finds the directory which holds the final component of a path, along with the final component
+    Mount points can't be changed as they belong to the filesystem mounted over them
+*/
+fn resolve_parent(path: &Path) -> Result<(Vnode, &str), &'static str> {
+    let (_, remaining_path) = find_mount(path)?;
+    if remaining_path.is_empty() {
+        return Err("Mount point is busy");
+    }
+
+    let filepath = path.as_str();
+    let (parent_path, name) = match filepath.rfind('/') {
+        Some(index) => (&filepath[..index], &filepath[index + 1..]),
+        None => return Err("Path isn't absolute"),
+    };
+
+    let parent = resolve(&Path::root().join(parent_path)?)?;
+    Ok((parent, name))
+}
+
+// Creates an empty file or directory at a path
+pub fn create(path: &Path, file_type: FileType) -> Result<Vnode, &'static str> {
+    let (parent, name) = resolve_parent(path)?;
+    let filesystem = get_filesystem(parent.mount)?;
+    let inode = unsafe { (*filesystem).create(parent.inode, name, file_type)? };
+
+    Ok(Vnode {
+        mount: parent.mount,
+        inode,
+    })
+}
+
+// Creates a symbolic link at a path (the target is stored as given, so doesn't have to exist)
+pub fn symlink(target: &str, path: &Path) -> Result<Vnode, &'static str> {
+    if target.is_empty() || target.len() > MAX_PATH_LENGTH {
+        return Err(INVALID_SYMLINK);
+    }
+
+    let (parent, name) = resolve_parent(path)?;
+    let filesystem = get_filesystem(parent.mount)?;
+    let inode = unsafe { (*filesystem).symlink(parent.inode, name, target)? };
+
+    Ok(Vnode {
+        mount: parent.mount,
+        inode,
+    })
+}
+
+// Hard links can only be made within the same filesystem (a symbolic link is linked to rather than its target)
+pub fn link(old_path: &Path, new_path: &Path) -> Result<(), &'static str> {
+    let vnode = resolve_link(old_path)?;
+    let (parent, name) = resolve_parent(new_path)?;
+    if vnode.mount != parent.mount {
+        return Err(CROSS_DEVICE_LINK);
+    }
+
+    let filesystem = get_filesystem(parent.mount)?;
+    unsafe { (*filesystem).link(parent.inode, name, vnode.inode) }
+}
+
+pub fn unlink(path: &Path) -> Result<(), &'static str> {
+    let (parent, name) = resolve_parent(path)?;
+    let filesystem = get_filesystem(parent.mount)?;
+    unsafe { (*filesystem).unlink(parent.inode, name) }
+}
+
+pub fn rmdir(path: &Path) -> Result<(), &'static str> {
+    let (parent, name) = resolve_parent(path)?;
+    let filesystem = get_filesystem(parent.mount)?;
+    unsafe { (*filesystem).rmdir(parent.inode, name) }
+}
+
+// Files can only be moved within the same filesystem
+pub fn rename(old_path: &Path, new_path: &Path) -> Result<(), &'static str> {
+    let (old_parent, old_name) = resolve_parent(old_path)?;
+    let (new_parent, new_name) = resolve_parent(new_path)?;
+    if old_parent.mount != new_parent.mount {
+        return Err("Cannot move a file between filesystems");
+    }
+
+    let filesystem = get_filesystem(old_parent.mount)?;
+    unsafe { (*filesystem).rename(old_parent.inode, old_name, new_parent.inode, new_name) }
+}
+
+pub fn stat(vnode: Vnode) -> Result<Stat, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    let mut stat = unsafe { (*filesystem).stat(vnode.inode)? };
+    stat.st_dev = vnode.mount as i16;
+    Ok(stat)
+}
+
+pub fn read(vnode: Vnode, offset: u64, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).read(vnode.inode, offset, buffer) }
+}
+
+pub fn write(vnode: Vnode, offset: u64, buffer: &[u8]) -> Result<usize, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).write(vnode.inode, offset, buffer) }
+}
+
+pub fn read_directory(
+    vnode: Vnode,
+    index: u64,
+) -> Result<Option<(DirectoryEntry, u64)>, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).read_directory(vnode.inode, index) }
+}
+
+pub fn readlink(vnode: Vnode, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).readlink(vnode.inode, buffer) }
+}
+
+pub fn chmod(vnode: Vnode, mode: u32) -> Result<(), &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).chmod(vnode.inode, mode & S_IPERM) }
+}
+
+pub fn chown(vnode: Vnode, uid: u16, gid: u16) -> Result<(), &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).chown(vnode.inode, uid, gid) }
+}
+
+/*
+    Checks the access (R_OK, W_OK and X_OK combined) a process has to a file using its effective ids
+    Only the owner bits apply to the owner and only the group bits to the group, even if others are given more
+    Root can do anything, apart from execute a file which nobody is allowed to execute
+*/
+pub fn check_access(
+    vnode: Vnode,
+    credentials: &Credentials,
+    access: u32,
+) -> Result<(), &'static str> {
+    let stat = stat(vnode)?;
+
+    let allowed = if credentials.euid == 0 {
+        let is_directory = stat.st_mode & S_IFMT == S_IFDIR;
+        access & X_OK == 0 || is_directory || stat.st_mode & 0o111 != 0
+    } else {
+        let permissions = if credentials.euid == stat.st_uid {
+            stat.st_mode >> 6
+        } else if credentials.egid == stat.st_gid {
+            stat.st_mode >> 3
+        } else {
+            stat.st_mode
+        };
+        permissions & access == access
+    };
+
+    match allowed {
+        true => Ok(()),
+        false => Err(PERMISSION_DENIED),
+    }
+}
+
+// Checks whether a process can add files to the directory which would hold a path
+pub fn check_creatable(path: &Path, credentials: &Credentials) -> Result<(), &'static str> {
+    let (parent, _) = resolve_parent(path)?;
+    check_access(parent, credentials, W_OK | X_OK)
+}
+
+/*
+    Checks whether a process can remove (or rename) the file at a path
+    The directory which holds it must be writable, and within sticky directories only root or the owner of the file or directory can
+*/
+pub fn check_removable(path: &Path, credentials: &Credentials) -> Result<(), &'static str> {
+    let (parent, _) = resolve_parent(path)?;
+    check_access(parent, credentials, W_OK | X_OK)?;
+
+    let directory = stat(parent)?;
+    if directory.st_mode & S_ISVTX == 0 || credentials.euid == 0 {
+        return Ok(());
+    }
+
+    let file = stat(resolve_link(path)?)?;
+    match credentials.euid == file.st_uid || credentials.euid == directory.st_uid {
+        true => Ok(()),
+        false => Err(NOT_PERMITTED),
+    }
+}
+
+// Tells the filesystem that a process is opening a file (before it is first added to its file table)
+pub fn open(vnode: Vnode) -> Result<(), &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).open(vnode.inode) }
+}
+
+// Tells the filesystem that a process has closed every file descriptor which referred to a file
+pub fn close(vnode: Vnode) {
+    if let Ok(filesystem) = get_filesystem(vnode.mount) {
+        unsafe { (*filesystem).close(vnode.inode) }
+    }
+}
+
+pub fn ioctl(vnode: Vnode, request: u64, argument: u64) -> Result<i64, &'static str> {
+    let filesystem = get_filesystem(vnode.mount)?;
+    unsafe { (*filesystem).ioctl(vnode.inode, request, argument) }
+}
+
+// Syncs a single mount (or every mount if None)
+pub fn sync(mount: Option<usize>) -> Result<(), &'static str> {
+    let mut result = Ok(());
+    for index in 0..MAX_MOUNTS {
+        if mount.is_some() && mount != Some(index) {
+            continue;
+        }
+
+        // Keep syncing the rest of the mounts even if one fails
+        if let Ok(filesystem) = get_filesystem(index) {
+            if let Err(error) = unsafe { (*filesystem).sync() } {
+                result = Err(error);
+            }
+        }
+    }
+    result
+}
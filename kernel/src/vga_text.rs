@@ -10,16 +10,21 @@ Each entry in the buffer must be formatted like this:
 +---------------------------------------------+
 | | Blink | Background | Foreground | ASCII | |
 +---------------------------------------------+
+Text mode is only used when there is no framebuffer (no BGA or grub didn't set a graphics mode)
+It then becomes the kernel console, showing boot logs, panics and the output of the console tty
+Output understands carriage returns, backspaces, tabs and ANSI colour escapes (such as ESC[31m for red)
 */
 
 // TODO: Fix having to import use crate::vga_text::TERMINAL; on each file
 
+use crate::ports::outb;
 use crate::writer::Writer;
 use core::fmt;
 use lazy_static::lazy_static;
 
 #[allow(dead_code)]
-enum VgaColours {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VgaColours {
     Black = 0,
     Bue = 1,
     Green = 2,
@@ -38,9 +43,26 @@ enum VgaColours {
     White = 15,
 }
 
+// Progress through an escape sequence (ESC [ parameters letter)
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Escape {
+    None,
+    Started,
+    Csi,
+}
+
+const MAX_PARAMETERS: usize = 8;
+
+// ANSI colours are in the order black, red, green, yellow, blue, magenta, cyan, white (VGA swaps red and blue)
+const ANSI_COLOURS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
 pub struct Terminal {
     terminal_row: usize,
     terminal_col: usize,
+    attributes: u8, // Colours given to new characters
+    escape: Escape,
+    parameters: [u16; MAX_PARAMETERS],
+    parameter_count: usize,
     vga_buffer: &'static mut [[u16; VGA_WIDTH]; VGA_HEIGHT],
 }
 
@@ -48,12 +70,26 @@ lazy_static! {
     pub static ref TERMINAL: spin::Mutex<Terminal> = spin::Mutex::new(Terminal {
         terminal_row: 0,
         terminal_col: 0,
+        attributes: DEFAULT_ATTRIBUTES,
+        escape: Escape::None,
+        parameters: [0; MAX_PARAMETERS],
+        parameter_count: 0,
         vga_buffer: unsafe { &mut *(0xb8000 as *mut [[u16; VGA_WIDTH]; VGA_HEIGHT]) }, // Make an array pointed at the address
     });
 }
 
 const VGA_WIDTH: usize = 80;
 const VGA_HEIGHT: usize = 25;
+const TAB_WIDTH: usize = 8;
+const DEFAULT_ATTRIBUTES: u8 = (VgaColours::Black as u8) << 4 | VgaColours::LightGrey as u8;
+
+// CRT controller registers which position the hardware cursor
+const CRTC_INDEX: u16 = 0x3d4;
+const CRTC_DATA: u16 = 0x3d5;
+const CURSOR_LOCATION_HIGH: u8 = 0x0e;
+const CURSOR_LOCATION_LOW: u8 = 0x0f;
+
+static mut IS_ENABLED: bool = false;
 
 #[macro_export]
 macro_rules! print_vga {
@@ -83,28 +119,122 @@ impl Terminal {
         self.clear_row(VGA_HEIGHT - 1);
     }
 
+    // Cleared cells keep the current colours so the background is filled
     fn clear_row(&mut self, row_num: usize) {
         for j in 0..VGA_WIDTH {
-            self.vga_buffer[row_num][j] = 0;
+            self.vga_buffer[row_num][j] = VgaColours::get_vga_entry(self.attributes, b' ');
         }
     }
 
+    // Moves back a character without erasing it (the tty erases by writing "\b \b")
     pub fn backspace(&mut self) {
-        if self.terminal_col == 0 {
-            self.terminal_col = 79;
-            self.terminal_row -= 1;
-            self.vga_buffer[self.terminal_row][self.terminal_col] = VgaColours::get_vga_entry(
-                VgaColours::get_attributes((VgaColours::Black, VgaColours::White)),
-                ' ' as u8,
-            );
-        } else {
+        if self.terminal_col > 0 {
             self.terminal_col -= 1;
-            self.vga_buffer[self.terminal_row][self.terminal_col] = VgaColours::get_vga_entry(
-                VgaColours::get_attributes((VgaColours::Black, VgaColours::White)),
-                ' ' as u8,
-            );
+        } else if self.terminal_row > 0 {
+            self.terminal_col = VGA_WIDTH - 1;
+            self.terminal_row -= 1;
+        }
+    }
+
+    pub fn set_colour(&mut self, background: VgaColours, foreground: VgaColours) {
+        self.attributes = VgaColours::get_attributes((background, foreground));
+    }
+
+    // Output from the console tty may hold control characters and escape sequences
+    fn put_byte(&mut self, byte: u8) {
+        match self.escape {
+            Escape::Started => {
+                self.escape = match byte {
+                    b'[' => Escape::Csi,
+                    _ => Escape::None,
+                };
+                self.parameters = [0; MAX_PARAMETERS];
+                self.parameter_count = 0;
+            }
+            Escape::Csi => self.put_csi_byte(byte),
+            Escape::None => match byte {
+                0x1b => self.escape = Escape::Started,
+                b'\r' => self.terminal_col = 0,
+                0x08 => self.backspace(),
+                b'\t' => {
+                    let next_stop = (self.terminal_col / TAB_WIDTH + 1) * TAB_WIDTH;
+                    while self.terminal_col < next_stop.min(VGA_WIDTH) {
+                        self.put_char(' ');
+                    }
+                }
+                _ => self.put_char(byte as char),
+            },
         }
     }
+
+    fn put_csi_byte(&mut self, byte: u8) {
+        match byte {
+            b'0'..=b'9' => {
+                let index = self.parameter_count.min(MAX_PARAMETERS - 1);
+                self.parameters[index] = self.parameters[index]
+                    .saturating_mul(10)
+                    .saturating_add((byte - b'0') as u16);
+            }
+            b';' => self.parameter_count += 1,
+            // Select graphic rendition (colours), where no parameters resets them
+            b'm' => {
+                let count = (self.parameter_count + 1).min(MAX_PARAMETERS);
+                for i in 0..count {
+                    self.select_graphic_rendition(self.parameters[i]);
+                }
+                self.escape = Escape::None;
+            }
+            // Erase display clears after the cursor (0), before it (1) or everything (2), without moving the cursor
+            b'J' => {
+                let cursor = self.terminal_row * VGA_WIDTH + self.terminal_col;
+                let (start, end) = match self.parameters[0] {
+                    0 => (cursor, VGA_WIDTH * VGA_HEIGHT),
+                    1 => (0, cursor + 1),
+                    _ => (0, VGA_WIDTH * VGA_HEIGHT),
+                };
+                for position in start..end.min(VGA_WIDTH * VGA_HEIGHT) {
+                    self.vga_buffer[position / VGA_WIDTH][position % VGA_WIDTH] =
+                        VgaColours::get_vga_entry(self.attributes, b' ');
+                }
+                self.escape = Escape::None;
+            }
+            // Erase line clears the rest of the line
+            b'K' => {
+                for j in self.terminal_col..VGA_WIDTH {
+                    self.vga_buffer[self.terminal_row][j] =
+                        VgaColours::get_vga_entry(self.attributes, b' ');
+                }
+                self.escape = Escape::None;
+            }
+            // Other sequences are ignored
+            0x40..=0x7e => self.escape = Escape::None,
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, parameter: u16) {
+        let foreground = self.attributes & 0x0f;
+        let background = self.attributes >> 4;
+
+        self.attributes = match parameter {
+            0 => DEFAULT_ATTRIBUTES,
+            1 => self.attributes | 0x08, // Bold is shown as bright
+            30..=37 => background << 4 | ANSI_COLOURS[parameter as usize - 30],
+            39 => background << 4 | (DEFAULT_ATTRIBUTES & 0x0f),
+            40..=47 => ANSI_COLOURS[parameter as usize - 40] << 4 | foreground,
+            49 => (DEFAULT_ATTRIBUTES & 0xf0) | foreground,
+            90..=97 => background << 4 | ANSI_COLOURS[parameter as usize - 90] | 0x08,
+            _ => self.attributes,
+        };
+    }
+
+    fn update_cursor(&self) {
+        let position = (self.terminal_row * VGA_WIDTH + self.terminal_col) as u16;
+        outb(CRTC_INDEX, CURSOR_LOCATION_HIGH);
+        outb(CRTC_DATA, (position >> 8) as u8);
+        outb(CRTC_INDEX, CURSOR_LOCATION_LOW);
+        outb(CRTC_DATA, position as u8);
+    }
 }
 
 impl VgaColours {
@@ -120,13 +250,19 @@ impl VgaColours {
 }
 
 impl Writer for Terminal {
+    fn write_string(&mut self, string: &str) {
+        for c in string.chars() {
+            self.put_char(c);
+        }
+        self.update_cursor();
+    }
+
     fn put_char(&mut self, character: char) {
         match character {
             '\n' => self.newline(),
             _ => {
-                let attributes = VgaColours::get_attributes((VgaColours::Black, VgaColours::White));
                 self.vga_buffer[self.terminal_row][self.terminal_col] =
-                    VgaColours::get_vga_entry(attributes, character as u8);
+                    VgaColours::get_vga_entry(self.attributes, character as u8);
                 self.terminal_col += 1;
                 if self.terminal_col >= VGA_WIDTH {
                     self.newline();
@@ -144,12 +280,57 @@ impl Writer for Terminal {
     }
 
     fn clear(&mut self) {
-        for _i in 0..VGA_HEIGHT {
-            for _j in 0..VGA_WIDTH {
-                self.put_char(' ');
-            }
+        for i in 0..VGA_HEIGHT {
+            self.clear_row(i);
         }
         self.terminal_row = 0;
         self.terminal_col = 0;
     }
 }
+
+// Makes text mode the kernel console (called when there is no framebuffer)
+pub fn init() {
+    let mut terminal = TERMINAL.lock();
+    terminal.clear();
+    terminal.update_cursor();
+    unsafe {
+        IS_ENABLED = true;
+    }
+}
+
+pub fn is_enabled() -> bool {
+    unsafe { IS_ENABLED }
+}
+
+// Shows formatted kernel messages (such as boot logs) when text mode is the console
+pub fn print(arguments: fmt::Arguments) {
+    if is_enabled() {
+        use core::fmt::Write;
+        TERMINAL.lock().write_fmt(arguments).unwrap();
+    }
+}
+
+// Shows output of the console tty
+pub fn write(buffer: &[u8]) {
+    if is_enabled() {
+        let mut terminal = TERMINAL.lock();
+        for &byte in buffer {
+            terminal.put_byte(byte);
+        }
+        terminal.update_cursor();
+    }
+}
+
+// Panics are shown in red, even part way through another message
+pub fn print_panic(arguments: fmt::Arguments) {
+    if is_enabled() {
+        use core::fmt::Write;
+        unsafe {
+            TERMINAL.force_unlock();
+        }
+        let mut terminal = TERMINAL.lock();
+        terminal.set_colour(VgaColours::Black, VgaColours::LightRed);
+        let _ = terminal.write_fmt(arguments);
+        terminal.update_cursor();
+    }
+}
@@ -0,0 +1,276 @@
+// src/virtio.rs
+
+/*
+    Virtio devices are virtual devices (provided by QEMU, etc) which share buffers with the guest rather than emulating real hardware
+    The legacy PCI interface is used, where the device registers are within the IO space given by BAR0
+    Requests are passed through split virtqueues which are made of 3 parts in memory shared with the device:
+        Descriptor table - address, length and flags of every buffer (buffers for a request are chained together)
+        Available ring - the driver adds the first descriptor of each request here and notifies the device
+        Used ring - the device adds the first descriptor of each finished request here
+*/
+
+use crate::page_frame_allocator::{FrameAllocator, PAGE_FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::pci::{BarType, PciDevice};
+use crate::ports::{inb, inl, inpw, outb, outl, outpw};
+use core::mem::size_of;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+// Offsets of the legacy registers from the IO base
+const DEVICE_FEATURES: u16 = 0x00;
+const GUEST_FEATURES: u16 = 0x04;
+const QUEUE_ADDRESS: u16 = 0x08;
+const QUEUE_SIZE: u16 = 0x0C;
+const QUEUE_SELECT: u16 = 0x0E;
+const QUEUE_NOTIFY: u16 = 0x10;
+const DEVICE_STATUS: u16 = 0x12;
+const DEVICE_CONFIG: u16 = 0x14; // Device specific configuration starts here (without MSI-X)
+
+const STATUS_ACKNOWLEDGE: u8 = 0x01;
+const STATUS_DRIVER: u8 = 0x02;
+const STATUS_DRIVER_OK: u8 = 0x04;
+const STATUS_FAILED: u8 = 0x80;
+
+const DESCRIPTOR_NEXT: u16 = 0x01; // Buffer continues into the descriptor given by next
+const DESCRIPTOR_WRITE: u16 = 0x02; // Buffer is written by the device rather than read
+
+// Legacy devices require the used ring to start on a new page
+const QUEUE_ALIGNMENT: usize = PAGE_SIZE;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Descriptor {
+    address: u64, // Physical address
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElement {
+    id: u32, // First descriptor of the finished request
+    length: u32,
+}
+
+// Buffer which is part of a request (buffers must be in identity mapped memory)
+pub struct Buffer {
+    pub address: u64,
+    pub length: u32,
+    pub device_writable: bool,
+}
+
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    descriptors: *mut Descriptor,
+    available_flags: *mut u16, // Followed by the available index and ring
+    used_flags: *mut u16,      // Followed by the used index and ring
+    free_head: u16,            // Unused descriptors are chained together through next
+    free_count: u16,
+    last_used_index: u16,
+}
+
+impl Virtqueue {
+    fn new(index: u16, size: u16) -> Virtqueue {
+        let size_usize = size as usize;
+        let available_offset = size_of::<Descriptor>() * size_usize;
+        let used_offset = align(
+            available_offset + size_of::<u16>() * (3 + size_usize),
+            QUEUE_ALIGNMENT,
+        );
+        let total_size = used_offset
+            + align(
+                size_of::<u16>() * 3 + size_of::<UsedElement>() * size_usize,
+                QUEUE_ALIGNMENT,
+            );
+
+        // Frames are identity mapped so their addresses can be given to the device
+        let memory = PAGE_FRAME_ALLOCATOR
+            .lock()
+            .alloc_frames((total_size / PAGE_SIZE) as u64) as *mut u8;
+        PAGE_FRAME_ALLOCATOR.free();
+
+        unsafe {
+            core::ptr::write_bytes(memory, 0, total_size);
+        }
+
+        let descriptors = memory as *mut Descriptor;
+        for i in 0..size {
+            unsafe {
+                (*descriptors.add(i as usize)).next = i + 1;
+            }
+        }
+
+        Virtqueue {
+            index,
+            size,
+            descriptors,
+            available_flags: unsafe { memory.add(available_offset) } as *mut u16,
+            used_flags: unsafe { memory.add(used_offset) } as *mut u16,
+            free_head: 0,
+            free_count: size,
+            last_used_index: 0,
+        }
+    }
+
+    fn physical_address(&self) -> u64 {
+        self.descriptors as u64
+    }
+
+    /*
+        Chains the buffers into descriptors and adds the request to the available ring
+        Returns the first descriptor (which the device uses to mark the request as finished)
+    */
+    pub fn submit(&mut self, buffers: &[Buffer]) -> Result<u16, &'static str> {
+        if buffers.is_empty() || buffers.len() > self.free_count as usize {
+            return Err("Not enough free descriptors within the virtqueue");
+        }
+
+        let head = self.free_head;
+        let mut index = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let descriptor = unsafe { &mut *self.descriptors.add(index as usize) };
+            let next = descriptor.next;
+
+            descriptor.address = buffer.address;
+            descriptor.length = buffer.length;
+            descriptor.flags = match buffer.device_writable {
+                true => DESCRIPTOR_WRITE,
+                false => 0,
+            };
+
+            if i + 1 < buffers.len() {
+                descriptor.flags |= DESCRIPTOR_NEXT;
+                index = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+        self.free_count -= buffers.len() as u16;
+
+        unsafe {
+            let available_index = self.available_flags.add(1);
+            let ring = self.available_flags.add(2);
+            let index = read_volatile(available_index);
+            write_volatile(ring.add((index % self.size) as usize), head);
+
+            // Descriptors must be visible to the device before the index is moved on
+            fence(Ordering::SeqCst);
+            write_volatile(available_index, index.wrapping_add(1));
+            fence(Ordering::SeqCst);
+        }
+
+        Ok(head)
+    }
+
+    /*
+        Polls the used ring until the device has finished the next request
+        The descriptors of the finished request are added back to the free chain
+    */
+    pub fn wait_for_used(&mut self) -> u16 {
+        let used_index = unsafe { self.used_flags.add(1) };
+        while unsafe { read_volatile(used_index) } == self.last_used_index {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+
+        let element = unsafe {
+            let ring = self.used_flags.add(2) as *const UsedElement;
+            read_volatile(&(*ring.add((self.last_used_index % self.size) as usize)).id)
+        };
+        self.last_used_index = self.last_used_index.wrapping_add(1);
+
+        let head = element as u16;
+        let mut index = head;
+        loop {
+            self.free_count += 1;
+            let descriptor = unsafe { &mut *self.descriptors.add(index as usize) };
+            if descriptor.flags & DESCRIPTOR_NEXT == 0 {
+                descriptor.next = self.free_head;
+                break;
+            }
+            index = descriptor.next;
+        }
+        self.free_head = head;
+
+        head
+    }
+}
+
+// Legacy virtio device which is accessed through IO ports
+pub struct VirtioDevice {
+    io_base: u16,
+}
+
+impl VirtioDevice {
+    /*
+        Resets the device and goes through the start of initialisation
+        Returns None if BAR0 isn't an IO BAR (modern only devices)
+    */
+    pub fn new(pci_device: &PciDevice) -> Option<VirtioDevice> {
+        let bar = pci_device.bars[0].filter(|bar| bar.bar_type == BarType::Io)?;
+
+        pci_device.enable_bus_mastering();
+
+        let device = VirtioDevice {
+            io_base: bar.address as u16,
+        };
+
+        outb(device.io_base + DEVICE_STATUS, 0);
+        outb(device.io_base + DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+        outb(
+            device.io_base + DEVICE_STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER,
+        );
+        Some(device)
+    }
+
+    pub fn device_features(&self) -> u32 {
+        inl(self.io_base + DEVICE_FEATURES)
+    }
+
+    pub fn set_guest_features(&self, features: u32) {
+        outl(self.io_base + GUEST_FEATURES, features);
+    }
+
+    // Allocates a virtqueue with the size the device asks for and gives its address to the device
+    pub fn setup_queue(&self, index: u16) -> Option<Virtqueue> {
+        outpw(self.io_base + QUEUE_SELECT, index);
+        let size = inpw(self.io_base + QUEUE_SIZE);
+        if size == 0 {
+            return None;
+        }
+
+        let queue = Virtqueue::new(index, size);
+        outl(
+            self.io_base + QUEUE_ADDRESS,
+            (queue.physical_address() / PAGE_SIZE as u64) as u32,
+        );
+        Some(queue)
+    }
+
+    pub fn notify(&self, queue: &Virtqueue) {
+        outpw(self.io_base + QUEUE_NOTIFY, queue.index);
+    }
+
+    // Marks initialisation as finished so the device starts handling requests
+    pub fn driver_ok(&self) {
+        let status = inb(self.io_base + DEVICE_STATUS);
+        outb(self.io_base + DEVICE_STATUS, status | STATUS_DRIVER_OK);
+    }
+
+    pub fn fail(&self) {
+        let status = inb(self.io_base + DEVICE_STATUS);
+        outb(self.io_base + DEVICE_STATUS, status | STATUS_FAILED);
+    }
+
+    pub fn read_config_32(&self, offset: u16) -> u32 {
+        inl(self.io_base + DEVICE_CONFIG + offset)
+    }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
@@ -0,0 +1,223 @@
+// src/virtio_blk.rs
+
+/*
+    Virtio block devices take requests made of 3 buffers chained together:
+        Header - request type and the first sector (read by the device)
+        Data - sectors which are read/written
+        Status - single byte written by the device once the request is finished
+    QEMU provides one with -drive file=fs.img,if=virtio (which is transitional so has the legacy interface)
+    Requests are sent one at a time and the driver polls until they are finished
+*/
+
+use crate::block::{self, BlockDevice, SECTOR_SIZE};
+use crate::page_frame_allocator::{FrameAllocator, PAGE_FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::pci::{PciDevice, PciDriver, PciMatch};
+use crate::virtio::{Buffer, VirtioDevice, Virtqueue, VIRTIO_VENDOR_ID};
+use crate::{allocator::kmalloc, print_serial, CONSOLE};
+use core::mem::size_of;
+
+// Transitional block device
+const VIRTIO_BLK_DEVICE_ID: u16 = 0x1001;
+
+const VIRTIO_BLK_F_RO: u32 = 1 << 5; // Device is read only
+
+const VIRTIO_BLK_T_IN: u32 = 0; // Read
+const VIRTIO_BLK_T_OUT: u32 = 1; // Write
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+// Capacity (in 512 byte sectors) is the first field of the device configuration
+const CONFIG_CAPACITY: u16 = 0x00;
+
+// Data is copied through a buffer which the device can access (caller buffers may not be identity mapped)
+const DMA_BUFFER_PAGES: usize = 8;
+const SECTORS_PER_REQUEST: usize = DMA_BUFFER_PAGES * PAGE_SIZE / SECTOR_SIZE;
+
+#[repr(C)]
+struct RequestHeader {
+    request_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+pub struct VirtioBlk {
+    device: VirtioDevice,
+    queue: Virtqueue,
+    sector_count: u64,
+    read_only: bool,
+    header: *mut RequestHeader, // Header and status share a page
+    status: *mut u8,
+    data: *mut u8,
+}
+
+impl VirtioBlk {
+    fn new(device: VirtioDevice) -> Option<VirtioBlk> {
+        let read_only = device.device_features() & VIRTIO_BLK_F_RO != 0;
+
+        // No optional features are used
+        device.set_guest_features(0);
+
+        let queue = match device.setup_queue(0) {
+            Some(queue) => queue,
+            None => {
+                device.fail();
+                return None;
+            }
+        };
+
+        let sector_count = (device.read_config_32(CONFIG_CAPACITY) as u64)
+            | ((device.read_config_32(CONFIG_CAPACITY + 4) as u64) << 32);
+
+        let (header_page, data) = {
+            let allocator = PAGE_FRAME_ALLOCATOR.lock();
+            (
+                allocator.alloc_frame() as *mut u8,
+                allocator.alloc_frames(DMA_BUFFER_PAGES as u64) as *mut u8,
+            )
+        };
+        PAGE_FRAME_ALLOCATOR.free();
+
+        device.driver_ok();
+
+        Some(VirtioBlk {
+            device,
+            queue,
+            sector_count,
+            read_only,
+            header: header_page as *mut RequestHeader,
+            status: unsafe { header_page.add(size_of::<RequestHeader>()) },
+            data,
+        })
+    }
+
+    // Sends a single request (of at most SECTORS_PER_REQUEST sectors) and waits for it to finish
+    fn request(&mut self, request_type: u32, lba: u64, count: usize) -> Result<(), &'static str> {
+        unsafe {
+            *self.header = RequestHeader {
+                request_type,
+                reserved: 0,
+                sector: lba,
+            };
+            *self.status = 0xFF;
+        }
+
+        let buffers = [
+            Buffer {
+                address: self.header as u64,
+                length: size_of::<RequestHeader>() as u32,
+                device_writable: false,
+            },
+            Buffer {
+                address: self.data as u64,
+                length: (count * SECTOR_SIZE) as u32,
+                device_writable: request_type == VIRTIO_BLK_T_IN,
+            },
+            Buffer {
+                address: self.status as u64,
+                length: 1,
+                device_writable: true,
+            },
+        ];
+
+        self.queue.submit(&buffers)?;
+        self.device.notify(&self.queue);
+        self.queue.wait_for_used();
+
+        match unsafe { core::ptr::read_volatile(self.status) } {
+            VIRTIO_BLK_S_OK => Ok(()),
+            status => {
+                print_serial!("VIRTIO BLK ERROR {}\n", status);
+                Err("Virtio block request failed")
+            }
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn name(&self) -> &str {
+        "virtio-blk"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *mut u8,
+    ) -> Result<(), &'static str> {
+        if lba + count as u64 > self.sector_count {
+            return Err("Sector is past the end of the drive");
+        }
+
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(SECTORS_PER_REQUEST);
+            self.request(VIRTIO_BLK_T_IN, lba + done as u64, chunk)?;
+            unsafe {
+                core::ptr::copy(
+                    self.data,
+                    buffer.add(done * SECTOR_SIZE),
+                    chunk * SECTOR_SIZE,
+                );
+            }
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    fn write_sectors(
+        &mut self,
+        lba: u64,
+        count: usize,
+        buffer: *const u8,
+    ) -> Result<(), &'static str> {
+        if self.read_only {
+            return Err("Drive is read only");
+        }
+        if lba + count as u64 > self.sector_count {
+            return Err("Sector is past the end of the drive");
+        }
+
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(SECTORS_PER_REQUEST);
+            unsafe {
+                core::ptr::copy(
+                    buffer.add(done * SECTOR_SIZE),
+                    self.data,
+                    chunk * SECTOR_SIZE,
+                );
+            }
+            self.request(VIRTIO_BLK_T_OUT, lba + done as u64, chunk)?;
+            done += chunk;
+        }
+        Ok(())
+    }
+}
+
+pub static VIRTIO_BLK_DRIVER: PciDriver = PciDriver {
+    name: "virtio-blk",
+    matches: &[PciMatch::Device(VIRTIO_VENDOR_ID, VIRTIO_BLK_DEVICE_ID)],
+    probe,
+};
+
+// Sets up a virtio block device found on the PCI bus and registers it as a block device
+fn probe(pci_device: &PciDevice) -> bool {
+    let drive = match VirtioDevice::new(pci_device).and_then(VirtioBlk::new) {
+        Some(drive) => drive,
+        None => {
+            print_serial!("VIRTIO BLK FAILED TO INITIALISE\n");
+            return false;
+        }
+    };
+
+    let device = kmalloc(size_of::<VirtioBlk>() as u64) as *mut VirtioBlk;
+    unsafe {
+        device.write(drive);
+    }
+    block::register(device);
+    true
+}
@@ -210,6 +210,12 @@ void *	_reallocf_r (struct _reent *, void *, size_t);
 int	setenv (const char *__string, const char *__value, int __overwrite);
 #endif
 int	_setenv_r (struct _reent *, const char *__string, const char *__value, int __overwrite);
+#if __XSI_VISIBLE >= 600
+int	posix_openpt (int __oflag);
+int	grantpt (int __fd);
+int	unlockpt (int __fd);
+char *	ptsname (int __fd);
+#endif
 
 #if __XSI_VISIBLE >= 4 && __POSIX_VISIBLE < 200112
 char *	gcvt (double,int,char *);
@@ -0,0 +1,33 @@
+#ifndef _SYS_DIRENT_H
+#define _SYS_DIRENT_H
+
+#include <stdint.h>
+
+/* Records given by the getdents syscall (the name starts straight after d_type) */
+struct dirent
+{
+    uint64_t d_ino;
+    int64_t d_off;
+    uint64_t d_size;
+    uint16_t d_reclen;
+    uint8_t d_type;
+    char d_name[];
+} __attribute__((packed));
+
+#define DT_UNKNOWN 0
+#define DT_CHR 2
+#define DT_DIR 4
+#define DT_REG 8
+#define DT_LNK 10
+
+#define DIRENT_BUFFER_SIZE 1024
+
+typedef struct
+{
+    int dd_fd;                           /* Directory opened through open */
+    int dd_loc;                          /* Position of the next record within the buffer */
+    int dd_size;                         /* Number of bytes held within the buffer */
+    char dd_buf[DIRENT_BUFFER_SIZE];
+} DIR;
+
+#endif
@@ -7,6 +7,9 @@
 #include <sys/time.h>
 #include <stdio.h>
 #include <sys/stat.h>
+#include <dirent.h>
+#include <termios.h>
+#include <stdlib.h>
 
 #include "../../../../../../syscalls/syscalls.h"
 
@@ -29,62 +32,209 @@ int fork()
     errno = EAGAIN;
     return -1;
 }
-int fstat(int file, struct stat *st)
+clock_t times(struct tms *buf)
 {
-    if (st == NULL)
-        return -1;
-    st->st_mode = S_IFCHR;
+    // asm volatile("mov $107, %rax \n\t\
+    //     int $0x80 \n\t\
+    //     ");
     return 0;
 }
-int kill(int pid, int sig)
+int wait(int *status)
 {
-    asm volatile("mov $103, %rax \n\t\
+    asm volatile("mov $108, %rax \n\t\
         int $0x80 \n\t\
         ");
-    errno = EINVAL;
+    errno = ECHILD;
     return -1;
 }
-int link(char *old, char *new)
+
+// Directories are read through records given by getdents which are buffered within the DIR
+DIR *opendir(const char *name)
 {
-    asm volatile("mov $104, %rax \n\t\
-        int $0x80 \n\t\
-        ");
-    errno = EMLINK;
-    return -1;
+    int fd = open(name, O_RDONLY);
+    if (fd < 0)
+    {
+        errno = ENOENT;
+        return NULL;
+    }
+
+    DIR *dirp = malloc(sizeof(DIR));
+    if (dirp == NULL)
+    {
+        close(fd);
+        errno = ENOMEM;
+        return NULL;
+    }
+
+    dirp->dd_fd = fd;
+    dirp->dd_loc = 0;
+    dirp->dd_size = 0;
+    return dirp;
 }
 
-int stat(const char *file, struct stat *st)
+struct dirent *readdir(DIR *dirp)
 {
-    asm volatile("mov $106, %rax \n\t\
-        int $0x80 \n\t\
-        ");
-    st->st_mode = S_IFCHR;
+    // Refill the buffer once every record has been read
+    if (dirp->dd_loc >= dirp->dd_size)
+    {
+        dirp->dd_size = getdents(dirp->dd_fd, dirp->dd_buf, DIRENT_BUFFER_SIZE);
+        dirp->dd_loc = 0;
+
+        if (dirp->dd_size <= 0)
+            return NULL;
+    }
+
+    struct dirent *entry = (struct dirent *)(dirp->dd_buf + dirp->dd_loc);
+    dirp->dd_loc += entry->d_reclen;
+    return entry;
+}
+
+int closedir(DIR *dirp)
+{
+    int result = close(dirp->dd_fd);
+    free(dirp);
+    return result;
+}
+
+// Terminal settings are got and set through ioctls on the tty
+int tcgetattr(int fd, struct termios *termios_p)
+{
+    if (ioctl(fd, TCGETS, termios_p) < 0)
+    {
+        return -1;
+    }
     return 0;
 }
-clock_t times(struct tms *buf)
+
+int tcsetattr(int fd, int optional_actions, const struct termios *termios_p)
 {
-    // asm volatile("mov $107, %rax \n\t\
-    //     int $0x80 \n\t\
-    //     ");
+    int request;
+    switch (optional_actions)
+    {
+    case TCSANOW:
+        request = TCSETS;
+        break;
+    case TCSADRAIN:
+        request = TCSETSW;
+        break;
+    case TCSAFLUSH:
+        request = TCSETSF;
+        break;
+    default:
+        errno = EINVAL;
+        return -1;
+    }
+
+    if (ioctl(fd, request, (void *)termios_p) < 0)
+    {
+        return -1;
+    }
     return 0;
 }
-int unlink(char *name)
+
+pid_t tcgetpgrp(int fd)
 {
-    asm volatile("mov $108, %rax \n\t\
-        int $0x80 \n\t\
-        ");
-    errno = ENOENT;
-    return -1;
+    pid_t pgrp;
+    if (ioctl(fd, TIOCGPGRP, &pgrp) < 0)
+    {
+        return -1;
+    }
+    return pgrp;
 }
-int wait(int *status)
+
+int tcsetpgrp(int fd, pid_t pgrp)
 {
-    asm volatile("mov $108, %rax \n\t\
-        int $0x80 \n\t\
-        ");
-    errno = ECHILD;
+    if (ioctl(fd, TIOCSPGRP, &pgrp) < 0)
+    {
+        return -1;
+    }
+    return 0;
+}
+
+// Byte at a time input without echo, signals or any processing
+void cfmakeraw(struct termios *termios_p)
+{
+    termios_p->c_iflag &= ~(IGNBRK | BRKINT | PARMRK | ISTRIP | INLCR | IGNCR | ICRNL | IXON);
+    termios_p->c_oflag &= ~OPOST;
+    termios_p->c_lflag &= ~(ECHO | ECHONL | ICANON | ISIG | IEXTEN);
+    termios_p->c_cflag &= ~(CSIZE | PARENB);
+    termios_p->c_cflag |= CS8;
+    termios_p->c_cc[VMIN] = 1;
+    termios_p->c_cc[VTIME] = 0;
+}
+
+speed_t cfgetispeed(const struct termios *termios_p)
+{
+    return termios_p->c_ispeed;
+}
+
+speed_t cfgetospeed(const struct termios *termios_p)
+{
+    return termios_p->c_ospeed;
+}
+
+int cfsetispeed(struct termios *termios_p, speed_t speed)
+{
+    termios_p->c_ispeed = speed;
+    return 0;
+}
+
+int cfsetospeed(struct termios *termios_p, speed_t speed)
+{
+    termios_p->c_ospeed = speed;
+    return 0;
+}
+
+/*
+    Pseudo-terminals are made at boot, so a new one is the first master (/dev/ptmN) which isn't already in use
+    Slaves can be opened as soon as their master is, so grantpt and unlockpt only check that fd is a master
+*/
+int posix_openpt(int flags)
+{
+    char path[] = "/dev/ptm0";
+    for (char number = '0'; number < '0' + MAX_PTYS; number++)
+    {
+        path[8] = number;
+        int fd = open(path, flags & O_ACCMODE);
+        if (fd >= 0)
+        {
+            return fd;
+        }
+    }
+
+    errno = EAGAIN;
     return -1;
 }
 
+int grantpt(int fd)
+{
+    unsigned int number;
+    if (ioctl(fd, TIOCGPTN, &number) < 0)
+    {
+        errno = EINVAL;
+        return -1;
+    }
+    return 0;
+}
+
+int unlockpt(int fd)
+{
+    return grantpt(fd);
+}
+
+char *ptsname(int fd)
+{
+    static char name[16];
+    unsigned int number;
+    if (ioctl(fd, TIOCGPTN, &number) < 0)
+    {
+        return NULL;
+    }
+
+    snprintf(name, sizeof(name), "/dev/pts%u", number);
+    return name;
+}
+
 int gettimeofday(struct timeval *__p, void *__tz)
 {
     __p->tv_sec = 0;
@@ -117,8 +267,9 @@ void *liballoc_alloc(int pages)
                  mov $8, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result)
-                 : "r"(pages));
+                 : "=a"(result)
+                 : "r"(pages)
+                 : "rbx", "rcx", "rdx", "memory");
     return (void *)result;
 }
 /*
@@ -129,13 +280,14 @@ void *liballoc_alloc(int pages)
 int liballoc_free(void *memory, int pages)
 {
     int64_t result;
-    asm volatile("mov %0, %%rcx \n\t\
-        mov %1, %%rbx \n\t\
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%ecx \n\t\
         mov $19, %%rax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "m"(memory), "r"(pages));
+                 : "=a"(result)
+                 : "m"(memory), "r"(pages)
+                 : "rbx", "rcx", "rdx", "memory");
     return (int)result;
 }
 
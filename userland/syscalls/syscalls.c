@@ -1,6 +1,23 @@
 #include "syscalls.h"
+#include <stdarg.h>
 #include <stdint.h>
 
+#define O_CREAT 0x0200
+
+// errno belongs to newlib, which keeps it behind __errno (so <errno.h> isn't needed here)
+int *__errno(void);
+
+// Every syscall fails with a negated error number, which its wrapper puts in errno before returning -1
+static int64_t set_errno(int64_t result)
+{
+    if (result < 0)
+    {
+        *__errno() = -result;
+        return -1;
+    }
+    return result;
+}
+
 void _exit()
 {
     asm volatile("mov $0, %rax \n\t\
@@ -11,13 +28,14 @@ void _exit()
 int close(int file)
 {
     int64_t result;
-    asm volatile("mov %0, %%rbx \n\t\
+    asm volatile("mov %1, %%ebx \n\t\
                  mov $1, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result)
-                 : "r"(file));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int getpid()
@@ -26,33 +44,62 @@ int getpid()
     asm volatile("mov $3, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result));
-    return (int)result;
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int isatty(int file)
 {
     int64_t result;
-    asm volatile("mov %0, %%rbx \n\t\
+    asm volatile("mov %1, %%ebx \n\t\
                  mov $4, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result)
-                 : "r"(file));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int kill(int pid, int sig)
+{
+    int64_t result;
+    asm volatile("mov %1, %%ebx \n\t\
+        mov %2, %%ecx \n\t\
+        mov $5, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(pid), "m"(sig)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int open(const char *name, int flags, ...)
 {
+    // Mode is only passed when creating a file
+    int64_t mode = 0;
+    if (flags & O_CREAT)
+    {
+        va_list arguments;
+        va_start(arguments, flags);
+        mode = va_arg(arguments, int);
+        va_end(arguments);
+    }
+
     int64_t result;
-    asm volatile("mov %0, %%rcx \n\t\
-        mov %1, %%rbx \n\t\
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%ecx \n\t\
+        mov %3, %%rdx \n\t\
         mov $7, %%rax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "m"(name), "r"(flags));
-    return (int)result;
+                 : "=a"(result)
+                 : "m"(name), "m"(flags), "m"(mode)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int write(int file, char *ptr, int len)
@@ -64,9 +111,10 @@ int write(int file, char *ptr, int len)
         mov $9, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "r"(len), "m"(ptr), "r"(file));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(len), "m"(ptr), "r"(file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int read(int file, char *ptr, int len)
@@ -78,9 +126,10 @@ int read(int file, char *ptr, int len)
         mov $10, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "r"(len), "m"(ptr), "r"(file));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(len), "m"(ptr), "r"(file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int create_window(Window *new_window)
@@ -90,9 +139,10 @@ int create_window(Window *new_window)
         mov $11, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "m"(new_window));
-    return (int)result;
+                 : "=a"(result)
+                 : "m"(new_window)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int paint_all()
@@ -101,8 +151,10 @@ int paint_all()
     asm volatile("mov $12, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result));
-    return (int)result;
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 Event *get_event()
@@ -111,7 +163,11 @@ Event *get_event()
     asm volatile("mov $13, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result));
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    if (set_errno(result) < 0)
+        return NULL;
     return (Event *)result;
 }
 
@@ -121,22 +177,25 @@ int get_current_scancode()
     asm volatile("mov $16, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result));
-    return (int)result;
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int lseek(int file, int ptr, int dir)
 {
-    uint64_t result;
+    int64_t result;
     asm volatile("mov %3, %%ebx \n\t\
         mov %2, %%ecx \n\t\
         mov %1, %%edx \n\t\
         mov $15, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "r"(file), "r"(ptr), "r"(dir));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(file), "r"(ptr), "r"(dir)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int paint_string(char *ptr, int wid, int x, int y)
@@ -149,21 +208,23 @@ int paint_string(char *ptr, int wid, int x, int y)
         mov $14, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "m"(ptr), "r"(wid), "r"(x), "r"(y));
-    return (int)result;
+                 : "=a"(result)
+                 : "m"(ptr), "r"(wid), "r"(x), "r"(y)
+                 : "rbx", "rcx", "rdx", "rsi", "rdi", "memory");
+    return (int)set_errno(result);
 }
 
 int initalise_window_buffer(int wid)
 {
     int64_t result;
-    asm volatile("mov %0, %%rbx \n\t\
+    asm volatile("mov %1, %%ebx \n\t\
                  mov $17, %%rax \n\t\
                  int $0x80 \n\t\
                  "
-                 : "=r"(result)
-                 : "r"(wid));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(wid)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int copy_to_buffer(int wid, uint32_t *buffer, int y_offset)
@@ -175,9 +236,10 @@ int copy_to_buffer(int wid, uint32_t *buffer, int y_offset)
         mov $18, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "r"(wid), "m"(buffer), "r"(y_offset));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(wid), "m"(buffer), "r"(y_offset)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
 
 int send_message(int cpid, int pid, char *ptr)
@@ -189,7 +251,432 @@ int send_message(int cpid, int pid, char *ptr)
         mov $20, %%eax \n\t\
         int $0x80 \n\t\
         "
-                 : "=r"(result)
-                 : "r"(cpid), "r"(pid), "m"(ptr));
-    return (int)result;
+                 : "=a"(result)
+                 : "r"(cpid), "r"(pid), "m"(ptr)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int futex(int *address, int op, int value)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%ecx \n\t\
+        mov %3, %%edx \n\t\
+        mov $21, %%eax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(address), "r"(op), "r"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int thread_create(void (*entry)(void *), void *stack_top, void *arg)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov %3, %%rdx \n\t\
+        mov $22, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(entry), "m"(stack_top), "m"(arg)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+void thread_exit()
+{
+    asm volatile("mov $23, %rax \n\t\
+        int $0x80 \n\t\
+        ");
+}
+
+int gettid()
+{
+    int64_t result;
+    asm volatile("mov $24, %%rax \n\t\
+                 int $0x80 \n\t\
+                 "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int unlink(const char *name)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $25, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(name)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int rmdir(const char *name)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $26, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(name)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int rename(const char *old, const char *new)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $27, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(old), "m"(new)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int fstat(int file, struct stat *st)
+{
+    int64_t result;
+    asm volatile("mov %1, %%ebx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $2, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "r"(file), "m"(st)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int stat(const char *file, struct stat *st)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $28, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(file), "m"(st)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int lstat(const char *file, struct stat *st)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $29, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(file), "m"(st)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int getdents(int file, void *buffer, int length)
+{
+    int64_t result;
+    asm volatile("mov %1, %%ebx \n\t\
+        mov %2, %%rcx \n\t\
+        mov %3, %%edx \n\t\
+        mov $30, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "r"(file), "m"(buffer), "r"(length)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int chdir(const char *path)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $31, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(path)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+char *getcwd(char *buffer, size_t size)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $32, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(buffer), "m"(size)
+                 : "rbx", "rcx", "rdx", "memory");
+    if (set_errno(result) < 0)
+        return NULL;
+    return buffer;
+}
+
+void sync(void)
+{
+    int64_t result;
+    asm volatile("mov $33, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    set_errno(result);
+}
+
+int fsync(int file)
+{
+    int64_t result;
+    asm volatile("mov %1, %%ebx \n\t\
+        mov $34, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "r"(file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int ioctl(int file, unsigned long request, void *argument)
+{
+    int64_t result;
+    asm volatile("mov %1, %%ebx \n\t\
+        mov %2, %%rcx \n\t\
+        mov %3, %%rdx \n\t\
+        mov $35, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "r"(file), "m"(request), "m"(argument)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int link(const char *old, const char *new)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $6, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(old), "m"(new)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int symlink(const char *target, const char *linkpath)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $36, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(target), "m"(linkpath)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+ssize_t readlink(const char *path, char *buffer, size_t size)
+{
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov %3, %%rdx \n\t\
+        mov $37, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(path), "m"(buffer), "m"(size)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (ssize_t)set_errno(result);
+}
+
+uid_t getuid(void)
+{
+    int64_t result;
+    asm volatile("mov $38, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (uid_t)set_errno(result);
+}
+
+uid_t geteuid(void)
+{
+    int64_t result;
+    asm volatile("mov $39, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (uid_t)set_errno(result);
+}
+
+gid_t getgid(void)
+{
+    int64_t result;
+    asm volatile("mov $40, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (gid_t)set_errno(result);
+}
+
+gid_t getegid(void)
+{
+    int64_t result;
+    asm volatile("mov $41, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 :
+                 : "rbx", "rcx", "rdx", "memory");
+    return (gid_t)set_errno(result);
+}
+
+int setuid(uid_t uid)
+{
+    int64_t value = uid;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $42, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int seteuid(uid_t euid)
+{
+    int64_t value = euid;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $43, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int setgid(gid_t gid)
+{
+    int64_t value = gid;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $44, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int setegid(gid_t egid)
+{
+    int64_t value = egid;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $45, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+mode_t umask(mode_t mask)
+{
+    int64_t value = mask;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov $46, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (mode_t)set_errno(result);
+}
+
+int chmod(const char *path, mode_t mode)
+{
+    int64_t value = mode;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $47, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(path), "m"(value)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int chown(const char *path, uid_t owner, gid_t group)
+{
+    int64_t uid = owner;
+    int64_t gid = group;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov %3, %%rdx \n\t\
+        mov $48, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(path), "m"(uid), "m"(gid)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
+}
+
+int dup2(int old, int new)
+{
+    int64_t old_file = old;
+    int64_t new_file = new;
+    int64_t result;
+    asm volatile("mov %1, %%rbx \n\t\
+        mov %2, %%rcx \n\t\
+        mov $49, %%rax \n\t\
+        int $0x80 \n\t\
+        "
+                 : "=a"(result)
+                 : "m"(old_file), "m"(new_file)
+                 : "rbx", "rcx", "rdx", "memory");
+    return (int)set_errno(result);
 }
@@ -1,4 +1,70 @@
 #include <stdint.h>
+#include <stddef.h>
+#include <sys/types.h>
+
+struct stat;
+
+#define FUTEX_WAIT 0
+#define FUTEX_WAKE 1
+
+#define FBIOGET_INFO 0x4600
+
+// Supported by every tty (through tcgetattr/tcsetattr and tcgetpgrp/tcsetpgrp)
+#define TCGETS 0x5401
+#define TCSETS 0x5402
+#define TCSETSW 0x5403
+#define TCSETSF 0x5404
+#define TIOCGPGRP 0x540f
+#define TIOCSPGRP 0x5410
+#define TIOCGWINSZ 0x5413
+#define TIOCSWINSZ 0x5414
+
+// Gives the number of a pty (the N of /dev/ptsN) through its master (used by ptsname)
+#define TIOCGPTN 0x80045430
+
+// Pseudo-terminals made at boot (/dev/ptm0 onwards), matching MAX_PTYS within the kernel
+#define MAX_PTYS 8
+
+// link, symlink, readlink, chmod, chown and futex set errno to one of these on failure (and return -1)
+#define EPERM 1
+#define ENOENT 2
+#define EIO 5
+#define EAGAIN 11 // FUTEX_WAIT when the word no longer holds the expected value
+#define ENOMEM 12
+#define EACCES 13
+#define EFAULT 14
+#define EEXIST 17
+#define EXDEV 18
+#define ENOTDIR 20
+#define EINVAL 22
+#define EROFS 30
+#define ELOOP 92
+
+// Size of a terminal, got and set with TIOCGWINSZ and TIOCSWINSZ (0 until the terminal sets it)
+struct winsize
+{
+    unsigned short ws_row;
+    unsigned short ws_col;
+    unsigned short ws_xpixel;
+    unsigned short ws_ypixel;
+};
+
+// Read from /dev/mouse
+typedef struct MouseEvent
+{
+    uint32_t x;
+    uint32_t y;
+    uint32_t buttons;
+} MouseEvent;
+
+// Filled by ioctl(fd, FBIOGET_INFO, &info) on /dev/fb0
+typedef struct FramebufferInfo
+{
+    uint32_t width;
+    uint32_t height;
+    uint32_t bits_per_pixel;
+    uint32_t pitch;
+} FramebufferInfo;
 
 typedef struct Event
 {
@@ -20,19 +86,29 @@ typedef struct Window
 
 void _exit();
 int close(int file);
+int dup2(int old, int new); // Standard streams use the console until they are replaced
 // char **environ; /* pointer to array of char * strings that define the current environment variables */
 // int execve(char *name, char **argv, char **env);
 // int fork();
-// int fstat(int file, struct stat *st);
+int fstat(int file, struct stat *st);
 int getpid();
 int isatty(int file);
-// int kill(int pid, int sig);
-// int link(char *old, char *new);
-int open(const char *name, int flags, ...);
+int kill(int pid, int sig);
+int link(const char *old, const char *new);
+int symlink(const char *target, const char *linkpath);
+ssize_t readlink(const char *path, char *buffer, size_t size);
+int open(const char *name, int flags, ...); // Takes a mode when flags has O_CREAT
 int read(int file, char *ptr, int len);
-// int stat(const char *file, struct stat *st);
+int stat(const char *file, struct stat *st);
+int lstat(const char *file, struct stat *st);
+int getdents(int file, void *buffer, int length);
+int chdir(const char *path);
+char *getcwd(char *buffer, size_t size);
+void sync(void);
+int fsync(int file);
+int ioctl(int file, unsigned long request, void *argument);
 // clock_t times(struct tms *buf);
-// int unlink(char *name);
+int unlink(const char *name);
 int create_window(Window *new_window);
 int paint_all();
 Event *get_event();
@@ -44,4 +120,21 @@ int send_message(int cpid, int pid, char *ptr);
 // int wait(int *status);
 int lseek(int file, int ptr, int dir);
 int write(int file, char *ptr, int len);
+int futex(int *address, int op, int value);
+int thread_create(void (*entry)(void *), void *stack_top, void *arg);
+void thread_exit();
+int gettid();
+int rmdir(const char *name);
+int rename(const char *old, const char *new);
+uid_t getuid(void);
+uid_t geteuid(void);
+gid_t getgid(void);
+gid_t getegid(void);
+int setuid(uid_t uid);
+int seteuid(uid_t euid);
+int setgid(gid_t gid);
+int setegid(gid_t egid);
+mode_t umask(mode_t mask);
+int chmod(const char *path, mode_t mode);
+int chown(const char *path, uid_t owner, gid_t group);
 // int gettimeofday(struct timeval *p, void *restrict);
\ No newline at end of file
@@ -2,6 +2,7 @@
 #include <stdint.h>
 #include <string.h>
 #include <stdlib.h>
+#include <dirent.h>
 
 #include "../syscalls/syscalls.h"
 
@@ -75,6 +76,30 @@ int evaluate_command(char command[255])
     {
         paint_string("...", 0, x_base, y_base);
     }
+    else if (strncmp(command, "ls", 2) == 0 && (command[2] == '\0' || command[2] == ' '))
+    {
+        // List the directory given (or the root directory)
+        char *path = command[2] == ' ' ? command + 3 : "/";
+        DIR *directory = opendir(path);
+        if (directory == NULL)
+        {
+            paint_string("No such directory", 0, x_base, y_base);
+        }
+        else
+        {
+            struct dirent *entry = readdir(directory);
+            while (entry != NULL)
+            {
+                paint_string(entry->d_name, 0, x_base, y_base);
+                entry = readdir(directory);
+
+                // Last line is moved past along with the command
+                if (entry != NULL)
+                    y_base += 20;
+            }
+            closedir(directory);
+        }
+    }
     else
     {
         paint_string("Unknown command", 0, x_base, y_base);